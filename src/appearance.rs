@@ -0,0 +1,172 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::player::{
+    box_avatar_altitudes, AvatarBox, HeadState, COLOR_PLAYER_BODY, COLOR_PLAYER_HEAD, HEAD_SIZE,
+    TORSO_HEIGHT, TORSO_WIDTH,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+const APPEARANCE_PATH: &str = "player_appearance.ron";
+// Wait for the sliders to settle before writing the file
+const SAVE_DELAY: f32 = 0.5;
+// Environment variable that overrides the avatar model of the appearance file
+const AVATAR_MODEL_ENV: &str = "AVATAR_MODEL";
+
+// Colors are stored as linear rgba, just like the color pickers in the Player window
+#[derive(Resource, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct PlayerAppearance {
+    pub body_color: [f32; 4],
+    pub head_color: [f32; 4],
+    pub emissive_color: [f32; 4],
+    pub perceptual_roughness: f32,
+    pub metallic: f32,
+    pub torso_width: f32,
+    pub torso_height: f32,
+    pub head_size: f32,
+    // Path of a rigged glTF character relative to the assets folder, the box avatar is used when
+    // there is none
+    pub model: String,
+}
+
+impl Default for PlayerAppearance {
+    fn default() -> Self {
+        PlayerAppearance {
+            body_color: Color::hex(COLOR_PLAYER_BODY).unwrap().as_linear_rgba_f32(),
+            head_color: Color::hex(COLOR_PLAYER_HEAD).unwrap().as_linear_rgba_f32(),
+            emissive_color: Color::BLACK.as_linear_rgba_f32(),
+            perceptual_roughness: StandardMaterial::default().perceptual_roughness,
+            metallic: StandardMaterial::default().metallic,
+            torso_width: TORSO_WIDTH,
+            torso_height: TORSO_HEIGHT,
+            head_size: HEAD_SIZE,
+            model: String::new(),
+        }
+    }
+}
+
+impl PlayerAppearance {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn load() -> Self {
+        let Ok(contents) = std::fs::read_to_string(APPEARANCE_PATH) else {
+            return PlayerAppearance::default();
+        };
+        ron::from_str(&contents).unwrap_or_else(|error| {
+            warn!("Could not read {APPEARANCE_PATH}: {error}");
+            PlayerAppearance::default()
+        })
+    }
+
+    // There is no file system to persist to on the web
+    #[cfg(target_arch = "wasm32")]
+    fn load() -> Self {
+        PlayerAppearance::default()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save(&self) {
+        let result = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|error| error.to_string())
+            .and_then(|contents| {
+                std::fs::write(APPEARANCE_PATH, contents).map_err(|error| error.to_string())
+            });
+        if let Err(error) = result {
+            warn!("Could not write {APPEARANCE_PATH}: {error}");
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn save(&self) {}
+
+    fn material(&self, base_color: [f32; 4]) -> StandardMaterial {
+        StandardMaterial {
+            base_color: linear_color(base_color),
+            emissive: linear_color(self.emissive_color),
+            perceptual_roughness: self.perceptual_roughness,
+            metallic: self.metallic,
+            ..default()
+        }
+    }
+}
+
+pub struct AppearancePlugin;
+
+impl Plugin for AppearancePlugin {
+    fn build(&self, app: &mut App) {
+        let mut appearance = PlayerAppearance::load();
+        if let Ok(model) = std::env::var(AVATAR_MODEL_ENV) {
+            appearance.model = model;
+        }
+        app.insert_resource(appearance)
+            .add_system(apply_appearance)
+            .add_system(save_appearance);
+    }
+}
+
+// Rebuilds the box avatar when the appearance changes, or once it has been spawned, and moves the
+// torso and the head so that they still meet
+fn apply_appearance(
+    appearance: Res<PlayerAppearance>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    query_new_boxes: Query<(), Added<AvatarBox>>,
+    mut query_boxes: Query<(Entity, &mut Transform), With<AvatarBox>>,
+    query_handles: Query<(&Handle<Mesh>, &Handle<StandardMaterial>)>,
+    query_heads: Query<(), With<HeadState>>,
+) {
+    if !appearance.is_changed() && query_new_boxes.is_empty() {
+        return;
+    }
+
+    let (torso_alt, head_alt) = box_avatar_altitudes(appearance.torso_height, appearance.head_size);
+    for (entity, mut transform) in query_boxes.iter_mut() {
+        let Ok((mesh, material)) = query_handles.get(entity) else {
+            continue;
+        };
+        let (new_mesh, new_material) = if query_heads.contains(entity) {
+            transform.translation.y = head_alt;
+            (
+                Mesh::from(shape::Cube::new(appearance.head_size)),
+                appearance.material(appearance.head_color),
+            )
+        } else {
+            transform.translation.y = torso_alt;
+            (
+                Mesh::from(shape::Box::new(
+                    appearance.torso_width,
+                    appearance.torso_height,
+                    appearance.head_size,
+                )),
+                appearance.material(appearance.body_color),
+            )
+        };
+        meshes.set_untracked(mesh, new_mesh);
+        materials.set_untracked(material, new_material);
+    }
+}
+
+fn save_appearance(
+    time: Res<Time>,
+    appearance: Res<PlayerAppearance>,
+    mut save_timer: Local<Option<f32>>,
+) {
+    // The resource counts as changed when it is first inserted, which doesn't need saving
+    if appearance.is_changed() && !appearance.is_added() {
+        *save_timer = Some(SAVE_DELAY);
+    }
+
+    let Some(remaining) = save_timer.as_mut() else {
+        return;
+    };
+    *remaining -= time.delta_seconds();
+    if *remaining <= 0.0 {
+        appearance.save();
+        *save_timer = None;
+    }
+}
+
+#[inline]
+fn linear_color(color: [f32; 4]) -> Color {
+    Color::rgba_linear(color[0], color[1], color[2], color[3])
+}
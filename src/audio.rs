@@ -0,0 +1,364 @@
+use bevy::{
+    audio::{play_queued_audio_system, AudioOutput, AudioSink, Decodable, Source},
+    gltf::GltfExtras,
+    prelude::*,
+    reflect::TypeUuid,
+    utils::HashMap,
+};
+use serde::Deserialize;
+use std::{f32::consts::TAU, sync::Arc, time::Duration};
+
+use crate::{
+    camera::CameraRig,
+    player::{Player, PlayerMotion},
+    AppState,
+};
+
+const SAMPLE_RATE: u32 = 22050;
+// Sounds are at full volume up to this distance from the listener, then fall off
+const REFERENCE_DISTANCE: f32 = 1.5;
+const HEARING_DISTANCE: f32 = 12.0;
+// Distance walked between two footsteps
+const FOOTSTEP_STRIDE: f32 = 0.8;
+
+// A procedurally generated mono sound, there are no audio files in the assets
+#[derive(TypeUuid)]
+#[uuid = "0c1f1e4e-7a62-4d2b-9b1e-2f8d9c4b7a31"]
+pub struct SynthSound {
+    samples: Arc<[f32]>,
+}
+
+pub struct SynthDecoder {
+    samples: Arc<[f32]>,
+    position: usize,
+}
+
+impl Iterator for SynthDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.samples.get(self.position).copied();
+        self.position += 1;
+        sample
+    }
+}
+
+impl Source for SynthDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.samples.len().saturating_sub(self.position))
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f32(
+            self.samples.len() as f32 / SAMPLE_RATE as f32,
+        ))
+    }
+}
+
+impl Decodable for SynthSound {
+    type DecoderItem = f32;
+    type Decoder = SynthDecoder;
+
+    fn decoder(&self) -> Self::Decoder {
+        SynthDecoder {
+            samples: self.samples.clone(),
+            position: 0,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum AmbientSoundKind {
+    Drip,
+    Torch,
+}
+
+impl AmbientSoundKind {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "drip" => Some(AmbientSoundKind::Drip),
+            "torch" => Some(AmbientSoundKind::Torch),
+            _ => None,
+        }
+    }
+}
+
+// Looping sound attenuated by the distance to the active camera
+#[derive(Component)]
+pub struct AmbientSound {
+    pub kind: AmbientSoundKind,
+    pub volume: f32,
+}
+
+#[derive(Component)]
+struct AmbientSoundSink(Handle<AudioSink>);
+
+#[derive(Resource)]
+pub struct AudioSettings {
+    pub master_volume: f32,
+    pub ambient_volume: f32,
+    pub footstep_volume: f32,
+    pub hearing_distance: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            master_volume: 0.8,
+            ambient_volume: 0.6,
+            footstep_volume: 0.5,
+            hearing_distance: HEARING_DISTANCE,
+        }
+    }
+}
+
+#[derive(Resource)]
+struct SoundHandles {
+    drip: Handle<SynthSound>,
+    torch: Handle<SynthSound>,
+    footstep: Handle<SynthSound>,
+}
+
+#[derive(Default)]
+struct Footsteps {
+    distance_walked: f32,
+    left_foot: bool,
+}
+
+// Custom properties exported from Blender, e.g. {"ambient_sound": "drip", "sound_volume": 0.5}
+#[derive(Deserialize)]
+struct AmbientSoundExtras {
+    ambient_sound: Option<String>,
+    sound_volume: Option<f32>,
+}
+
+pub struct GameAudioPlugin;
+
+impl Plugin for GameAudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_non_send_resource::<AudioOutput<SynthSound>>()
+            .add_asset::<SynthSound>()
+            .init_resource::<Audio<SynthSound>>()
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                play_queued_audio_system::<SynthSound>,
+            )
+            .insert_resource(AudioSettings::default())
+            .add_startup_system(setup_sounds)
+            .add_system(attach_ambient_sounds)
+            .add_system(play_ambient_sounds.after(attach_ambient_sounds))
+            .add_system(update_ambient_volumes.after(play_ambient_sounds))
+            .add_system_set(SystemSet::on_update(AppState::InGame).with_system(footstep_system));
+    }
+}
+
+fn setup_sounds(mut commands: Commands, mut sounds: ResMut<Assets<SynthSound>>) {
+    let mut add = |samples: Vec<f32>| {
+        sounds.add(SynthSound {
+            samples: samples.into(),
+        })
+    };
+    commands.insert_resource(SoundHandles {
+        drip: add(synthesize_drip()),
+        torch: add(synthesize_torch()),
+        footstep: add(synthesize_footstep()),
+    });
+}
+
+fn attach_ambient_sounds(
+    mut commands: Commands,
+    query_nodes: Query<(Entity, &Name, Option<&GltfExtras>), Added<Name>>,
+    query_meshes: Query<&Handle<Mesh>>,
+) {
+    for (entity, name, extras) in query_nodes.iter() {
+        if query_meshes.contains(entity) {
+            continue;
+        }
+
+        let extras: Option<AmbientSoundExtras> =
+            extras.and_then(|extras| serde_json::from_str(&extras.value).ok());
+        let (kind, volume) = match extras {
+            Some(AmbientSoundExtras {
+                ambient_sound: Some(kind),
+                sound_volume,
+            }) => (AmbientSoundKind::from_name(&kind), sound_volume),
+            _ => {
+                // "torch.003" -> "torch"
+                let name = name.to_ascii_lowercase();
+                let kind = name.split('.').next().and_then(AmbientSoundKind::from_name);
+                (kind, None)
+            }
+        };
+        let Some(kind) = kind else {
+            continue;
+        };
+
+        commands.entity(entity).insert(AmbientSound {
+            kind,
+            volume: volume.unwrap_or(1.0),
+        });
+    }
+}
+
+fn play_ambient_sounds(
+    mut commands: Commands,
+    audio: Res<Audio<SynthSound>>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    sound_handles: Res<SoundHandles>,
+    query_sounds: Query<(Entity, &AmbientSound), Added<AmbientSound>>,
+) {
+    for (entity, sound) in query_sounds.iter() {
+        let source = match sound.kind {
+            AmbientSoundKind::Drip => sound_handles.drip.clone(),
+            AmbientSoundKind::Torch => sound_handles.torch.clone(),
+        };
+        // Silent until update_ambient_volumes knows how far away the listener is
+        let sink = audio.play_with_settings(source, PlaybackSettings::LOOP.with_volume(0.0));
+        commands
+            .entity(entity)
+            .insert(AmbientSoundSink(audio_sinks.get_handle(sink)));
+    }
+}
+
+fn update_ambient_volumes(
+    audio_settings: Res<AudioSettings>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    query_cams: Query<(&Camera, &GlobalTransform), With<CameraRig>>,
+    query_sounds: Query<(&AmbientSound, &AmbientSoundSink, &GlobalTransform)>,
+) {
+    let Some(listener) = query_cams
+        .iter()
+        .find(|(cam, _)| cam.is_active)
+        .map(|(_, transform)| transform.translation())
+    else {
+        return;
+    };
+
+    for (sound, sink, transform) in query_sounds.iter() {
+        let Some(sink) = audio_sinks.get(&sink.0) else {
+            continue;
+        };
+        let distance = transform.translation().distance(listener);
+        // Inverse distance, faded out to silence at the hearing distance
+        let fade = (1.0 - distance / audio_settings.hearing_distance).max(0.0);
+        let attenuation = REFERENCE_DISTANCE / distance.max(REFERENCE_DISTANCE) * fade;
+        sink.set_volume(
+            audio_settings.master_volume
+                * audio_settings.ambient_volume
+                * sound.volume
+                * attenuation,
+        );
+    }
+}
+
+fn footstep_system(
+    time: Res<Time>,
+    audio: Res<Audio<SynthSound>>,
+    audio_settings: Res<AudioSettings>,
+    sound_handles: Res<SoundHandles>,
+    query_motion: Query<(Entity, &PlayerMotion), With<Player>>,
+    mut footsteps: Local<HashMap<Entity, Footsteps>>,
+) {
+    footsteps.retain(|player, _| query_motion.contains(*player));
+    for (player, motion) in query_motion.iter() {
+        let footsteps = footsteps.entry(player).or_default();
+        if motion.speed <= 0.0 {
+            // The next step is heard as soon as the player starts walking again
+            footsteps.distance_walked = FOOTSTEP_STRIDE;
+            continue;
+        }
+
+        footsteps.distance_walked += motion.speed * time.delta_seconds();
+        if footsteps.distance_walked < FOOTSTEP_STRIDE {
+            continue;
+        }
+        footsteps.distance_walked = 0.0;
+        footsteps.left_foot = !footsteps.left_foot;
+
+        // Alternate the pitch a little so that both feet don't sound the same
+        let speed = if footsteps.left_foot { 0.95 } else { 1.05 };
+        audio.play_with_settings(
+            sound_handles.footstep.clone(),
+            PlaybackSettings::ONCE
+                .with_volume(audio_settings.master_volume * audio_settings.footstep_volume)
+                .with_speed(speed),
+        );
+    }
+}
+
+// Cheap deterministic noise for the synthesizers
+struct Noise(u32);
+
+impl Noise {
+    fn next(&mut self) -> f32 {
+        // xorshift32
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+fn seconds(duration: f32) -> usize {
+    (duration * SAMPLE_RATE as f32) as usize
+}
+
+// A falling blip every 2.5 seconds, with a quieter echo
+fn synthesize_drip() -> Vec<f32> {
+    let mut samples = vec![0.0; seconds(2.5)];
+    for (start, gain) in [(0.0, 0.6), (0.18, 0.15)] {
+        let mut phase = 0.0;
+        let offset = seconds(start);
+        for i in 0..seconds(0.08) {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            let frequency = 1400.0 - 7000.0 * t;
+            phase += frequency / SAMPLE_RATE as f32;
+            samples[offset + i] += (phase * TAU).sin() * (-t * 60.0).exp() * gain;
+        }
+    }
+    samples
+}
+
+// Low rumble with random crackles on top
+fn synthesize_torch() -> Vec<f32> {
+    let mut noise = Noise(0x2545f491);
+    let mut samples = vec![0.0; seconds(3.0)];
+    let mut rumble = 0.0;
+    for sample in samples.iter_mut() {
+        rumble += (noise.next() - rumble) * 0.02;
+        *sample = rumble * 0.8;
+    }
+
+    let len = samples.len();
+    for _ in 0..24 {
+        let start = ((noise.next() * 0.5 + 0.5) * (len - seconds(0.02)) as f32) as usize;
+        let gain = 0.2 + (noise.next() * 0.5 + 0.5) * 0.3;
+        for i in 0..seconds(0.02) {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            samples[start + i] += noise.next() * (-t * 300.0).exp() * gain;
+        }
+    }
+    samples
+}
+
+// A short muffled thud
+fn synthesize_footstep() -> Vec<f32> {
+    let mut noise = Noise(0x9e3779b9);
+    let mut filtered = 0.0;
+    (0..seconds(0.15))
+        .map(|i| {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            filtered += (noise.next() - filtered) * 0.15;
+            let thump = (t * 90.0 * TAU).sin();
+            (filtered * 0.7 + thump * 0.5) * (-t * 35.0).exp()
+        })
+        .collect()
+}
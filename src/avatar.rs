@@ -0,0 +1,377 @@
+use bevy::{
+    animation::{EntityPath, Keyframes, VariableCurve},
+    asset::LoadState,
+    gltf::Gltf,
+    prelude::*,
+    transform::TransformSystem,
+    utils::HashMap,
+};
+use std::f32::consts::PI;
+
+use crate::{
+    appearance::PlayerAppearance,
+    camera::CameraTransition,
+    player::{AvatarBox, HeadState, Player, PlayerMotion, PLAYER_HEIGHT_2, PLAYER_SPEED},
+    ui::CameraSettings,
+};
+
+// How fast the animation weights follow the movement of the player
+const BLEND_RATE: f32 = 8.0;
+// The walk animation is fully blended in at this speed, the run animation at full speed
+const WALK_ANIMATION_SPEED: f32 = PLAYER_SPEED / 2.0;
+// Below this vertical speed or altitude the player is considered to be on the ground
+const FLY_THRESHOLD: f32 = 0.1;
+const WEIGHT_EPSILON: f32 = 0.001;
+
+#[derive(Clone, Copy)]
+enum AvatarClip {
+    Idle,
+    Walk,
+    Run,
+    Fly,
+}
+
+impl AvatarClip {
+    const ALL: [AvatarClip; 4] = [
+        AvatarClip::Idle,
+        AvatarClip::Walk,
+        AvatarClip::Run,
+        AvatarClip::Fly,
+    ];
+}
+
+#[derive(Resource)]
+pub struct AvatarSettings {
+    // Names of the idle, walk, run and fly animations in the model
+    pub clip_names: [String; 4],
+}
+
+impl Default for AvatarSettings {
+    fn default() -> Self {
+        AvatarSettings {
+            clip_names: ["Idle", "Walk", "Run", "Fly"].map(String::from),
+        }
+    }
+}
+
+#[derive(Component)]
+struct AvatarModel {
+    gltf: Handle<Gltf>,
+    player: Entity,
+}
+
+// Blends the avatar clips by hand, bevy's AnimationPlayer only plays one clip at a time
+#[derive(Component)]
+struct AvatarAnimator {
+    player: Entity,
+    clips: [Option<Handle<AnimationClip>>; 4],
+    weights: [f32; 4],
+    elapsed: [f32; 4],
+    targets: HashMap<EntityPath, Option<Entity>>,
+}
+
+#[derive(Default)]
+struct BlendedTransform {
+    translation: Vec3,
+    translation_weight: f32,
+    rotation: Vec4,
+    rotation_weight: f32,
+    scale: Vec3,
+    scale_weight: f32,
+}
+
+pub struct AvatarPlugin;
+
+impl Plugin for AvatarPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(AvatarSettings::default())
+            .add_system(spawn_avatar_model)
+            .add_system(attach_avatar_animator)
+            .add_system(update_avatar_visibility)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                animate_avatar.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+// New players get the model of the appearance, and all of them get the new one when it changes
+fn spawn_avatar_model(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    appearance: Res<PlayerAppearance>,
+    query_players: Query<Entity, With<Player>>,
+    query_new_players: Query<Entity, Added<Player>>,
+    query_models: Query<Entity, With<AvatarModel>>,
+    mut current_model: Local<String>,
+) {
+    let players: Vec<Entity> = if *current_model != appearance.model {
+        *current_model = appearance.model.clone();
+        for model in query_models.iter() {
+            commands.entity(model).despawn_recursive();
+        }
+        query_players.iter().collect()
+    } else {
+        query_new_players.iter().collect()
+    };
+    let model = &appearance.model;
+    if model.is_empty() {
+        return;
+    }
+
+    for player in players {
+        // Characters are usually modelled facing +Z with their origin at the feet
+        let transform = Transform::from_xyz(0.0, -PLAYER_HEIGHT_2, 0.0)
+            .with_rotation(Quat::from_rotation_y(PI));
+        let model = commands
+            .spawn((
+                SceneBundle {
+                    scene: asset_server.load(format!("{model}#Scene0")),
+                    transform,
+                    visibility: Visibility { is_visible: false },
+                    ..default()
+                },
+                AvatarModel {
+                    gltf: asset_server.load(model.as_str()),
+                    player,
+                },
+            ))
+            .id();
+        commands.entity(player).add_child(model);
+    }
+}
+
+fn attach_avatar_animator(
+    mut commands: Commands,
+    avatar_settings: Res<AvatarSettings>,
+    gltfs: Res<Assets<Gltf>>,
+    query_roots: Query<Entity, Added<AnimationPlayer>>,
+    query_parents: Query<&Parent>,
+    query_models: Query<&AvatarModel>,
+) {
+    for root in query_roots.iter() {
+        let mut ancestor = root;
+        let model = loop {
+            if let Ok(model) = query_models.get(ancestor) {
+                break Some(model);
+            }
+            match query_parents.get(ancestor) {
+                Ok(parent) => ancestor = parent.get(),
+                Err(_) => break None,
+            }
+        };
+        let Some(model) = model else {
+            continue;
+        };
+        let Some(gltf) = gltfs.get(&model.gltf) else {
+            continue;
+        };
+
+        let clips = AvatarClip::ALL.map(|clip| {
+            let name = &avatar_settings.clip_names[clip as usize];
+            let handle = gltf.named_animations.get(name).cloned();
+            if handle.is_none() {
+                warn!("Avatar model has no {name} animation");
+            }
+            handle
+        });
+        commands.entity(root).insert(AvatarAnimator {
+            player: model.player,
+            clips,
+            weights: [1.0, 0.0, 0.0, 0.0],
+            elapsed: [0.0; 4],
+            targets: HashMap::default(),
+        });
+    }
+}
+
+// The model replaces the boxes once it has loaded. In first person the model and the head box
+// are hidden so that they don't get in the way of the camera, visibility isn't per view so with
+// split screen the other players don't see them either
+fn update_avatar_visibility(
+    asset_server: Res<AssetServer>,
+    cam_settings: Res<CameraSettings>,
+    transition: Res<CameraTransition>,
+    mut query_models: Query<(&AvatarModel, &mut Visibility), Without<AvatarBox>>,
+    mut query_boxes: Query<(Entity, &mut Visibility), With<AvatarBox>>,
+    query_heads: Query<(), With<HeadState>>,
+    mut load_failed: Local<bool>,
+) {
+    let first_person = transition.is_first_person_view(&cam_settings);
+
+    let mut show_boxes = true;
+    for (model, mut visibility) in query_models.iter_mut() {
+        let loaded = match asset_server.get_load_state(&model.gltf) {
+            LoadState::Loaded => true,
+            LoadState::Failed => {
+                if !*load_failed {
+                    warn!("Could not load the avatar model, using the box avatar instead");
+                    *load_failed = true;
+                }
+                false
+            }
+            _ => false,
+        };
+        show_boxes &= !loaded;
+
+        let show_model = loaded && !first_person;
+        if visibility.is_visible != show_model {
+            visibility.is_visible = show_model;
+        }
+    }
+
+    for (entity, mut visibility) in query_boxes.iter_mut() {
+        let show_box = show_boxes && !(first_person && query_heads.contains(entity));
+        if visibility.is_visible != show_box {
+            visibility.is_visible = show_box;
+        }
+    }
+}
+
+fn animate_avatar(
+    time: Res<Time>,
+    animation_clips: Res<Assets<AnimationClip>>,
+    query_players: Query<(&PlayerMotion, &Transform), With<Player>>,
+    mut query_animators: Query<(Entity, &mut AvatarAnimator)>,
+    query_children: Query<&Children>,
+    query_names: Query<&Name>,
+    mut query_transforms: Query<&mut Transform, Without<Player>>,
+) {
+    let blend = 1.0 - (-BLEND_RATE * time.delta_seconds()).exp();
+
+    for (root, mut animator) in query_animators.iter_mut() {
+        let animator = &mut *animator;
+        let Ok((motion, player_transform)) = query_players.get(animator.player) else {
+            continue;
+        };
+        let flying = motion.vertical_speed.abs() > FLY_THRESHOLD
+            || player_transform.translation.y > PLAYER_HEIGHT_2 + FLY_THRESHOLD;
+        let target_weights = locomotion_weights(motion.speed, flying);
+        let mut blended: HashMap<Entity, BlendedTransform> = HashMap::default();
+
+        for clip in AvatarClip::ALL {
+            let i = clip as usize;
+            animator.weights[i] += (target_weights[i] - animator.weights[i]) * blend;
+            let Some(animation_clip) = animator.clips[i]
+                .as_ref()
+                .and_then(|handle| animation_clips.get(handle))
+            else {
+                continue;
+            };
+            if animation_clip.duration() <= 0.0 {
+                continue;
+            }
+
+            animator.elapsed[i] =
+                (animator.elapsed[i] + time.delta_seconds()) % animation_clip.duration();
+            let weight = animator.weights[i];
+            if weight < WEIGHT_EPSILON {
+                continue;
+            }
+
+            for (path, curves) in animation_clip.curves() {
+                let target = *animator
+                    .targets
+                    .entry(path.clone())
+                    .or_insert_with(|| find_target(root, path, &query_children, &query_names));
+                let Some(target) = target else {
+                    continue;
+                };
+                let blended = blended.entry(target).or_default();
+                for curve in curves {
+                    sample_curve(curve, animator.elapsed[i], weight, blended);
+                }
+            }
+        }
+
+        for (entity, blended) in blended {
+            let Ok(mut transform) = query_transforms.get_mut(entity) else {
+                continue;
+            };
+            if blended.translation_weight > 0.0 {
+                transform.translation = blended.translation / blended.translation_weight;
+            }
+            if blended.rotation_weight > 0.0 {
+                transform.rotation = Quat::from_vec4(blended.rotation).normalize();
+            }
+            if blended.scale_weight > 0.0 {
+                transform.scale = blended.scale / blended.scale_weight;
+            }
+        }
+    }
+}
+
+// Idle, walk and run are blended by speed, flying overrides all of them
+fn locomotion_weights(speed: f32, flying: bool) -> [f32; 4] {
+    if flying {
+        return [0.0, 0.0, 0.0, 1.0];
+    }
+    if speed <= WALK_ANIMATION_SPEED {
+        let t = (speed / WALK_ANIMATION_SPEED).clamp(0.0, 1.0);
+        [1.0 - t, t, 0.0, 0.0]
+    } else {
+        let t = ((speed - WALK_ANIMATION_SPEED) / (PLAYER_SPEED - WALK_ANIMATION_SPEED))
+            .clamp(0.0, 1.0);
+        [0.0, 1.0 - t, t, 0.0]
+    }
+}
+
+// Same lookup as bevy's animation_player, the first part of the path is the root itself
+fn find_target(
+    root: Entity,
+    path: &EntityPath,
+    query_children: &Query<&Children>,
+    query_names: &Query<&Name>,
+) -> Option<Entity> {
+    let mut current = root;
+    for part in path.parts.iter().skip(1) {
+        current = query_children
+            .get(current)
+            .ok()?
+            .iter()
+            .copied()
+            .find(|child| query_names.get(*child).is_ok_and(|name| name == part))?;
+    }
+    Some(current)
+}
+
+fn sample_curve(curve: &VariableCurve, time: f32, weight: f32, blended: &mut BlendedTransform) {
+    let timestamps = &curve.keyframe_timestamps;
+    if timestamps.is_empty() {
+        return;
+    }
+    // Index of the keyframe before `time` and how far we are towards the next one
+    let next = timestamps.partition_point(|timestamp| *timestamp <= time);
+    let (start, end, t) = if next == 0 {
+        (0, 0, 0.0)
+    } else if next >= timestamps.len() {
+        (timestamps.len() - 1, timestamps.len() - 1, 0.0)
+    } else {
+        let (ts_start, ts_end) = (timestamps[next - 1], timestamps[next]);
+        (next - 1, next, (time - ts_start) / (ts_end - ts_start))
+    };
+
+    match &curve.keyframes {
+        Keyframes::Translation(keyframes) => {
+            blended.translation += keyframes[start].lerp(keyframes[end], t) * weight;
+            blended.translation_weight += weight;
+        }
+        Keyframes::Scale(keyframes) => {
+            blended.scale += keyframes[start].lerp(keyframes[end], t) * weight;
+            blended.scale_weight += weight;
+        }
+        Keyframes::Rotation(keyframes) => {
+            let (rot_start, mut rot_end) = (keyframes[start], keyframes[end]);
+            if rot_end.dot(rot_start) < 0.0 {
+                rot_end = -rot_end;
+            }
+            let mut rotation = Vec4::from(rot_start.normalize().slerp(rot_end.normalize(), t));
+            // Keep all the rotations in the same hemisphere before adding them up
+            if blended.rotation_weight > 0.0 && rotation.dot(blended.rotation) < 0.0 {
+                rotation = -rotation;
+            }
+            blended.rotation += rotation * weight;
+            blended.rotation_weight += weight;
+        }
+    }
+}
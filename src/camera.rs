@@ -0,0 +1,366 @@
+use bevy::{core_pipeline::clear_color::ClearColorConfig, prelude::*, render::camera::Viewport};
+
+use crate::{
+    player::LocalPlayer,
+    ui::{CameraSettings, CameraType},
+};
+
+const TRANSITION_DURATION: f32 = 0.4;
+
+#[derive(Component)]
+pub struct FirstPersonCamera;
+
+#[derive(Component)]
+pub struct ThirdPersonCamera;
+
+// A camera the player can look through, only the ones matching `CameraSettings::c_type` are active.
+// Cameras without a rig (minimap, map...) are left alone
+#[derive(Component)]
+pub struct CameraRig {
+    pub c_type: CameraType,
+    // The local player whose part of the screen the camera renders to, or None for the whole
+    // screen
+    pub player: Option<Entity>,
+}
+
+// The rigs of the players that have a camera, in the order they are cycled through. The editor
+// and the flythrough switch to their own cameras themselves
+#[derive(Resource, Default)]
+pub struct CameraRigs {
+    rigs: Vec<CameraType>,
+}
+
+impl CameraRigs {
+    pub fn iter(&self) -> impl Iterator<Item = CameraType> + '_ {
+        self.rigs.iter().copied()
+    }
+
+    pub fn next(&self, c_type: CameraType) -> CameraType {
+        match self.rigs.iter().position(|rig| *rig == c_type) {
+            Some(i) => self.rigs[(i + 1) % self.rigs.len()],
+            None => self.rigs.first().copied().unwrap_or(c_type),
+        }
+    }
+}
+
+// Moves the active cameras to where the cameras of the new rig are, then swaps which ones are
+// active
+#[derive(Resource, Default)]
+pub struct CameraTransition {
+    active: Vec<Transition>,
+}
+
+struct Transition {
+    camera: Entity,
+    // Where the moving camera goes back to once the other camera has taken over
+    original: Transform,
+    from: Transform,
+    to: Entity,
+    progress: f32,
+}
+
+impl CameraTransition {
+    pub fn in_progress(&self) -> bool {
+        !self.active.is_empty()
+    }
+
+    // Whether the view is from inside the players' heads
+    pub fn is_first_person_view(&self, cam_settings: &CameraSettings) -> bool {
+        cam_settings.c_type == CameraType::FirstPerson && !self.in_progress()
+    }
+}
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(CameraRigs::default())
+            .insert_resource(CameraTransition::default())
+            .add_system(register_camera_rigs)
+            .add_system(start_camera_transition)
+            .add_system(animate_camera_transition.after(start_camera_transition))
+            .add_system(activate_camera_rigs.after(animate_camera_transition))
+            .add_system(update_viewports);
+    }
+}
+
+// Rigs whose cameras have all been despawned are dropped, new ones go at the end
+fn register_camera_rigs(mut rigs: ResMut<CameraRigs>, query_rigs: Query<&CameraRig>) {
+    let live: Vec<CameraType> = query_rigs
+        .iter()
+        .filter(|rig| rig.player.is_some())
+        .map(|rig| rig.c_type)
+        .collect();
+    let mut registered: Vec<CameraType> = rigs
+        .rigs
+        .iter()
+        .copied()
+        .filter(|c_type| live.contains(c_type))
+        .collect();
+    for c_type in live {
+        if !registered.contains(&c_type) {
+            registered.push(c_type);
+        }
+    }
+    if registered != rigs.rigs {
+        rigs.rigs = registered;
+    }
+}
+
+fn start_camera_transition(
+    cam_settings: Res<CameraSettings>,
+    mut transition: ResMut<CameraTransition>,
+    query_rigs: Query<(Entity, &Camera, &CameraRig, &Transform)>,
+    mut previous_type: Local<Option<CameraType>>,
+) {
+    if !cam_settings.is_changed() || *previous_type == Some(cam_settings.c_type) {
+        return;
+    }
+    let first_run = previous_type.is_none();
+    *previous_type = Some(cam_settings.c_type);
+    if first_run {
+        return;
+    }
+
+    // Prefer the new rig of the same player, the rigs without a player are shared by everyone
+    let find_target = |camera: Entity| {
+        let player = query_rigs.get(camera).ok()?.2.player;
+        let rigs = || {
+            query_rigs
+                .iter()
+                .filter(|(_, _, rig, _)| rig.c_type == cam_settings.c_type)
+        };
+        rigs()
+            .find(|(_, _, rig, _)| rig.player == player)
+            .or_else(|| rigs().next())
+            .map(|(entity, ..)| entity)
+    };
+
+    // Switching again halfway through heads for the new rig from wherever the camera is now
+    for active in transition.active.iter_mut() {
+        if let Some(to) = find_target(active.camera) {
+            if let Ok((.., transform)) = query_rigs.get(active.camera) {
+                active.from = *transform;
+            }
+            active.to = to;
+            active.progress = 0.0;
+        }
+    }
+
+    for (camera, cam, _, transform) in query_rigs.iter() {
+        if !cam.is_active
+            || transition
+                .active
+                .iter()
+                .any(|active| active.camera == camera)
+        {
+            continue;
+        }
+        let Some(to) = find_target(camera) else {
+            continue;
+        };
+        if to == camera {
+            continue;
+        }
+        transition.active.push(Transition {
+            camera,
+            original: *transform,
+            from: *transform,
+            to,
+            progress: 0.0,
+        });
+    }
+}
+
+fn animate_camera_transition(
+    time: Res<Time>,
+    mut transition: ResMut<CameraTransition>,
+    mut query_transforms: Query<&mut Transform, With<CameraRig>>,
+    query_globals: Query<&GlobalTransform>,
+    query_parents: Query<&Parent>,
+) {
+    transition.active.retain_mut(|active| {
+        active.progress += time.delta_seconds() / TRANSITION_DURATION;
+
+        // The target in the space of the moving camera's parent, so that it follows the player
+        let target = if active.to == active.camera {
+            Some(active.original)
+        } else {
+            let parent = query_parents
+                .get(active.camera)
+                .ok()
+                .and_then(|parent| query_globals.get(parent.get()).ok())
+                .copied()
+                .unwrap_or_default();
+            query_globals.get(active.to).ok().map(|target| {
+                Transform::from_matrix(parent.compute_matrix().inverse() * target.compute_matrix())
+            })
+        };
+
+        let Ok(mut transform) = query_transforms.get_mut(active.camera) else {
+            return false;
+        };
+        if let (Some(target), true) = (target, active.progress < 1.0) {
+            let t = active.progress;
+            let t = t * t * (3.0 - 2.0 * t);
+            transform.translation = active.from.translation.lerp(target.translation, t);
+            transform.rotation = active.from.rotation.slerp(target.rotation, t);
+            return true;
+        }
+
+        *transform = active.original;
+        false
+    });
+}
+
+fn activate_camera_rigs(
+    cam_settings: Res<CameraSettings>,
+    transition: Res<CameraTransition>,
+    mut query_rigs: Query<(&mut Camera, &CameraRig)>,
+) {
+    if transition.in_progress() {
+        return;
+    }
+    // Keep the current cameras rather than showing nothing
+    if !query_rigs
+        .iter()
+        .any(|(_, rig)| rig.c_type == cam_settings.c_type)
+    {
+        return;
+    }
+
+    for (mut cam, rig) in query_rigs.iter_mut() {
+        let is_active = rig.c_type == cam_settings.c_type;
+        if cam.is_active != is_active {
+            cam.is_active = is_active;
+        }
+    }
+}
+
+// Splits the window between the local players, side by side for two and in quarters for more
+fn update_viewports(
+    windows: Res<Windows>,
+    query_players: Query<(Entity, &LocalPlayer)>,
+    mut query_rigs: Query<(&CameraRig, &mut Camera, &mut Camera3d)>,
+) {
+    let Some(window) = windows.get_primary() else {
+        return;
+    };
+    let window_size = UVec2::new(window.physical_width(), window.physical_height());
+    if window_size.x == 0 || window_size.y == 0 {
+        return;
+    }
+
+    let mut players: Vec<(Entity, usize)> = query_players
+        .iter()
+        .map(|(entity, player)| (entity, player.index))
+        .collect();
+    players.sort_by_key(|(_, index)| *index);
+    let columns = if players.len() > 1 { 2 } else { 1 };
+    let rows = if players.len() > 2 { 2 } else { 1 };
+    let cell_size = window_size / UVec2::new(columns, rows);
+
+    for (rig, mut cam, mut camera_3d) in query_rigs.iter_mut() {
+        let slot = rig
+            .player
+            .and_then(|player| players.iter().position(|(entity, _)| *entity == player))
+            .unwrap_or(0);
+        let viewport = (players.len() > 1 && rig.player.is_some()).then(|| {
+            let cell = UVec2::new(slot as u32 % columns, slot as u32 / columns);
+            (cell * cell_size, cell_size)
+        });
+
+        let current = cam
+            .viewport
+            .as_ref()
+            .map(|viewport| (viewport.physical_position, viewport.physical_size));
+        if current != viewport {
+            cam.viewport = viewport.map(|(physical_position, physical_size)| Viewport {
+                physical_position,
+                physical_size,
+                ..default()
+            });
+        }
+
+        // Only the first view clears the window, otherwise it would clear the views before it
+        let priority = slot as isize;
+        if cam.priority != priority {
+            cam.priority = priority;
+            camera_3d.clear_color = if slot == 0 {
+                ClearColorConfig::Default
+            } else {
+                ClearColorConfig::None
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn_rig(app: &mut App, c_type: CameraType, player: Option<Entity>) -> Entity {
+        app.world
+            .spawn((
+                Camera {
+                    is_active: false,
+                    ..default()
+                },
+                CameraRig { c_type, player },
+            ))
+            .id()
+    }
+
+    fn is_active(app: &App, camera: Entity) -> bool {
+        app.world.get::<Camera>(camera).unwrap().is_active
+    }
+
+    #[test]
+    fn activation_follows_the_selected_rig() {
+        let mut app = App::new();
+        app.insert_resource(CameraSettings::default())
+            .insert_resource(CameraRigs::default())
+            .insert_resource(CameraTransition::default())
+            .add_system(register_camera_rigs)
+            .add_system(activate_camera_rigs.after(register_camera_rigs));
+
+        let player = Some(app.world.spawn_empty().id());
+        let first_person = spawn_rig(&mut app, CameraType::FirstPerson, player);
+        let third_person = spawn_rig(&mut app, CameraType::ThirdPerson, player);
+        let custom = spawn_rig(&mut app, CameraType::Custom("Test"), player);
+        let editor = spawn_rig(&mut app, CameraType::Custom("Editor"), None);
+        app.update();
+
+        assert!(!is_active(&app, first_person));
+        assert!(is_active(&app, third_person));
+        assert!(!is_active(&app, custom));
+
+        let rigs = app.world.resource::<CameraRigs>();
+        assert!(rigs.next(CameraType::ThirdPerson) == CameraType::Custom("Test"));
+        assert!(rigs.next(CameraType::Custom("Test")) == CameraType::FirstPerson);
+        assert!(!rigs
+            .iter()
+            .any(|c_type| c_type == CameraType::Custom("Editor")));
+
+        app.world.resource_mut::<CameraSettings>().c_type = CameraType::Custom("Test");
+        app.update();
+        assert!(!is_active(&app, first_person));
+        assert!(!is_active(&app, third_person));
+        assert!(is_active(&app, custom));
+
+        // A rig that doesn't exist keeps the current cameras
+        app.world.resource_mut::<CameraSettings>().c_type = CameraType::Custom("Missing");
+        app.update();
+        assert!(is_active(&app, custom));
+
+        app.world.despawn(custom);
+        app.update();
+        let rigs = app.world.resource::<CameraRigs>();
+        assert!(rigs.next(CameraType::ThirdPerson) == CameraType::FirstPerson);
+
+        // Rigs that aren't cycled through can still be switched to
+        app.world.resource_mut::<CameraSettings>().c_type = CameraType::Custom("Editor");
+        app.update();
+        assert!(is_active(&app, editor));
+    }
+}
@@ -0,0 +1,169 @@
+use bevy::{prelude::*, transform::TransformSystem, utils::HashMap};
+use std::f32::consts::PI;
+
+use crate::{
+    camera::CameraRig,
+    light_animation::value_noise,
+    player::{PlayerMotion, PLAYER_SPEED},
+    ui::{CameraSettings, CameraType},
+};
+
+// Distance walked during one step, a full bob cycle is two steps
+const HEAD_BOB_STRIDE: f32 = 0.8;
+const HEAD_BOB_HEIGHT: f32 = 0.04;
+const HEAD_BOB_SWAY: f32 = 0.02;
+// How fast the bob fades in and out when starting and stopping
+const HEAD_BOB_BLEND_RATE: f32 = 6.0;
+
+// Trauma lost per second
+const TRAUMA_DECAY: f32 = 0.8;
+const SHAKE_FREQUENCY: f32 = 18.0;
+const SHAKE_MAX_ANGLE: f32 = 0.08;
+const SHAKE_MAX_OFFSET: f32 = 0.08;
+
+#[derive(Resource)]
+pub struct CameraEffectSettings {
+    pub head_bob: bool,
+    pub camera_shake: bool,
+}
+
+impl Default for CameraEffectSettings {
+    fn default() -> Self {
+        CameraEffectSettings {
+            head_bob: true,
+            camera_shake: true,
+        }
+    }
+}
+
+// Gameplay code adds trauma, the shake grows with the square of it and calms down over time
+#[derive(Resource, Default)]
+pub struct CameraShake {
+    trauma: f32,
+}
+
+impl CameraShake {
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+}
+
+// What was added on top of the camera transform this frame, so that it can be taken off again
+#[derive(Component, Default)]
+struct CameraEffectOffset {
+    translation: Vec3,
+    rotation: Quat,
+}
+
+#[derive(Default)]
+struct HeadBob {
+    phase: f32,
+    amount: f32,
+}
+
+pub struct CameraEffectsPlugin;
+
+impl Plugin for CameraEffectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(CameraEffectSettings::default())
+            .insert_resource(CameraShake::default())
+            .add_system(attach_camera_offsets)
+            .add_system_to_stage(CoreStage::PreUpdate, remove_camera_effects)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                apply_camera_effects.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+fn attach_camera_offsets(mut commands: Commands, query_cams: Query<Entity, Added<Camera>>) {
+    for entity in query_cams.iter() {
+        commands
+            .entity(entity)
+            .insert(CameraEffectOffset::default());
+    }
+}
+
+// Everything else only ever sees the camera transforms without the effects
+fn remove_camera_effects(mut query_cams: Query<(&mut Transform, &mut CameraEffectOffset)>) {
+    for (mut transform, mut offset) in query_cams.iter_mut() {
+        if offset.translation == Vec3::ZERO && offset.rotation == Quat::IDENTITY {
+            continue;
+        }
+        transform.translation -= offset.translation;
+        transform.rotation *= offset.rotation.inverse();
+        *offset = CameraEffectOffset::default();
+    }
+}
+
+fn apply_camera_effects(
+    time: Res<Time>,
+    effect_settings: Res<CameraEffectSettings>,
+    cam_settings: Res<CameraSettings>,
+    mut shake: ResMut<CameraShake>,
+    query_motion: Query<&PlayerMotion>,
+    mut query_cams: Query<(
+        Entity,
+        &Camera,
+        &CameraRig,
+        &mut Transform,
+        &mut CameraEffectOffset,
+    )>,
+    mut head_bobs: Local<HashMap<Entity, HeadBob>>,
+) {
+    let dt = time.delta_seconds();
+    shake.trauma = (shake.trauma - TRAUMA_DECAY * dt).max(0.0);
+
+    // Trauma based shake, using noise so that it looks like handheld shaking instead of jitter
+    let shake_amount = if effect_settings.camera_shake {
+        shake.trauma * shake.trauma
+    } else {
+        0.0
+    };
+    let t = time.elapsed_seconds() * SHAKE_FREQUENCY;
+    let noise = |seed| value_noise(seed, t) * 2.0 - 1.0;
+    let shake_rotation = Quat::from_euler(
+        EulerRot::YXZ,
+        noise(1) * SHAKE_MAX_ANGLE * shake_amount,
+        noise(2) * SHAKE_MAX_ANGLE * shake_amount,
+        noise(3) * SHAKE_MAX_ANGLE * shake_amount,
+    );
+    let shake_offset = Vec3::new(noise(4), noise(5), noise(6)) * SHAKE_MAX_OFFSET * shake_amount;
+
+    head_bobs.retain(|camera, _| query_cams.contains(*camera));
+    for (camera, cam, rig, mut transform, mut offset) in query_cams.iter_mut() {
+        if !cam.is_active {
+            continue;
+        }
+
+        // Head bob, in first person only, following the player the camera belongs to
+        let speed = rig
+            .player
+            .and_then(|player| query_motion.get(player).ok())
+            .map_or(0.0, |motion| motion.speed);
+        let target_amount =
+            if effect_settings.head_bob && cam_settings.c_type == CameraType::FirstPerson {
+                (speed / PLAYER_SPEED).min(1.5)
+            } else {
+                0.0
+            };
+        let head_bob = head_bobs.entry(camera).or_default();
+        head_bob.amount +=
+            (target_amount - head_bob.amount) * (1.0 - (-HEAD_BOB_BLEND_RATE * dt).exp());
+        head_bob.phase = (head_bob.phase + speed * dt / HEAD_BOB_STRIDE * PI) % (2.0 * PI);
+        let bob = Vec3::new(
+            head_bob.phase.cos() * HEAD_BOB_SWAY,
+            -head_bob.phase.sin().abs() * HEAD_BOB_HEIGHT,
+            0.0,
+        ) * head_bob.amount;
+
+        // The offsets are in camera space, the translation is in the space of the parent
+        let translation = transform.rotation * (bob + shake_offset);
+        transform.translation += translation;
+        transform.rotation *= shake_rotation;
+        *offset = CameraEffectOffset {
+            translation,
+            rotation: shake_rotation,
+        };
+    }
+}
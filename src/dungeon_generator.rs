@@ -0,0 +1,396 @@
+use bevy::{gltf::GltfExtras, prelude::*, utils::HashSet};
+
+use crate::player::PLAYER_HEIGHT_2;
+
+// Levels named like "procedural:42" are generated from the seed instead of loaded from a file
+pub const PROCEDURAL_LEVEL_PREFIX: &str = "procedural:";
+
+const CELL_SIZE: f32 = 2.0;
+const WALL_HEIGHT: f32 = 3.0;
+const WALL_THICKNESS: f32 = 0.2;
+const FLOOR_THICKNESS: f32 = 0.1;
+const LIGHT_HEIGHT: f32 = 2.4;
+const PICKUP_HEIGHT: f32 = 0.5;
+
+const COLOR_FLOOR: &str = "5A5550"; // Worn stone
+const COLOR_WALL: &str = "3E3A36"; // Dark stone
+const COLOR_LIGHT: &str = "FFB46B"; // Torch light
+
+pub fn procedural_level(seed: u64) -> String {
+    format!("{PROCEDURAL_LEVEL_PREFIX}{seed}")
+}
+
+pub fn procedural_seed(level: &str) -> Option<u64> {
+    level.strip_prefix(PROCEDURAL_LEVEL_PREFIX)?.parse().ok()
+}
+
+pub struct DungeonSettings {
+    pub room_count: usize,
+    // Width and depth of the dungeon, in cells
+    pub size: i32,
+    pub min_room_size: i32,
+    pub max_room_size: i32,
+}
+
+impl Default for DungeonSettings {
+    fn default() -> Self {
+        DungeonSettings {
+            room_count: 8,
+            size: 32,
+            min_room_size: 3,
+            max_room_size: 6,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Room {
+    pub min: IVec2,
+    // Exclusive
+    pub max: IVec2,
+}
+
+impl Room {
+    fn center(&self) -> IVec2 {
+        (self.min + self.max) / 2
+    }
+
+    // Rooms are kept a cell apart so that their walls don't touch
+    fn overlaps(&self, other: &Room) -> bool {
+        self.min.x <= other.max.x
+            && other.min.x <= self.max.x
+            && self.min.y <= other.max.y
+            && other.min.y <= self.max.y
+    }
+}
+
+// Rooms connected by corridors, in cells of CELL_SIZE with x to the east and y to the south
+pub struct DungeonLayout {
+    pub rooms: Vec<Room>,
+    // Every cell that has a floor, sorted so that building the level doesn't depend on hashing
+    pub floor: Vec<IVec2>,
+}
+
+impl DungeonLayout {
+    pub fn is_floor(&self, cell: IVec2) -> bool {
+        self.floor
+            .binary_search_by_key(&(cell.y, cell.x), |cell| (cell.y, cell.x))
+            .is_ok()
+    }
+
+    // The dungeon is centered on the origin
+    pub fn cell_center(&self, cell: IVec2, size: i32) -> Vec3 {
+        let offset = (cell - IVec2::splat(size / 2)).as_vec2() * CELL_SIZE + CELL_SIZE / 2.0;
+        Vec3::new(offset.x, 0.0, offset.y)
+    }
+}
+
+// SplitMix64, small and the same on every platform, which is all the generator needs
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // In min..=max
+    fn range(&mut self, min: i32, max: i32) -> i32 {
+        min + (self.next() % (max - min + 1) as u64) as i32
+    }
+
+    fn coin(&mut self) -> bool {
+        self.next() & 1 == 1
+    }
+}
+
+pub fn generate_dungeon(seed: u64, settings: &DungeonSettings) -> DungeonLayout {
+    let mut rng = Rng(seed);
+
+    let mut rooms: Vec<Room> = Vec::new();
+    for _ in 0..settings.room_count * 20 {
+        if rooms.len() == settings.room_count {
+            break;
+        }
+        let size = IVec2::new(
+            rng.range(settings.min_room_size, settings.max_room_size),
+            rng.range(settings.min_room_size, settings.max_room_size),
+        );
+        let min = IVec2::new(
+            rng.range(1, settings.size - size.x - 1),
+            rng.range(1, settings.size - size.y - 1),
+        );
+        let room = Room {
+            min,
+            max: min + size,
+        };
+        if rooms.iter().all(|other| !room.overlaps(other)) {
+            rooms.push(room);
+        }
+    }
+
+    let mut floor: HashSet<IVec2> = HashSet::default();
+    for room in rooms.iter() {
+        for y in room.min.y..room.max.y {
+            for x in room.min.x..room.max.x {
+                floor.insert(IVec2::new(x, y));
+            }
+        }
+    }
+
+    // Every room is connected to the closest room placed before it, so they are all reachable
+    for (i, room) in rooms.iter().enumerate().skip(1) {
+        let from = room.center();
+        let Some(to) = rooms[..i]
+            .iter()
+            .map(|other| other.center())
+            .min_by_key(|center| {
+                let distance = (*center - from).abs();
+                distance.x + distance.y
+            })
+        else {
+            continue;
+        };
+        let corner = if rng.coin() {
+            IVec2::new(to.x, from.y)
+        } else {
+            IVec2::new(from.x, to.y)
+        };
+        for (start, end) in [(from, corner), (corner, to)] {
+            let step = (end - start).signum();
+            let mut cell = start;
+            floor.insert(cell);
+            while cell != end {
+                cell += step;
+                floor.insert(cell);
+            }
+        }
+    }
+
+    let mut floor: Vec<IVec2> = floor.into_iter().collect();
+    floor.sort_by_key(|cell| (cell.y, cell.x));
+    DungeonLayout { rooms, floor }
+}
+
+// A scene like the ones loaded from glTF files, with the same node names so that spawn points
+// and torches are picked up by the other plugins
+pub fn build_dungeon_scene(
+    layout: &DungeonLayout,
+    settings: &DungeonSettings,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) -> Scene {
+    let mut world = World::new();
+
+    let floor_mesh = meshes.add(Mesh::from(shape::Box::new(
+        CELL_SIZE,
+        FLOOR_THICKNESS,
+        CELL_SIZE,
+    )));
+    let wall_mesh_x = meshes.add(Mesh::from(shape::Box::new(
+        CELL_SIZE + WALL_THICKNESS,
+        WALL_HEIGHT,
+        WALL_THICKNESS,
+    )));
+    let wall_mesh_z = meshes.add(Mesh::from(shape::Box::new(
+        WALL_THICKNESS,
+        WALL_HEIGHT,
+        CELL_SIZE + WALL_THICKNESS,
+    )));
+    let floor_material = materials.add(StandardMaterial {
+        base_color: Color::hex(COLOR_FLOOR).unwrap(),
+        perceptual_roughness: 0.9,
+        ..default()
+    });
+    let wall_material = materials.add(StandardMaterial {
+        base_color: Color::hex(COLOR_WALL).unwrap(),
+        perceptual_roughness: 0.8,
+        ..default()
+    });
+
+    for (i, cell) in layout.floor.iter().enumerate() {
+        let center = layout.cell_center(*cell, settings.size);
+        world.spawn((
+            PbrBundle {
+                mesh: floor_mesh.clone(),
+                material: floor_material.clone(),
+                transform: Transform::from_translation(center - Vec3::Y * FLOOR_THICKNESS / 2.0),
+                ..default()
+            },
+            Name::new(format!("floor.{i:03}")),
+        ));
+
+        // A wall on every side that doesn't lead to more floor
+        for direction in [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+            if layout.is_floor(*cell + direction) {
+                continue;
+            }
+            let offset = direction.as_vec2() * CELL_SIZE / 2.0;
+            let mesh = if direction.x == 0 {
+                wall_mesh_x.clone()
+            } else {
+                wall_mesh_z.clone()
+            };
+            world.spawn((
+                PbrBundle {
+                    mesh,
+                    material: wall_material.clone(),
+                    transform: Transform::from_translation(
+                        center + Vec3::new(offset.x, WALL_HEIGHT / 2.0, offset.y),
+                    ),
+                    ..default()
+                },
+                Name::new("wall"),
+            ));
+        }
+    }
+
+    for (i, room) in layout.rooms.iter().enumerate() {
+        let center = layout.cell_center(room.center(), settings.size);
+        world.spawn((
+            PointLightBundle {
+                point_light: PointLight {
+                    color: Color::hex(COLOR_LIGHT).unwrap(),
+                    range: 12.0,
+                    ..default()
+                },
+                transform: Transform::from_translation(center + Vec3::Y * LIGHT_HEIGHT),
+                ..default()
+            },
+            Name::new(format!("torch_light.{i:03}")),
+        ));
+    }
+
+    if let Some(room) = layout.rooms.first() {
+        let center = layout.cell_center(room.center(), settings.size);
+        world.spawn((
+            SpatialBundle::from_transform(Transform::from_translation(
+                center + Vec3::Y * PLAYER_HEIGHT_2,
+            )),
+            Name::new("spawn_point"),
+        ));
+
+        // The follower waits next to the player
+        let next_to_center = layout.cell_center(room.center() + IVec2::X, settings.size);
+        world.spawn((
+            SpatialBundle::from_transform(Transform::from_translation(
+                next_to_center + Vec3::Y * PLAYER_HEIGHT_2,
+            )),
+            Name::new("npc_follow"),
+        ));
+    }
+
+    // The wanderer walks from room to room, starting in the second one
+    let room_centers: Vec<Vec3> = layout
+        .rooms
+        .iter()
+        .map(|room| layout.cell_center(room.center(), settings.size))
+        .collect();
+    if let Some(start) = room_centers.get(1) {
+        let waypoints: Vec<[f32; 3]> = room_centers
+            .iter()
+            .cycle()
+            .skip(2)
+            .take(room_centers.len())
+            .map(|center| center.to_array())
+            .collect();
+        world.spawn((
+            SpatialBundle::from_transform(Transform::from_translation(
+                *start + Vec3::Y * PLAYER_HEIGHT_2,
+            )),
+            Name::new("npc_wander"),
+            GltfExtras {
+                value: serde_json::json!({ "npc": "wander", "waypoints": waypoints }).to_string(),
+            },
+        ));
+    }
+
+    // A coin in each room after the first, and the key in the last one
+    for (i, center) in room_centers.iter().enumerate().skip(1) {
+        let kind = if i == room_centers.len() - 1 {
+            "key"
+        } else {
+            "coin"
+        };
+        world.spawn((
+            SpatialBundle::from_transform(Transform::from_translation(
+                *center + Vec3::Y * PICKUP_HEIGHT,
+            )),
+            Name::new(format!("pickup_{kind}.{i:03}")),
+        ));
+    }
+
+    Scene::new(world)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn same_seed_same_dungeon() {
+        let settings = DungeonSettings::default();
+        let a = generate_dungeon(42, &settings);
+        let b = generate_dungeon(42, &settings);
+        assert_eq!(a.rooms, b.rooms);
+        assert_eq!(a.floor, b.floor);
+    }
+
+    #[test]
+    fn different_seeds_different_dungeons() {
+        let settings = DungeonSettings::default();
+        let a = generate_dungeon(1, &settings);
+        let b = generate_dungeon(2, &settings);
+        assert_ne!(a.floor, b.floor);
+    }
+
+    #[test]
+    fn rooms_fit_and_dont_overlap() {
+        let settings = DungeonSettings::default();
+        for seed in 0..50 {
+            let layout = generate_dungeon(seed, &settings);
+            assert!(layout.rooms.len() > 1, "seed {seed} placed too few rooms");
+            for (i, room) in layout.rooms.iter().enumerate() {
+                assert!(room.min.cmpge(IVec2::ZERO).all());
+                assert!(room.max.cmple(IVec2::splat(settings.size)).all());
+                for other in layout.rooms[i + 1..].iter() {
+                    assert!(
+                        !room.overlaps(other),
+                        "seed {seed}: {room:?} overlaps {other:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_room_is_reachable() {
+        let settings = DungeonSettings::default();
+        for seed in 0..50 {
+            let layout = generate_dungeon(seed, &settings);
+            let start = layout.rooms[0].center();
+            let mut reached: HashSet<IVec2> = HashSet::default();
+            let mut queue = VecDeque::from([start]);
+            reached.insert(start);
+            while let Some(cell) = queue.pop_front() {
+                for direction in [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+                    let next = cell + direction;
+                    if layout.is_floor(next) && reached.insert(next) {
+                        queue.push_back(next);
+                    }
+                }
+            }
+            assert_eq!(reached.len(), layout.floor.len(), "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn seed_round_trips_through_the_level_name() {
+        assert_eq!(procedural_seed(&procedural_level(1234)), Some(1234));
+        assert_eq!(procedural_seed("dungeon.gltf"), None);
+    }
+}
@@ -0,0 +1,997 @@
+use bevy::{
+    input::mouse::MouseMotion, math::Ray, pbr::NotShadowCaster, prelude::*, reflect::TypeUuid,
+    render::primitives::Aabb, utils::HashMap,
+};
+use bevy_egui::EguiContext;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::{
+    camera::CameraRig,
+    dungeon_generator::procedural_seed,
+    items::Pickup,
+    light_animation::{LightAnimation, LightAnimationKind},
+    navigation::Npc,
+    ron_asset::RonAssetPlugin,
+    ui::{CameraSettings, CameraType},
+    AppState, CurrentLevel, LevelRoot,
+};
+
+pub const EDITOR_CAMERA: CameraType = CameraType::Custom("Editor");
+const EDITOR_CAMERA_POS: Vec3 = Vec3::new(-1.0, 10.0, 16.0);
+const EDITOR_CAMERA_SPEED: f32 = 6.0;
+const EDITOR_CAMERA_RUN_SPEED: f32 = 15.0;
+// Radians per pixel of mouse movement
+const EDITOR_LOOK_SENSITIVITY: f32 = 0.004;
+const GIZMO_ROTATE_SENSITIVITY: f32 = 0.01;
+
+const GIZMO_LENGTH: f32 = 1.0;
+const GIZMO_THICKNESS: f32 = 0.06;
+// New lights are placed this high above where the floor was clicked
+const LIGHT_HEIGHT: f32 = 2.4;
+const LIGHT_MARKER_RADIUS: f32 = 0.15;
+const COLOR_LIGHT_MARKER: &str = "FFE9A8";
+// Meshes of the level bigger than this, from their center to their furthest side, are walls and
+// floors rather than props
+const PROP_MAX_HALF_SIZE: f32 = 2.0;
+
+const KEY_MOVE: KeyCode = KeyCode::G;
+const KEY_ROTATE: KeyCode = KeyCode::R;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PropKind {
+    Crate,
+    Pillar,
+    Orb,
+}
+
+impl PropKind {
+    pub const ALL: [PropKind; 3] = [PropKind::Crate, PropKind::Pillar, PropKind::Orb];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PropKind::Crate => "Crate",
+            PropKind::Pillar => "Pillar",
+            PropKind::Orb => "Orb",
+        }
+    }
+
+    fn size(&self) -> Vec3 {
+        match self {
+            PropKind::Crate => Vec3::splat(0.8),
+            PropKind::Pillar => Vec3::new(0.5, 3.0, 0.5),
+            PropKind::Orb => Vec3::splat(0.6),
+        }
+    }
+
+    fn color(&self) -> &'static str {
+        match self {
+            PropKind::Crate => "7A5230",  // Wood
+            PropKind::Pillar => "6E6A64", // Stone
+            PropKind::Orb => "4FA3C7",    // Crystal
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum EditorObjectKind {
+    Light { range: f32, flicker: bool },
+    Prop(PropKind),
+}
+
+impl EditorObjectKind {
+    pub const LIGHT: EditorObjectKind = EditorObjectKind::Light {
+        range: 10.0,
+        flicker: false,
+    };
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            EditorObjectKind::Light { .. } => "Light",
+            EditorObjectKind::Prop(prop) => prop.label(),
+        }
+    }
+
+    // Size of the sphere the mouse has to be over to select the object
+    fn pick_radius(&self) -> f32 {
+        match self {
+            EditorObjectKind::Light { .. } => LIGHT_MARKER_RADIUS * 2.0,
+            EditorObjectKind::Prop(prop) => prop.size().max_element() / 2.0,
+        }
+    }
+}
+
+// A light or prop placed with the editor, saved in the overlay file of the level
+#[derive(Component)]
+pub struct EditorObject {
+    pub kind: EditorObjectKind,
+}
+
+// A point light or a prop mesh of the level's glTF file, the overlay moves, rotates or deletes it
+// by name
+#[derive(Component)]
+struct LevelNode {
+    original: Transform,
+    pick_radius: f32,
+}
+
+// Only shown while editing, like the bulbs that make lights clickable
+#[derive(Component)]
+struct EditorOnly;
+
+#[derive(Component)]
+struct Gizmo;
+
+#[derive(Component)]
+struct GizmoHandle {
+    axis: Vec3,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct OverlayObject {
+    kind: EditorObjectKind,
+    translation: [f32; 3],
+    rotation: [f32; 4],
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct OverlayNode {
+    name: String,
+    translation: [f32; 3],
+    rotation: [f32; 4],
+}
+
+// Lights and props added on top of a level, and changes to the ones of the level itself, read
+// from e.g. "dungeon.overlay.ron" next to "dungeon.gltf"
+#[derive(Serialize, Deserialize, TypeUuid, Default)]
+#[uuid = "0c7f4d8e-2b6a-4f3e-9d1c-7a5e8b2f6c40"]
+pub struct LevelOverlay {
+    objects: Vec<OverlayObject>,
+    // Nodes of the glTF file that have been moved or rotated
+    #[serde(default)]
+    nodes: Vec<OverlayNode>,
+    // Names of the nodes of the glTF file that have been deleted
+    #[serde(default)]
+    deleted: Vec<String>,
+}
+
+// Generated levels are different for every seed, only the ones made in Blender get an overlay
+fn overlay_path(level: &str) -> Option<String> {
+    if procedural_seed(level).is_some() {
+        return None;
+    }
+    let path = Path::new(level).with_extension("overlay.ron");
+    Some(path.to_string_lossy().replace('\\', "/"))
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum GizmoMode {
+    Move,
+    Rotate,
+}
+
+struct Drag {
+    axis: Vec3,
+    translation: Vec3,
+    rotation: Quat,
+    // Where the mouse started on the axis, for moving
+    axis_start: f32,
+    cursor_start: Vec2,
+}
+
+#[derive(Resource)]
+pub struct Editor {
+    pub mode: GizmoMode,
+    // The next click places this rather than selecting
+    pub placing: Option<EditorObjectKind>,
+    pub selected: Option<Entity>,
+    pub status: Option<String>,
+    drag: Option<Drag>,
+    // The camera to go back to when leaving the editor
+    previous_camera: Option<CameraType>,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Editor {
+            mode: GizmoMode::Move,
+            placing: None,
+            selected: None,
+            status: None,
+            drag: None,
+            previous_camera: None,
+        }
+    }
+}
+
+pub struct SaveOverlay;
+
+pub struct DeleteSelected;
+
+#[derive(Resource)]
+struct EditorAssets {
+    prop_meshes: Vec<(PropKind, Handle<Mesh>, Handle<StandardMaterial>)>,
+    light_marker_mesh: Handle<Mesh>,
+    light_marker_material: Handle<StandardMaterial>,
+}
+
+#[derive(Resource, Default)]
+struct OverlayState {
+    handle: Option<Handle<LevelOverlay>>,
+    // The level root the overlay has been spawned under
+    applied_to: Option<Entity>,
+    // Nodes of the level deleted by the overlay or since, they are gone from the world
+    deleted: Vec<String>,
+}
+
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(RonAssetPlugin::<LevelOverlay>::new(&["overlay.ron"]))
+            .add_event::<SaveOverlay>()
+            .add_event::<DeleteSelected>()
+            .init_resource::<Editor>()
+            .init_resource::<OverlayState>()
+            .add_startup_system(setup_editor)
+            .add_system(switch_editor_camera)
+            .add_system_set(
+                SystemSet::on_update(AppState::Editor)
+                    .with_system(move_editor_camera)
+                    .with_system(editor_shortcuts.before(editor_pointer))
+                    .with_system(editor_pointer),
+            )
+            .add_system(delete_selected.after(editor_shortcuts))
+            .add_system(update_gizmo.after(editor_pointer))
+            .add_system(update_editor_only_visibility)
+            .add_system(sync_editor_lights)
+            .add_system(load_overlay)
+            .add_system(apply_overlay.after(load_overlay))
+            .add_system(attach_level_nodes)
+            .add_system(apply_node_overlay.after(load_overlay))
+            .add_system(save_overlay);
+    }
+}
+
+fn setup_editor(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    cam_settings: Res<CameraSettings>,
+) {
+    let prop_meshes = PropKind::ALL
+        .iter()
+        .map(|prop| {
+            let size = prop.size();
+            let mesh = match prop {
+                PropKind::Orb => Mesh::from(shape::UVSphere {
+                    radius: size.x / 2.0,
+                    ..default()
+                }),
+                _ => Mesh::from(shape::Box::new(size.x, size.y, size.z)),
+            };
+            let material = StandardMaterial {
+                base_color: Color::hex(prop.color()).unwrap(),
+                perceptual_roughness: 0.8,
+                ..default()
+            };
+            (*prop, meshes.add(mesh), materials.add(material))
+        })
+        .collect();
+    commands.insert_resource(EditorAssets {
+        prop_meshes,
+        light_marker_mesh: meshes.add(Mesh::from(shape::UVSphere {
+            radius: LIGHT_MARKER_RADIUS,
+            ..default()
+        })),
+        light_marker_material: materials.add(StandardMaterial {
+            base_color: Color::hex(COLOR_LIGHT_MARKER).unwrap(),
+            unlit: true,
+            ..default()
+        }),
+    });
+
+    // Free flying camera, only used in the editor
+    commands.spawn((
+        Camera3dBundle {
+            transform: Transform::from_translation(EDITOR_CAMERA_POS)
+                .looking_at(Vec3::new(EDITOR_CAMERA_POS.x, 0.0, 4.0), Vec3::Y),
+            camera: Camera {
+                is_active: false,
+                hdr: cam_settings.bloom_enabled,
+                ..default()
+            },
+            tonemapping: cam_settings.tonemapping.clone(),
+            ..default()
+        },
+        cam_settings.bloom.clone(),
+        CameraRig {
+            c_type: EDITOR_CAMERA,
+            player: None,
+        },
+    ));
+
+    // One handle per axis, red for X, green for Y and blue for Z
+    let gizmo_mesh = meshes.add(Mesh::from(shape::Box::new(
+        GIZMO_LENGTH,
+        GIZMO_THICKNESS,
+        GIZMO_THICKNESS,
+    )));
+    commands
+        .spawn((
+            SpatialBundle {
+                visibility: Visibility { is_visible: false },
+                ..default()
+            },
+            Gizmo,
+        ))
+        .with_children(|parent| {
+            for (axis, color) in [
+                (Vec3::X, Color::RED),
+                (Vec3::Y, Color::GREEN),
+                (Vec3::Z, Color::BLUE),
+            ] {
+                parent.spawn((
+                    PbrBundle {
+                        mesh: gizmo_mesh.clone(),
+                        material: materials.add(StandardMaterial {
+                            base_color: color,
+                            unlit: true,
+                            ..default()
+                        }),
+                        transform: Transform::from_translation(axis * GIZMO_LENGTH / 2.0)
+                            .with_rotation(Quat::from_rotation_arc(Vec3::X, axis)),
+                        ..default()
+                    },
+                    GizmoHandle { axis },
+                    NotShadowCaster,
+                ));
+            }
+        });
+}
+
+fn spawn_editor_object(
+    commands: &mut Commands,
+    editor_assets: &EditorAssets,
+    root: Option<Entity>,
+    kind: EditorObjectKind,
+    transform: Transform,
+) -> Entity {
+    let mut entity = match kind {
+        EditorObjectKind::Light { range, .. } => {
+            let mut entity = commands.spawn((
+                PointLightBundle {
+                    point_light: PointLight {
+                        range,
+                        shadows_enabled: true,
+                        ..default()
+                    },
+                    transform,
+                    ..default()
+                },
+                Name::new("editor_light"),
+            ));
+            entity.with_children(|parent| {
+                parent.spawn((
+                    PbrBundle {
+                        mesh: editor_assets.light_marker_mesh.clone(),
+                        material: editor_assets.light_marker_material.clone(),
+                        ..default()
+                    },
+                    EditorOnly,
+                    NotShadowCaster,
+                ));
+            });
+            entity
+        }
+        EditorObjectKind::Prop(prop) => {
+            let (_, mesh, material) = editor_assets
+                .prop_meshes
+                .iter()
+                .find(|(kind, ..)| *kind == prop)
+                .unwrap();
+            commands.spawn((
+                PbrBundle {
+                    mesh: mesh.clone(),
+                    material: material.clone(),
+                    transform,
+                    ..default()
+                },
+                Name::new(format!("prop_{}", prop.label().to_ascii_lowercase())),
+            ))
+        }
+    };
+    entity.insert(EditorObject { kind });
+    let entity = entity.id();
+
+    // Part of the level, so that it goes away with it and counts for navigation
+    if let Some(root) = root {
+        commands.entity(root).add_child(entity);
+    }
+    entity
+}
+
+fn switch_editor_camera(
+    app_state: Res<State<AppState>>,
+    mut editor: ResMut<Editor>,
+    mut cam_settings: ResMut<CameraSettings>,
+) {
+    let editing = *app_state.current() == AppState::Editor;
+    if editing && editor.previous_camera.is_none() {
+        editor.previous_camera = Some(cam_settings.c_type);
+        cam_settings.c_type = EDITOR_CAMERA;
+    } else if !editing {
+        if let Some(previous_camera) = editor.previous_camera.take() {
+            cam_settings.c_type = previous_camera;
+            editor.selected = None;
+            editor.placing = None;
+            editor.drag = None;
+        }
+    }
+}
+
+// Hold the right mouse button to look around, WASD to move and Q/E to go down and up
+fn move_editor_camera(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    mut mouse_motion_events: EventReader<MouseMotion>,
+    mut query_cams: Query<(&CameraRig, &mut Transform)>,
+) {
+    let mouse_delta: Vec2 = mouse_motion_events.iter().map(|event| event.delta).sum();
+    let Some((_, mut transform)) = query_cams
+        .iter_mut()
+        .find(|(rig, _)| rig.c_type == EDITOR_CAMERA)
+    else {
+        return;
+    };
+
+    if mouse.pressed(MouseButton::Right) {
+        let (yaw, pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+        let yaw = yaw - mouse_delta.x * EDITOR_LOOK_SENSITIVITY;
+        let pitch = (pitch - mouse_delta.y * EDITOR_LOOK_SENSITIVITY).clamp(-1.54, 1.54);
+        transform.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.0);
+    }
+
+    let mut direction = Vec3::ZERO;
+    for (keys, axis) in [
+        ([KeyCode::W, KeyCode::Up], transform.forward()),
+        ([KeyCode::S, KeyCode::Down], transform.back()),
+        ([KeyCode::D, KeyCode::Right], transform.right()),
+        ([KeyCode::A, KeyCode::Left], transform.left()),
+        ([KeyCode::E, KeyCode::PageUp], Vec3::Y),
+        ([KeyCode::Q, KeyCode::PageDown], Vec3::NEG_Y),
+    ] {
+        if keyboard_input.any_pressed(keys) {
+            direction += axis;
+        }
+    }
+    let speed = if keyboard_input.pressed(KeyCode::LShift) {
+        EDITOR_CAMERA_RUN_SPEED
+    } else {
+        EDITOR_CAMERA_SPEED
+    };
+    transform.translation += direction.normalize_or_zero() * speed * time.delta_seconds();
+}
+
+// Typing into a text field or a number of the Editor window doesn't count
+fn editor_shortcuts(
+    mut egui_context: ResMut<EguiContext>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut editor: ResMut<Editor>,
+    mut delete_events: EventWriter<DeleteSelected>,
+) {
+    if egui_context.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    if keyboard_input.just_pressed(KEY_MOVE) {
+        editor.mode = GizmoMode::Move;
+    }
+    if keyboard_input.just_pressed(KEY_ROTATE) {
+        editor.mode = GizmoMode::Rotate;
+    }
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        editor.placing = None;
+        editor.selected = None;
+    }
+    if keyboard_input.just_pressed(KeyCode::Delete) {
+        delete_events.send(DeleteSelected);
+    }
+}
+
+// Deleted nodes of the level are remembered by name for the overlay
+fn delete_selected(
+    mut commands: Commands,
+    mut delete_events: EventReader<DeleteSelected>,
+    mut editor: ResMut<Editor>,
+    mut overlay_state: ResMut<OverlayState>,
+    query_nodes: Query<&Name, With<LevelNode>>,
+) {
+    if delete_events.iter().last().is_none() {
+        return;
+    }
+    let Some(entity) = editor.selected.take() else {
+        return;
+    };
+    if let Ok(name) = query_nodes.get(entity) {
+        overlay_state.deleted.push(name.to_string());
+    }
+    commands.entity(entity).despawn_recursive();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn editor_pointer(
+    mut commands: Commands,
+    mut egui_context: ResMut<EguiContext>,
+    windows: Res<Windows>,
+    mouse: Res<Input<MouseButton>>,
+    editor_assets: Res<EditorAssets>,
+    mut editor: ResMut<Editor>,
+    query_cams: Query<(&Camera, &GlobalTransform, &CameraRig)>,
+    query_objects: Query<(Entity, &EditorObject, &GlobalTransform)>,
+    query_nodes: Query<(Entity, &LevelNode, &GlobalTransform)>,
+    query_handles: Query<(&GizmoHandle, &GlobalTransform)>,
+    query_roots: Query<Entity, With<LevelRoot>>,
+    mut query_transforms: Query<&mut Transform>,
+) {
+    if !mouse.pressed(MouseButton::Left) {
+        editor.drag = None;
+    }
+    let Some(cursor) = windows
+        .get_primary()
+        .and_then(|window| window.cursor_position())
+    else {
+        return;
+    };
+    let Some(ray) = query_cams
+        .iter()
+        .find(|(_, _, rig)| rig.c_type == EDITOR_CAMERA)
+        .and_then(|(cam, transform, _)| cam.viewport_to_world(transform, cursor))
+    else {
+        return;
+    };
+
+    if let Some(drag) = &editor.drag {
+        let Some(mut transform) = editor
+            .selected
+            .and_then(|entity| query_transforms.get_mut(entity).ok())
+        else {
+            return;
+        };
+        match editor.mode {
+            GizmoMode::Move => {
+                if let Some(along) = closest_on_axis(drag.translation, drag.axis, &ray) {
+                    transform.translation =
+                        drag.translation + drag.axis * (along - drag.axis_start);
+                }
+            }
+            GizmoMode::Rotate => {
+                let angle = (cursor.x - drag.cursor_start.x) * GIZMO_ROTATE_SENSITIVITY;
+                transform.rotation = Quat::from_axis_angle(drag.axis, angle) * drag.rotation;
+            }
+        }
+        return;
+    }
+
+    if !mouse.just_pressed(MouseButton::Left) || egui_context.ctx_mut().is_pointer_over_area() {
+        return;
+    }
+
+    // Grabbing a handle of the gizmo
+    if let Some(selected) = editor.selected {
+        let handle = query_handles.iter().find(|(handle, transform)| {
+            let center = transform.translation();
+            let half_size = (handle.axis * GIZMO_LENGTH + GIZMO_THICKNESS * 2.0) / 2.0;
+            ray_hits_box(&ray, center - half_size, center + half_size)
+        });
+        if let (Some((handle, _)), Ok(transform)) = (handle, query_transforms.get(selected)) {
+            editor.drag = Some(Drag {
+                axis: handle.axis,
+                translation: transform.translation,
+                rotation: transform.rotation,
+                axis_start: closest_on_axis(transform.translation, handle.axis, &ray)
+                    .unwrap_or_default(),
+                cursor_start: cursor,
+            });
+            return;
+        }
+    }
+
+    // Placing on the floor
+    if let Some(kind) = editor.placing.take() {
+        let Some(floor) = ray_hits_floor(&ray) else {
+            return;
+        };
+        let height = match kind {
+            EditorObjectKind::Light { .. } => LIGHT_HEIGHT,
+            EditorObjectKind::Prop(prop) => prop.size().y / 2.0,
+        };
+        let transform = Transform::from_translation(floor + Vec3::Y * height);
+        let root = query_roots.iter().next();
+        let entity = spawn_editor_object(&mut commands, &editor_assets, root, kind, transform);
+        editor.selected = Some(entity);
+        return;
+    }
+
+    // Selecting the closest object under the mouse
+    let objects = query_objects
+        .iter()
+        .map(|(entity, object, transform)| (entity, object.kind.pick_radius(), transform));
+    let nodes = query_nodes
+        .iter()
+        .map(|(entity, node, transform)| (entity, node.pick_radius, transform));
+    editor.selected = objects
+        .chain(nodes)
+        .filter_map(|(entity, radius, transform)| {
+            let distance = ray_hits_sphere(&ray, transform.translation(), radius);
+            distance.map(|distance| (entity, distance))
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(entity, _)| entity);
+}
+
+// Distance along the axis of the point closest to the ray
+fn closest_on_axis(origin: Vec3, axis: Vec3, ray: &Ray) -> Option<f32> {
+    let b = axis.dot(ray.direction);
+    let denominator = 1.0 - b * b;
+    if denominator.abs() < 1e-4 {
+        return None;
+    }
+    let offset = origin - ray.origin;
+    let d = axis.dot(offset);
+    let e = ray.direction.dot(offset);
+    Some((b * e - d) / denominator)
+}
+
+fn ray_hits_sphere(ray: &Ray, center: Vec3, radius: f32) -> Option<f32> {
+    let along = (center - ray.origin).dot(ray.direction);
+    if along < 0.0 {
+        return None;
+    }
+    let closest = ray.origin + ray.direction * along;
+    (closest.distance(center) <= radius).then_some(along)
+}
+
+fn ray_hits_box(ray: &Ray, min: Vec3, max: Vec3) -> bool {
+    let inverse = ray.direction.recip();
+    let t1 = (min - ray.origin) * inverse;
+    let t2 = (max - ray.origin) * inverse;
+    let near = t1.min(t2).max_element();
+    let far = t1.max(t2).min_element();
+    far >= near.max(0.0)
+}
+
+fn ray_hits_floor(ray: &Ray) -> Option<Vec3> {
+    if ray.direction.y >= 0.0 {
+        return None;
+    }
+    Some(ray.origin + ray.direction * (-ray.origin.y / ray.direction.y))
+}
+
+fn update_gizmo(
+    app_state: Res<State<AppState>>,
+    editor: Res<Editor>,
+    query_selected: Query<&GlobalTransform>,
+    mut query_gizmo: Query<(&mut Transform, &mut Visibility), With<Gizmo>>,
+) {
+    let target = editor
+        .selected
+        .filter(|_| *app_state.current() == AppState::Editor)
+        .and_then(|entity| query_selected.get(entity).ok());
+    for (mut transform, mut visibility) in query_gizmo.iter_mut() {
+        if visibility.is_visible != target.is_some() {
+            visibility.is_visible = target.is_some();
+        }
+        if let Some(target) = target {
+            transform.translation = target.translation();
+        }
+    }
+}
+
+fn update_editor_only_visibility(
+    app_state: Res<State<AppState>>,
+    mut query_visibility: Query<&mut Visibility, With<EditorOnly>>,
+) {
+    let editing = *app_state.current() == AppState::Editor;
+    for mut visibility in query_visibility.iter_mut() {
+        if visibility.is_visible != editing {
+            visibility.is_visible = editing;
+        }
+    }
+}
+
+fn sync_editor_lights(
+    mut commands: Commands,
+    mut query_lights: Query<(Entity, &EditorObject, &mut PointLight), Changed<EditorObject>>,
+) {
+    for (entity, object, mut light) in query_lights.iter_mut() {
+        let EditorObjectKind::Light { range, flicker } = object.kind else {
+            continue;
+        };
+        light.range = range;
+        if flicker {
+            commands.entity(entity).insert(LightAnimation {
+                kind: LightAnimationKind::Flicker,
+                seed: entity.index(),
+            });
+        } else {
+            commands.entity(entity).remove::<LightAnimation>();
+        }
+    }
+}
+
+// Starts loading the overlay whenever another level is loaded
+fn load_overlay(
+    asset_server: Res<AssetServer>,
+    level: Res<CurrentLevel>,
+    mut overlay_state: ResMut<OverlayState>,
+) {
+    if !level.is_changed() {
+        return;
+    }
+    // Asking for a file that doesn't exist logs an error, most levels have no overlay
+    let handle = overlay_path(&level.0)
+        .filter(|path| asset_server.asset_io().is_file(Path::new(path)))
+        .map(|path| asset_server.load(path.as_str()));
+    *overlay_state = OverlayState {
+        handle,
+        ..default()
+    };
+}
+
+// Spawns the overlay on top of the level, again after the level or the overlay file changed
+#[allow(clippy::too_many_arguments)]
+fn apply_overlay(
+    mut commands: Commands,
+    editor_assets: Res<EditorAssets>,
+    overlays: Res<Assets<LevelOverlay>>,
+    mut overlay_events: EventReader<AssetEvent<LevelOverlay>>,
+    mut overlay_state: ResMut<OverlayState>,
+    mut editor: ResMut<Editor>,
+    query_roots: Query<Entity, With<LevelRoot>>,
+    query_objects: Query<Entity, With<EditorObject>>,
+) {
+    let modified = overlay_events.iter().any(|event| match event {
+        AssetEvent::Modified { handle } => Some(handle) == overlay_state.handle.as_ref(),
+        _ => false,
+    });
+    let Some(root) = query_roots.iter().next() else {
+        return;
+    };
+    if overlay_state.applied_to == Some(root) && !modified {
+        return;
+    }
+    let Some(overlay) = overlay_state
+        .handle
+        .as_ref()
+        .and_then(|handle| overlays.get(handle))
+    else {
+        return;
+    };
+
+    for entity in query_objects.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    editor.selected = None;
+    for object in overlay.objects.iter() {
+        let transform = Transform::from_translation(Vec3::from(object.translation))
+            .with_rotation(Quat::from_array(object.rotation).normalize());
+        spawn_editor_object(
+            &mut commands,
+            &editor_assets,
+            Some(root),
+            object.kind,
+            transform,
+        );
+    }
+    overlay_state.applied_to = Some(root);
+}
+
+// Makes the point lights and the props of the levels that can have an overlay editable
+#[allow(clippy::too_many_arguments)]
+fn attach_level_nodes(
+    mut commands: Commands,
+    level: Res<CurrentLevel>,
+    editor_assets: Res<EditorAssets>,
+    query_new_meshes: Query<(&Aabb, &Parent), Without<EditorOnly>>,
+    query_new_aabbs: Query<Entity, Added<Aabb>>,
+    query_new_lights: Query<&Parent, Added<PointLight>>,
+    query_nodes: Query<&Transform, (With<Name>, Without<EditorObject>)>,
+    // Pickups and NPCs are moved by the game itself
+    query_pickups: Query<(), With<Pickup>>,
+    query_npcs: Query<(), With<Npc>>,
+    query_parents: Query<&Parent>,
+    query_roots: Query<(), With<LevelRoot>>,
+) {
+    if overlay_path(&level.0).is_none() {
+        return;
+    }
+    let editable = |mut entity: Entity| loop {
+        if query_pickups.contains(entity) || query_npcs.contains(entity) {
+            return false;
+        }
+        if query_roots.contains(entity) {
+            return true;
+        }
+        match query_parents.get(entity) {
+            Ok(parent) => entity = parent.get(),
+            Err(_) => return false,
+        }
+    };
+
+    // The glTF loader puts the primitives of a mesh and the lights under their node
+    for parent in query_new_lights.iter() {
+        let node = parent.get();
+        let Ok(transform) = query_nodes.get(node) else {
+            continue;
+        };
+        if !editable(node) {
+            continue;
+        }
+        commands
+            .entity(node)
+            .insert(LevelNode {
+                original: *transform,
+                pick_radius: LIGHT_MARKER_RADIUS * 2.0,
+            })
+            .with_children(|parent| {
+                parent.spawn((
+                    PbrBundle {
+                        mesh: editor_assets.light_marker_mesh.clone(),
+                        material: editor_assets.light_marker_material.clone(),
+                        visibility: Visibility { is_visible: false },
+                        ..default()
+                    },
+                    EditorOnly,
+                    NotShadowCaster,
+                ));
+            });
+    }
+
+    let mut props: HashMap<Entity, f32> = HashMap::default();
+    for (aabb, parent) in query_new_aabbs
+        .iter()
+        .filter_map(|entity| query_new_meshes.get(entity).ok())
+    {
+        let node = parent.get();
+        let Ok(transform) = query_nodes.get(node) else {
+            continue;
+        };
+        let half_size = Vec3::from(aabb.half_extents) * transform.scale.abs();
+        let radius = props.entry(node).or_default();
+        *radius = radius.max(half_size.max_element());
+    }
+    for (node, radius) in props {
+        if radius > PROP_MAX_HALF_SIZE || !editable(node) {
+            continue;
+        }
+        commands.entity(node).insert(LevelNode {
+            original: *query_nodes.get(node).unwrap(),
+            pick_radius: radius,
+        });
+    }
+}
+
+// Moves and deletes the nodes of the level as the overlay says, each node once it shows up and
+// all of them again when the overlay file is loaded or changes. Nodes deleted before the file
+// changed only come back when the level is loaded again
+fn apply_node_overlay(
+    mut commands: Commands,
+    overlays: Res<Assets<LevelOverlay>>,
+    mut overlay_events: EventReader<AssetEvent<LevelOverlay>>,
+    mut overlay_state: ResMut<OverlayState>,
+    mut editor: ResMut<Editor>,
+    query_new_nodes: Query<(), Added<LevelNode>>,
+    mut query_nodes: Query<(Entity, &Name, &LevelNode, &mut Transform)>,
+) {
+    let Some(handle) = overlay_state.handle.clone() else {
+        return;
+    };
+    let reloaded = overlay_events.iter().any(|event| match event {
+        AssetEvent::Created { handle: changed } | AssetEvent::Modified { handle: changed } => {
+            *changed == handle
+        }
+        _ => false,
+    });
+    let Some(overlay) = overlays.get(&handle) else {
+        return;
+    };
+    if reloaded {
+        overlay_state.deleted = overlay.deleted.clone();
+    }
+
+    for (entity, name, node, mut transform) in query_nodes.iter_mut() {
+        if !reloaded && !query_new_nodes.contains(entity) {
+            continue;
+        }
+        if overlay
+            .deleted
+            .iter()
+            .any(|deleted| deleted == name.as_str())
+        {
+            if !overlay_state.deleted.contains(&name.to_string()) {
+                overlay_state.deleted.push(name.to_string());
+            }
+            if editor.selected == Some(entity) {
+                editor.selected = None;
+            }
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        *transform = match overlay.nodes.iter().find(|edit| edit.name == name.as_str()) {
+            Some(edit) => Transform {
+                translation: Vec3::from(edit.translation),
+                rotation: Quat::from_array(edit.rotation).normalize(),
+                scale: node.original.scale,
+            },
+            None => node.original,
+        };
+    }
+}
+
+fn save_overlay(
+    mut save_events: EventReader<SaveOverlay>,
+    level: Res<CurrentLevel>,
+    overlay_state: Res<OverlayState>,
+    mut editor: ResMut<Editor>,
+    query_objects: Query<(&EditorObject, &Transform)>,
+    query_nodes: Query<(&Name, &LevelNode, &Transform)>,
+) {
+    if save_events.iter().last().is_none() {
+        return;
+    }
+    let Some(path) = overlay_path(&level.0) else {
+        editor.status = Some("Generated levels can't have an overlay".to_string());
+        return;
+    };
+
+    let overlay = LevelOverlay {
+        objects: query_objects
+            .iter()
+            .map(|(object, transform)| OverlayObject {
+                kind: object.kind,
+                translation: transform.translation.to_array(),
+                rotation: transform.rotation.to_array(),
+            })
+            .collect(),
+        nodes: query_nodes
+            .iter()
+            .filter(|(_, node, transform)| {
+                transform.translation != node.original.translation
+                    || transform.rotation != node.original.rotation
+            })
+            .map(|(name, _, transform)| OverlayNode {
+                name: name.to_string(),
+                translation: transform.translation.to_array(),
+                rotation: transform.rotation.to_array(),
+            })
+            .collect(),
+        deleted: overlay_state.deleted.clone(),
+    };
+    editor.status = Some(match write_overlay(&path, &overlay) {
+        Ok(()) => format!(
+            "Saved {} objects and {} changed nodes to {path}",
+            overlay.objects.len(),
+            overlay.nodes.len() + overlay.deleted.len()
+        ),
+        Err(error) => {
+            warn!("Could not save {path}: {error}");
+            format!("Could not save {path}: {error}")
+        }
+    });
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_overlay(path: &str, overlay: &LevelOverlay) -> Result<(), String> {
+    let text = ron::ser::to_string_pretty(overlay, ron::ser::PrettyConfig::default())
+        .map_err(|error| error.to_string())?;
+    let path = bevy::asset::FileAssetIo::get_base_path()
+        .join("assets")
+        .join(path);
+    std::fs::write(path, text).map_err(|error| error.to_string())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_overlay(_path: &str, _overlay: &LevelOverlay) -> Result<(), String> {
+    Err("there are no files to write to on the web".to_string())
+}
@@ -0,0 +1,512 @@
+use bevy::{app::AppExit, gltf::GltfExtras, prelude::*, scene::SceneInstance, window::PresentMode};
+use serde::Deserialize;
+use std::fs;
+
+use crate::{
+    camera::CameraRig,
+    ui::{CameraSettings, CameraType},
+    CurrentLevel, LevelRoot, DUNGEON_LEVEL,
+};
+
+pub const FLYTHROUGH_CAMERA: CameraType = CameraType::Custom("Flythrough");
+// Empties named like "camera_path.003" are waypoints, visited in the order of their names
+const WAYPOINT_NAME_PREFIX: &str = "camera_path";
+// Samples per segment when measuring the length of the path
+const LENGTH_SAMPLES: usize = 16;
+
+// Placed by code until the dungeon has markers of its own, as (position, looking at)
+const DUNGEON_PATH: [(Vec3, Vec3); 7] = [
+    (Vec3::new(-5.0, 1.6, -6.0), Vec3::new(-5.0, 1.2, 0.0)),
+    (Vec3::new(-5.0, 1.6, -1.0), Vec3::new(-1.5, 1.2, 4.0)),
+    (Vec3::new(-2.0, 1.8, 3.0), Vec3::new(0.0, 1.2, 8.0)),
+    (Vec3::new(0.0, 1.6, 7.0), Vec3::new(0.0, 1.2, 16.0)),
+    (Vec3::new(0.0, 1.8, 14.0), Vec3::new(0.0, 0.5, 18.0)),
+    (Vec3::new(0.0, 6.0, 18.0), Vec3::new(-1.0, 0.0, 8.0)),
+    (Vec3::new(-1.0, 20.0, 14.0), Vec3::new(-1.0, 0.0, 6.0)),
+];
+
+const BENCHMARK_REPORT_PATH: &str = "benchmark_report.txt";
+// Time for the level to finish loading its textures and shaders before measuring
+const BENCHMARK_WARMUP: f32 = 2.0;
+
+#[derive(Clone, Copy)]
+pub struct Waypoint {
+    pub translation: Vec3,
+    pub rotation: Quat,
+}
+
+impl From<&GlobalTransform> for Waypoint {
+    fn from(transform: &GlobalTransform) -> Self {
+        let (_, rotation, translation) = transform.to_scale_rotation_translation();
+        Waypoint {
+            translation,
+            rotation,
+        }
+    }
+}
+
+// A Catmull-Rom spline through the waypoints, the camera turns from one waypoint's rotation to
+// the next
+#[derive(Resource)]
+pub struct CameraPath {
+    pub waypoints: Vec<Waypoint>,
+    // Whether the waypoints come from the level rather than the built-in path
+    from_level: bool,
+}
+
+impl Default for CameraPath {
+    fn default() -> Self {
+        CameraPath::built_in(DUNGEON_LEVEL)
+    }
+}
+
+impl CameraPath {
+    // Only the hand-made dungeon has a path placed by code, other levels need their own markers
+    fn built_in(level: &str) -> Self {
+        let waypoints = match level {
+            DUNGEON_LEVEL => DUNGEON_PATH
+                .iter()
+                .map(|(translation, target)| Waypoint {
+                    translation: *translation,
+                    rotation: Transform::from_translation(*translation)
+                        .looking_at(*target, Vec3::Y)
+                        .rotation,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        CameraPath {
+            waypoints,
+            from_level: false,
+        }
+    }
+
+    fn segment_count(&self) -> usize {
+        self.waypoints.len().saturating_sub(1)
+    }
+
+    fn position(&self, segment: usize, t: f32) -> Vec3 {
+        let last = self.waypoints.len() - 1;
+        let point = |i: isize| self.waypoints[i.clamp(0, last as isize) as usize].translation;
+        let i = segment as isize;
+        catmull_rom(point(i - 1), point(i), point(i + 1), point(i + 2), t)
+    }
+
+    fn segment_length(&self, segment: usize) -> f32 {
+        let mut length = 0.0;
+        let mut previous = self.position(segment, 0.0);
+        for i in 1..=LENGTH_SAMPLES {
+            let position = self.position(segment, i as f32 / LENGTH_SAMPLES as f32);
+            length += previous.distance(position);
+            previous = position;
+        }
+        length
+    }
+
+    pub fn length(&self) -> f32 {
+        (0..self.segment_count())
+            .map(|segment| self.segment_length(segment))
+            .sum()
+    }
+
+    // Where the camera is after travelling `distance` along the path
+    pub fn sample(&self, distance: f32) -> Option<Transform> {
+        let first = self.waypoints.first()?;
+        let mut remaining = distance.max(0.0);
+        for segment in 0..self.segment_count() {
+            let length = self.segment_length(segment);
+            if remaining <= length || segment + 1 == self.segment_count() {
+                let t = if length > 0.0 {
+                    (remaining / length).min(1.0)
+                } else {
+                    1.0
+                };
+                let (from, to) = (self.waypoints[segment], self.waypoints[segment + 1]);
+                let eased = t * t * (3.0 - 2.0 * t);
+                return Some(
+                    Transform::from_translation(self.position(segment, t))
+                        .with_rotation(from.rotation.slerp(to.rotation, eased)),
+                );
+            }
+            remaining -= length;
+        }
+        Some(Transform::from_translation(first.translation).with_rotation(first.rotation))
+    }
+}
+
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * (2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+#[derive(Resource)]
+pub struct FlythroughSettings {
+    // Metres per second along the path
+    pub speed: f32,
+    pub looping: bool,
+}
+
+impl Default for FlythroughSettings {
+    fn default() -> Self {
+        FlythroughSettings {
+            speed: 3.0,
+            looping: false,
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Flythrough {
+    playing: bool,
+    distance: f32,
+    // The camera to go back to when the flythrough is over
+    previous_camera: Option<CameraType>,
+    // Flythroughs that have reached the end of the path
+    completed: u32,
+}
+
+impl Flythrough {
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+        self.distance = 0.0;
+    }
+
+    pub fn stop(&mut self) {
+        self.playing = false;
+    }
+}
+
+// Marks a node of the level as a waypoint of the camera path
+#[derive(Component)]
+struct CameraWaypoint {
+    order: f32,
+}
+
+// Custom properties exported from Blender, e.g. {"camera_path": 3}
+#[derive(Deserialize)]
+struct CameraWaypointExtras {
+    camera_path: Option<f32>,
+}
+
+// Started with --benchmark, plays the flythrough once and writes how smoothly it ran
+#[derive(Resource)]
+struct Benchmark {
+    warmup: f32,
+    started: bool,
+    frame_times: Vec<f32>,
+    reported: bool,
+}
+
+pub struct FlythroughPlugin;
+
+impl Plugin for FlythroughPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraPath>()
+            .init_resource::<FlythroughSettings>()
+            .init_resource::<Flythrough>()
+            .add_startup_system(spawn_flythrough_camera)
+            .add_system(reset_camera_path)
+            .add_system(attach_camera_waypoints)
+            .add_system(
+                collect_camera_waypoints
+                    .after(attach_camera_waypoints)
+                    .after(reset_camera_path),
+            )
+            .add_system(play_flythrough.after(collect_camera_waypoints));
+
+        if std::env::args().any(|arg| arg == "--benchmark") {
+            app.insert_resource(Benchmark {
+                warmup: BENCHMARK_WARMUP,
+                started: false,
+                frame_times: Vec::new(),
+                reported: false,
+            })
+            .add_startup_system(disable_vsync)
+            .add_system(run_benchmark.after(play_flythrough))
+            .add_system_to_stage(CoreStage::Last, report_benchmark_on_exit);
+        }
+    }
+}
+
+fn spawn_flythrough_camera(
+    mut commands: Commands,
+    cam_settings: Res<CameraSettings>,
+    path: Res<CameraPath>,
+) {
+    commands.spawn((
+        Camera3dBundle {
+            transform: path.sample(0.0).unwrap_or_default(),
+            camera: Camera {
+                is_active: false,
+                hdr: cam_settings.bloom_enabled,
+                ..default()
+            },
+            tonemapping: cam_settings.tonemapping.clone(),
+            ..default()
+        },
+        cam_settings.bloom.clone(),
+        CameraRig {
+            c_type: FLYTHROUGH_CAMERA,
+            player: None,
+        },
+    ));
+}
+
+fn attach_camera_waypoints(
+    mut commands: Commands,
+    query_nodes: Query<(Entity, &Name, Option<&GltfExtras>), Added<Name>>,
+    query_meshes: Query<&Handle<Mesh>>,
+) {
+    for (entity, name, extras) in query_nodes.iter() {
+        if query_meshes.contains(entity) {
+            continue;
+        }
+
+        let extras: Option<CameraWaypointExtras> =
+            extras.and_then(|extras| serde_json::from_str(&extras.value).ok());
+        let order = match extras.and_then(|extras| extras.camera_path) {
+            Some(order) => order,
+            None => {
+                // "camera_path.003" -> 3
+                let name = name.to_ascii_lowercase();
+                let Some(suffix) = name.strip_prefix(WAYPOINT_NAME_PREFIX) else {
+                    continue;
+                };
+                suffix.trim_start_matches(['.', '_']).parse().unwrap_or(0.0)
+            }
+        };
+        commands.entity(entity).insert(CameraWaypoint { order });
+    }
+}
+
+// The waypoints of the previous level are gone with it
+fn reset_camera_path(
+    level: Res<CurrentLevel>,
+    mut path: ResMut<CameraPath>,
+    query_new_roots: Query<(), Added<LevelRoot>>,
+) {
+    if !query_new_roots.is_empty() {
+        *path = CameraPath::built_in(&level.0);
+    }
+}
+
+// The global transforms of new waypoints are only known a frame after they are attached
+fn collect_camera_waypoints(
+    mut path: ResMut<CameraPath>,
+    query_new_waypoints: Query<(), Added<CameraWaypoint>>,
+    query_waypoints: Query<(&CameraWaypoint, &GlobalTransform)>,
+) {
+    if query_new_waypoints.is_empty() {
+        return;
+    }
+
+    let mut waypoints: Vec<(f32, Waypoint)> = query_waypoints
+        .iter()
+        .map(|(waypoint, transform)| (waypoint.order, Waypoint::from(transform)))
+        .collect();
+    waypoints.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+    path.waypoints = waypoints
+        .into_iter()
+        .map(|(_, waypoint)| waypoint)
+        .collect();
+    if !path.from_level {
+        info!("Using the camera path of the level");
+        path.from_level = true;
+    }
+}
+
+fn play_flythrough(
+    time: Res<Time>,
+    path: Res<CameraPath>,
+    settings: Res<FlythroughSettings>,
+    mut flythrough: ResMut<Flythrough>,
+    mut cam_settings: ResMut<CameraSettings>,
+    mut query_cams: Query<(&CameraRig, &mut Transform)>,
+) {
+    if !flythrough.playing {
+        if let Some(previous_camera) = flythrough.previous_camera.take() {
+            cam_settings.c_type = previous_camera;
+        }
+        return;
+    }
+    if path.segment_count() == 0 {
+        flythrough.playing = false;
+        return;
+    }
+
+    if flythrough.previous_camera.is_none() {
+        flythrough.previous_camera = Some(cam_settings.c_type);
+        cam_settings.c_type = FLYTHROUGH_CAMERA;
+    }
+
+    let length = path.length();
+    flythrough.distance += settings.speed * time.delta_seconds();
+    if flythrough.distance >= length {
+        flythrough.completed += 1;
+        if settings.looping {
+            flythrough.distance %= length.max(f32::EPSILON);
+        } else {
+            flythrough.distance = length;
+            flythrough.playing = false;
+        }
+    }
+
+    let Some(sample) = path.sample(flythrough.distance) else {
+        return;
+    };
+    for (rig, mut transform) in query_cams.iter_mut() {
+        if rig.c_type == FLYTHROUGH_CAMERA {
+            *transform = sample;
+        }
+    }
+}
+
+// Frame times are what is being measured, they shouldn't be capped by the display
+fn disable_vsync(mut windows: ResMut<Windows>) {
+    if let Some(window) = windows.get_primary_mut() {
+        window.set_present_mode(PresentMode::AutoNoVsync);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_benchmark(
+    time: Res<Time>,
+    scene_spawner: Res<SceneSpawner>,
+    mut benchmark: ResMut<Benchmark>,
+    mut flythrough: ResMut<Flythrough>,
+    mut settings: ResMut<FlythroughSettings>,
+    level: Res<CurrentLevel>,
+    path: Res<CameraPath>,
+    windows: Res<Windows>,
+    msaa: Res<Msaa>,
+    query_levels: Query<Option<&SceneInstance>, With<LevelRoot>>,
+    mut exit_events: EventWriter<AppExit>,
+) {
+    if !benchmark.started {
+        let level_ready = query_levels.iter().all(|instance| {
+            instance.is_some_and(|instance| scene_spawner.instance_is_ready(**instance))
+        });
+        if !level_ready {
+            return;
+        }
+        benchmark.warmup -= time.delta_seconds();
+        if benchmark.warmup > 0.0 {
+            return;
+        }
+
+        if path.segment_count() == 0 {
+            warn!("{} has no camera path to fly through", level.0);
+            exit_events.send(AppExit);
+            return;
+        }
+        info!("Benchmark started");
+        benchmark.started = true;
+        settings.looping = false;
+        flythrough.completed = 0;
+        flythrough.play();
+        return;
+    }
+
+    benchmark.frame_times.push(time.delta_seconds());
+    if flythrough.completed > 0 && !benchmark.reported {
+        write_benchmark_report(&mut benchmark, &level, &path, &settings, &windows, &msaa);
+        exit_events.send(AppExit);
+    }
+}
+
+// Closing the window halfway through still reports what has been measured
+fn report_benchmark_on_exit(
+    exit_events: EventReader<AppExit>,
+    mut benchmark: ResMut<Benchmark>,
+    level: Res<CurrentLevel>,
+    path: Res<CameraPath>,
+    settings: Res<FlythroughSettings>,
+    windows: Res<Windows>,
+    msaa: Res<Msaa>,
+) {
+    if !exit_events.is_empty() && !benchmark.reported && benchmark.started {
+        write_benchmark_report(&mut benchmark, &level, &path, &settings, &windows, &msaa);
+    }
+}
+
+fn write_benchmark_report(
+    benchmark: &mut Benchmark,
+    level: &CurrentLevel,
+    path: &CameraPath,
+    settings: &FlythroughSettings,
+    windows: &Windows,
+    msaa: &Msaa,
+) {
+    benchmark.reported = true;
+
+    let mut frame_times: Vec<f32> = benchmark
+        .frame_times
+        .iter()
+        .map(|frame_time| frame_time * 1000.0)
+        .collect();
+    if frame_times.is_empty() {
+        warn!("The benchmark ended before any frame was measured");
+        return;
+    }
+    frame_times.sort_by(|a, b| a.total_cmp(b));
+
+    let frames = frame_times.len();
+    let duration: f32 = frame_times.iter().sum::<f32>() / 1000.0;
+    let mean = frame_times.iter().sum::<f32>() / frames as f32;
+    let percentile = |p: f32| frame_times[((frames - 1) as f32 * p).round() as usize];
+    // Average of the slowest 1% of the frames
+    let slowest = &frame_times[frames - (frames / 100).max(1)..];
+    let low_1_percent = 1000.0 / (slowest.iter().sum::<f32>() / slowest.len() as f32);
+    let resolution = windows
+        .get_primary()
+        .map_or("unknown".to_string(), |window| {
+            format!("{}x{}", window.physical_width(), window.physical_height())
+        });
+
+    let report = format!(
+        "Benchmark report\n\
+         \n\
+         Level: {}\n\
+         Path: {} waypoints, {:.1} m at {:.1} m/s\n\
+         Resolution: {resolution}\n\
+         MSAA samples: {}\n\
+         \n\
+         Frames: {frames}\n\
+         Duration: {duration:.2} s\n\
+         Average FPS: {:.1}\n\
+         1% low FPS: {low_1_percent:.1}\n\
+         \n\
+         Frame time (ms)\n\
+         Mean: {mean:.2}\n\
+         Min: {:.2}\n\
+         Median: {:.2}\n\
+         95th percentile: {:.2}\n\
+         99th percentile: {:.2}\n\
+         Max: {:.2}\n",
+        level.0,
+        path.waypoints.len(),
+        path.length(),
+        settings.speed,
+        msaa.samples,
+        frames as f32 / duration.max(f32::EPSILON),
+        frame_times[0],
+        percentile(0.5),
+        percentile(0.95),
+        percentile(0.99),
+        frame_times[frames - 1],
+    );
+
+    info!("\n{report}");
+    match fs::write(BENCHMARK_REPORT_PATH, &report) {
+        Ok(()) => info!("Benchmark report written to {BENCHMARK_REPORT_PATH}"),
+        Err(error) => error!("Could not write {BENCHMARK_REPORT_PATH}: {error}"),
+    }
+}
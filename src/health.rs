@@ -0,0 +1,254 @@
+use bevy::{gltf::GltfExtras, prelude::*};
+use serde::Deserialize;
+
+use crate::{
+    camera_effects::CameraShake,
+    player::{HeadState, Player},
+    triggers::{TriggerEntered, TriggerOccupants},
+};
+
+const PLAYER_MAX_HEALTH: f32 = 100.0;
+// Camera shake trauma for every point of damage taken
+const TRAUMA_PER_DAMAGE: f32 = 0.03;
+// Anything below this altitude has fallen out of the level
+const KILL_PLANE_Y: f32 = -20.0;
+const FADE_OUT_DURATION: f32 = 1.0;
+const FADE_IN_DURATION: f32 = 0.5;
+// Empties named like this mark where the player respawns before reaching a checkpoint
+const SPAWN_POINT_NAME_PREFIX: &str = "spawn";
+
+#[derive(Component)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Health {
+            current: PLAYER_MAX_HEALTH,
+            max: PLAYER_MAX_HEALTH,
+        }
+    }
+}
+
+// Hurts whoever is inside the trigger volume it is attached to
+#[derive(Component)]
+pub struct DamageZone {
+    pub damage_per_second: f32,
+}
+
+// Walking into the trigger volume moves the respawn point there
+#[derive(Component)]
+pub struct Checkpoint;
+
+// Marks the center of the player's body when (re)spawning
+#[derive(Component)]
+pub struct SpawnPoint;
+
+// Added to the player while fading out and back in after dying
+#[derive(Component)]
+pub struct Dead {
+    elapsed: f32,
+    respawned: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RespawnCause {
+    Died,
+    LevelStart,
+    Loaded,
+}
+
+// Sent when a player has been moved back to the respawn point, to the start of a new level or to
+// where a loaded game left them
+pub struct Respawned {
+    pub player: Entity,
+    pub cause: RespawnCause,
+}
+
+#[derive(Resource, Default)]
+pub struct RespawnPoint {
+    pub transform: Option<Transform>,
+    // Checkpoints win over the spawn points of the level
+    pub from_checkpoint: bool,
+}
+
+// Opacity of the black overlay drawn over the whole screen
+#[derive(Resource, Default)]
+pub struct ScreenFade {
+    pub alpha: f32,
+}
+
+// Custom properties exported from Blender, e.g. {"spawn_point": true}
+#[derive(Deserialize)]
+struct SpawnPointExtras {
+    spawn_point: Option<bool>,
+}
+
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Respawned>()
+            .insert_resource(RespawnPoint::default())
+            .insert_resource(ScreenFade::default())
+            .add_system(attach_spawn_points)
+            .add_system(update_respawn_point.after(attach_spawn_points))
+            .add_system(damage_zone_system.before(death_system))
+            .add_system(kill_plane_system.before(death_system))
+            .add_system(death_system);
+    }
+}
+
+fn attach_spawn_points(
+    mut commands: Commands,
+    query_nodes: Query<(Entity, &Name, Option<&GltfExtras>), Added<Name>>,
+    query_meshes: Query<&Handle<Mesh>>,
+) {
+    for (entity, name, extras) in query_nodes.iter() {
+        if query_meshes.contains(entity) {
+            continue;
+        }
+
+        let extras: Option<SpawnPointExtras> =
+            extras.and_then(|extras| serde_json::from_str(&extras.value).ok());
+        let is_spawn_point = match extras.and_then(|extras| extras.spawn_point) {
+            Some(spawn_point) => spawn_point,
+            None => name
+                .to_ascii_lowercase()
+                .starts_with(SPAWN_POINT_NAME_PREFIX),
+        };
+        if is_spawn_point {
+            commands.entity(entity).insert(SpawnPoint);
+        }
+    }
+}
+
+fn update_respawn_point(
+    mut respawn_point: ResMut<RespawnPoint>,
+    mut trigger_events: EventReader<TriggerEntered>,
+    query_new_players: Query<&Transform, Added<Player>>,
+    query_spawn_points: Query<&GlobalTransform, Added<SpawnPoint>>,
+    query_checkpoints: Query<&GlobalTransform, With<Checkpoint>>,
+    query_players: Query<(), With<Player>>,
+) {
+    // Where the player starts is the fallback until the level provides a spawn point
+    if respawn_point.transform.is_none() {
+        respawn_point.transform = query_new_players.iter().next().copied();
+    }
+
+    if !respawn_point.from_checkpoint {
+        if let Some(transform) = query_spawn_points.iter().next() {
+            respawn_point.transform = Some(upright(transform));
+        }
+    }
+
+    for event in trigger_events.iter() {
+        if !query_players.contains(event.entity) {
+            continue;
+        }
+        if let Ok(transform) = query_checkpoints.get(event.trigger) {
+            respawn_point.transform = Some(upright(transform));
+            respawn_point.from_checkpoint = true;
+        }
+    }
+}
+
+fn damage_zone_system(
+    time: Res<Time>,
+    mut shake: ResMut<CameraShake>,
+    query_zones: Query<(&DamageZone, &TriggerOccupants)>,
+    mut query_health: Query<&mut Health, Without<Dead>>,
+    query_players: Query<(), With<Player>>,
+) {
+    for (zone, occupants) in query_zones.iter() {
+        for entity in occupants.0.iter() {
+            if let Ok(mut health) = query_health.get_mut(*entity) {
+                let damage = zone.damage_per_second * time.delta_seconds();
+                health.current -= damage;
+                if query_players.contains(*entity) {
+                    shake.add_trauma(damage * TRAUMA_PER_DAMAGE);
+                }
+            }
+        }
+    }
+}
+
+fn kill_plane_system(mut query_health: Query<(&GlobalTransform, &mut Health), Without<Dead>>) {
+    for (transform, mut health) in query_health.iter_mut() {
+        if transform.translation().y < KILL_PLANE_Y {
+            health.current = 0.0;
+        }
+    }
+}
+
+fn death_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    respawn_point: Res<RespawnPoint>,
+    mut screen_fade: ResMut<ScreenFade>,
+    mut respawned_events: EventWriter<Respawned>,
+    mut query_players: Query<
+        (Entity, &mut Health, &mut Transform, Option<&mut Dead>),
+        With<Player>,
+    >,
+    mut query_heads: Query<(&Parent, &mut HeadState, &mut Transform), Without<Player>>,
+) {
+    for (entity, mut health, mut transform, dead) in query_players.iter_mut() {
+        let Some(mut dead) = dead else {
+            if health.current <= 0.0 {
+                info!("Player died");
+                commands.entity(entity).insert(Dead {
+                    elapsed: 0.0,
+                    respawned: false,
+                });
+            }
+            continue;
+        };
+
+        dead.elapsed += time.delta_seconds();
+        if dead.elapsed < FADE_OUT_DURATION {
+            screen_fade.alpha = dead.elapsed / FADE_OUT_DURATION;
+            continue;
+        }
+
+        // Move the player while the screen is completely black
+        if !dead.respawned {
+            if let Some(respawn_transform) = respawn_point.transform {
+                *transform = respawn_transform;
+            }
+            // Looking straight ahead, the way the respawn point faces
+            for (parent, mut head_state, mut head_transform) in query_heads.iter_mut() {
+                if parent.get() == entity {
+                    head_state.pitch = 0.0;
+                    head_state.yaw = 0.0;
+                    head_transform.rotation = Quat::IDENTITY;
+                }
+            }
+            health.current = health.max;
+            dead.respawned = true;
+            respawned_events.send(Respawned {
+                player: entity,
+                cause: RespawnCause::Died,
+            });
+        }
+
+        screen_fade.alpha = 1.0 - (dead.elapsed - FADE_OUT_DURATION) / FADE_IN_DURATION;
+        if screen_fade.alpha <= 0.0 {
+            screen_fade.alpha = 0.0;
+            commands.entity(entity).remove::<Dead>();
+        }
+    }
+}
+
+// Only keep the yaw so that the player doesn't respawn tilted
+fn upright(transform: &GlobalTransform) -> Transform {
+    let (_, rotation, translation) = transform.to_scale_rotation_translation();
+    let forward = rotation * Vec3::NEG_Z;
+    let transform = Transform::from_translation(translation);
+    if forward.x == 0.0 && forward.z == 0.0 {
+        return transform;
+    }
+    transform.looking_at(translation + Vec3::new(forward.x, 0.0, forward.z), Vec3::Y)
+}
@@ -0,0 +1,426 @@
+use bevy::{gltf::GltfExtras, math::Vec3A, prelude::*, render::primitives::Aabb};
+use serde::Deserialize;
+use std::f32::consts::FRAC_PI_2;
+
+use crate::{
+    camera::{CameraRig, FirstPersonCamera},
+    items::{Inventory, ItemKind},
+    player::{HeadState, PrimaryPlayer},
+    ui::{CameraSettings, CameraType},
+    AppState,
+};
+
+const KEY_INTERACT: KeyCode = KeyCode::F;
+const INTERACTION_DISTANCE: f32 = 3.0;
+// Torches and their point lights are separate nodes in the dungeon
+const LIGHT_SWITCH_RADIUS: f32 = 1.0;
+const DOOR_OPEN_ANGLE: f32 = FRAC_PI_2;
+const DOOR_SPEED: f32 = 2.0;
+const LEVER_ANGLE: f32 = 1.2;
+const LEVER_SPEED: f32 = 4.0;
+const COLOR_HIGHLIGHT: Color = Color::rgb_linear(0.25, 0.2, 0.05);
+
+// Nodes named like one of these, with or without Blender's ".001" suffix, become interactable.
+// "pickup_torch.001" or "torch_light.001" are something else
+const NAME_RULES: [(&str, Interactable); 4] = [
+    ("door", Interactable::Door),
+    ("lever", Interactable::Lever),
+    ("switch", Interactable::LightSwitch),
+    ("torch", Interactable::LightSwitch),
+];
+
+#[derive(Component, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Interactable {
+    Door,
+    Lever,
+    LightSwitch,
+}
+
+impl Interactable {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "door" => Some(Interactable::Door),
+            "lever" => Some(Interactable::Lever),
+            "switch" | "light_switch" => Some(Interactable::LightSwitch),
+            _ => None,
+        }
+    }
+
+    pub fn verb(&self) -> &'static str {
+        match self {
+            Interactable::Door => "Open/close",
+            Interactable::Lever => "Pull",
+            Interactable::LightSwitch => "Toggle light",
+        }
+    }
+}
+
+// Name of the node a lever or switch acts on
+#[derive(Component)]
+pub struct InteractionTarget(pub String);
+
+#[derive(Component, Default)]
+pub struct Door {
+    pub open: bool,
+    // Opening a locked door uses up a key
+    pub locked: bool,
+    angle: f32,
+    closed_rotation: Option<Quat>,
+}
+
+#[derive(Component, Default)]
+pub struct Lever {
+    pub on: bool,
+    angle: f32,
+    rest_rotation: Option<Quat>,
+}
+
+// Mesh whose material was swapped for a highlighted copy
+#[derive(Component)]
+struct Highlighted {
+    original: Handle<StandardMaterial>,
+}
+
+#[derive(Resource, Default)]
+pub struct InteractionFocus {
+    pub entity: Option<Entity>,
+}
+
+pub struct InteractionEvent {
+    pub entity: Entity,
+    pub player: Entity,
+}
+
+// Custom properties exported from Blender, e.g. {"interactable": "lever", "target": "door.001"}
+#[derive(Deserialize)]
+struct InteractableExtras {
+    interactable: Option<String>,
+    target: Option<String>,
+    locked: Option<bool>,
+}
+
+pub struct InteractionPlugin;
+
+impl Plugin for InteractionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InteractionFocus>()
+            .add_event::<InteractionEvent>()
+            .add_system(attach_interactables)
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(update_focus.before(interact_system))
+                    .with_system(interact_system),
+            )
+            .add_system(handle_interactions.after(interact_system))
+            .add_system(animate_doors.after(handle_interactions))
+            .add_system(animate_levers.after(handle_interactions));
+    }
+}
+
+fn attach_interactables(
+    mut commands: Commands,
+    query_nodes: Query<(Entity, &Name, Option<&GltfExtras>), Added<Name>>,
+    query_meshes: Query<&Handle<Mesh>>,
+) {
+    for (entity, name, extras) in query_nodes.iter() {
+        // Mesh primitives are named after the mesh, only look at the nodes
+        if query_meshes.contains(entity) {
+            continue;
+        }
+
+        let extras: Option<InteractableExtras> =
+            extras.and_then(|extras| serde_json::from_str(&extras.value).ok());
+
+        let interactable = match &extras {
+            Some(InteractableExtras {
+                interactable: Some(kind),
+                ..
+            }) => Interactable::from_name(kind),
+            _ => kind_from_name_rules(name.as_str()),
+        };
+        let Some(interactable) = interactable else {
+            continue;
+        };
+
+        let mut entity_commands = commands.entity(entity);
+        entity_commands.insert(interactable);
+        match interactable {
+            Interactable::Door => {
+                let locked = extras.as_ref().and_then(|extras| extras.locked);
+                entity_commands.insert(Door {
+                    locked: locked.unwrap_or(false),
+                    ..default()
+                });
+            }
+            Interactable::Lever => {
+                entity_commands.insert(Lever::default());
+            }
+            Interactable::LightSwitch => (),
+        }
+        if let Some(target) = extras.and_then(|extras| extras.target) {
+            entity_commands.insert(InteractionTarget(target));
+        }
+    }
+}
+
+fn kind_from_name_rules(name: &str) -> Option<Interactable> {
+    let name = name.to_ascii_lowercase();
+    let stem = name.split('.').next().unwrap_or_default();
+    NAME_RULES
+        .iter()
+        .find(|(pattern, _)| stem == *pattern)
+        .map(|(_, kind)| *kind)
+}
+
+// Looks through the first person camera of the primary player, or from their head with any other
+// camera
+#[allow(clippy::too_many_arguments)]
+fn update_focus(
+    mut commands: Commands,
+    mut focus: ResMut<InteractionFocus>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    cam_settings: Res<CameraSettings>,
+    query_primary: Query<Entity, With<PrimaryPlayer>>,
+    query_fps_cams: Query<(&GlobalTransform, &CameraRig), With<FirstPersonCamera>>,
+    query_heads: Query<(&GlobalTransform, &Parent), With<HeadState>>,
+    query_meshes: Query<(Entity, &Aabb, &GlobalTransform), With<Handle<Mesh>>>,
+    query_parents: Query<&Parent>,
+    query_interactables: Query<&Interactable>,
+    query_children: Query<&Children>,
+    mut query_highlight: Query<(&mut Handle<StandardMaterial>, Option<&Highlighted>)>,
+) {
+    let Ok(primary) = query_primary.get_single() else {
+        return;
+    };
+    let eye = match cam_settings.c_type {
+        CameraType::FirstPerson => query_fps_cams
+            .iter()
+            .find(|(_, rig)| rig.player == Some(primary))
+            .map(|(transform, _)| transform),
+        _ => query_heads
+            .iter()
+            .find(|(_, parent)| parent.get() == primary)
+            .map(|(transform, _)| transform),
+    };
+    let Some(eye) = eye else {
+        return;
+    };
+    let origin = eye.translation();
+    let direction = eye.forward();
+
+    let hit = query_meshes
+        .iter()
+        .filter_map(|(entity, aabb, transform)| {
+            ray_intersects_aabb(origin, direction, aabb, transform)
+                .filter(|distance| *distance <= INTERACTION_DISTANCE)
+                .map(|distance| (entity, distance))
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b));
+
+    // The mesh is usually a child of the interactable node
+    let target = hit.and_then(|(mut entity, _)| loop {
+        if query_interactables.contains(entity) {
+            break Some(entity);
+        }
+        entity = query_parents.get(entity).ok()?.get();
+    });
+
+    if target == focus.entity {
+        return;
+    }
+
+    if let Some(previous) = focus.entity {
+        for entity in descendants(previous, &query_children) {
+            if let Ok((mut material, Some(highlighted))) = query_highlight.get_mut(entity) {
+                *material = highlighted.original.clone();
+                commands.entity(entity).remove::<Highlighted>();
+            }
+        }
+    }
+    if let Some(target) = target {
+        for entity in descendants(target, &query_children) {
+            if let Ok((mut material, None)) = query_highlight.get_mut(entity) {
+                let Some(mut highlight) = materials.get(&material).cloned() else {
+                    continue;
+                };
+                highlight.emissive += COLOR_HIGHLIGHT;
+                let original = std::mem::replace(&mut *material, materials.add(highlight));
+                commands.entity(entity).insert(Highlighted { original });
+            }
+        }
+    }
+    focus.entity = target;
+}
+
+fn interact_system(
+    key: Res<Input<KeyCode>>,
+    focus: Res<InteractionFocus>,
+    query_primary: Query<Entity, With<PrimaryPlayer>>,
+    mut interaction_events: EventWriter<InteractionEvent>,
+) {
+    if !key.just_pressed(KEY_INTERACT) {
+        return;
+    }
+    if let (Some(entity), Ok(player)) = (focus.entity, query_primary.get_single()) {
+        interaction_events.send(InteractionEvent { entity, player });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_interactions(
+    mut interaction_events: EventReader<InteractionEvent>,
+    query_interactables: Query<(&Interactable, &GlobalTransform, Option<&InteractionTarget>)>,
+    query_names: Query<(Entity, &Name)>,
+    query_children: Query<&Children>,
+    mut query_doors: Query<&mut Door>,
+    mut query_levers: Query<&mut Lever>,
+    mut query_lights: Query<(&mut Visibility, &GlobalTransform), With<PointLight>>,
+    mut query_inventories: Query<&mut Inventory>,
+) {
+    for event in interaction_events.iter() {
+        let Ok((interactable, transform, target)) = query_interactables.get(event.entity) else {
+            continue;
+        };
+
+        if let Ok(mut lever) = query_levers.get_mut(event.entity) {
+            lever.on = !lever.on;
+        }
+
+        // Levers and switches act on their target, anything else acts on itself
+        let targets: Vec<Entity> = match target {
+            Some(InteractionTarget(target)) => query_names
+                .iter()
+                .filter(|(_, name)| name.as_str() == target)
+                .map(|(entity, _)| entity)
+                .collect(),
+            None => vec![event.entity],
+        };
+
+        for target in targets {
+            if let Ok(mut door) = query_doors.get_mut(target) {
+                if door.locked {
+                    let Ok(mut inventory) = query_inventories.get_mut(event.player) else {
+                        continue;
+                    };
+                    if !inventory.remove(ItemKind::Key, 1) {
+                        continue;
+                    }
+                    door.locked = false;
+                }
+                door.open = !door.open;
+                continue;
+            }
+
+            let lights: Vec<Entity> = descendants(target, &query_children)
+                .into_iter()
+                .filter(|entity| query_lights.contains(*entity))
+                .collect();
+            if !lights.is_empty() {
+                for entity in lights {
+                    let (mut visibility, _) = query_lights.get_mut(entity).unwrap();
+                    visibility.is_visible = !visibility.is_visible;
+                }
+            } else if *interactable == Interactable::LightSwitch {
+                let position = transform.translation();
+                let nearest = query_lights
+                    .iter_mut()
+                    .map(|(visibility, transform)| {
+                        (visibility, transform.translation().distance(position))
+                    })
+                    .filter(|(_, distance)| *distance <= LIGHT_SWITCH_RADIUS)
+                    .min_by(|(_, a), (_, b)| a.total_cmp(b));
+                if let Some((mut visibility, _)) = nearest {
+                    visibility.is_visible = !visibility.is_visible;
+                }
+            }
+        }
+    }
+}
+
+fn animate_doors(time: Res<Time>, mut query_doors: Query<(&mut Door, &mut Transform)>) {
+    for (mut door, mut transform) in query_doors.iter_mut() {
+        let closed_rotation = *door.closed_rotation.get_or_insert(transform.rotation);
+        let target = if door.open { DOOR_OPEN_ANGLE } else { 0.0 };
+        if door.angle == target {
+            continue;
+        }
+
+        door.angle = move_towards(door.angle, target, DOOR_SPEED * time.delta_seconds());
+        transform.rotation = closed_rotation * Quat::from_rotation_y(door.angle);
+    }
+}
+
+fn animate_levers(time: Res<Time>, mut query_levers: Query<(&mut Lever, &mut Transform)>) {
+    for (mut lever, mut transform) in query_levers.iter_mut() {
+        let rest_rotation = *lever.rest_rotation.get_or_insert(transform.rotation);
+        let target = if lever.on { LEVER_ANGLE } else { 0.0 };
+        if lever.angle == target {
+            continue;
+        }
+
+        lever.angle = move_towards(lever.angle, target, LEVER_SPEED * time.delta_seconds());
+        transform.rotation = rest_rotation * Quat::from_rotation_x(lever.angle);
+    }
+}
+
+#[inline]
+fn move_towards(current: f32, target: f32, max_step: f32) -> f32 {
+    if (target - current).abs() <= max_step {
+        target
+    } else {
+        current + max_step * (target - current).signum()
+    }
+}
+
+// Returns the entity itself and all of its descendants
+fn descendants(entity: Entity, query_children: &Query<&Children>) -> Vec<Entity> {
+    let mut entities = vec![entity];
+    let mut i = 0;
+    while i < entities.len() {
+        if let Ok(children) = query_children.get(entities[i]) {
+            entities.extend(children.iter());
+        }
+        i += 1;
+    }
+    entities
+}
+
+// Distance along the (normalized) ray to where it enters the box, if it does.
+// Rays starting inside a box are ignored, so that the player's own head doesn't block the view.
+pub fn ray_intersects_aabb(
+    origin: Vec3,
+    direction: Vec3,
+    aabb: &Aabb,
+    transform: &GlobalTransform,
+) -> Option<f32> {
+    let world_to_local = transform.affine().inverse();
+    let origin = Vec3A::from(world_to_local.transform_point3(origin));
+    let direction = Vec3A::from(world_to_local.transform_vector3(direction));
+
+    let t1 = (aabb.center - aabb.half_extents - origin) / direction;
+    let t2 = (aabb.center + aabb.half_extents - origin) / direction;
+    let t_enter = t1.min(t2).max_element();
+    let t_exit = t1.max(t2).min_element();
+
+    (t_enter <= t_exit && t_enter > 0.0).then_some(t_enter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_rules_match_the_stem() {
+        assert_eq!(
+            kind_from_name_rules("torch"),
+            Some(Interactable::LightSwitch)
+        );
+        assert_eq!(
+            kind_from_name_rules("Torch.003"),
+            Some(Interactable::LightSwitch)
+        );
+        assert_eq!(kind_from_name_rules("door.012"), Some(Interactable::Door));
+        assert_eq!(kind_from_name_rules("pickup_torch.001"), None);
+        assert_eq!(kind_from_name_rules("torch_light.001"), None);
+    }
+}
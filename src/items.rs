@@ -0,0 +1,254 @@
+use bevy::{gltf::GltfExtras, prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::TAU;
+
+use crate::{player::Player, CurrentLevel, LevelRoot, DUNGEON_LEVEL};
+
+const PICKUP_RADIUS: f32 = 1.0;
+const PICKUP_SPIN_SPEED: f32 = 1.5;
+// Empties named like "pickup_key" or "pickup_coin.003" become pickups
+const PICKUP_NAME_PREFIX: &str = "pickup_";
+
+const COLOR_KEY: &str = "D4AF37"; // Metallic Gold
+const COLOR_COIN: &str = "FFD700"; // Gold
+const COLOR_TORCH: &str = "8B5A2B"; // Wood
+
+// Placed by code until the dungeon has markers of its own
+const DUNGEON_PICKUPS: [(ItemKind, Vec3); 4] = [
+    (ItemKind::Coin, Vec3::new(-5.0, 0.5, -1.0)),
+    (ItemKind::Coin, Vec3::new(-1.5, 0.5, 4.0)),
+    (ItemKind::Torch, Vec3::new(0.0, 0.5, 6.0)),
+    (ItemKind::Key, Vec3::new(0.0, 0.5, 16.0)),
+];
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum ItemKind {
+    Key,
+    Coin,
+    Torch,
+}
+
+impl ItemKind {
+    pub const ALL: [ItemKind; 3] = [ItemKind::Key, ItemKind::Coin, ItemKind::Torch];
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "key" => Some(ItemKind::Key),
+            "coin" => Some(ItemKind::Coin),
+            "torch" => Some(ItemKind::Torch),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ItemKind::Key => "Key",
+            ItemKind::Coin => "Coin",
+            ItemKind::Torch => "Torch",
+        }
+    }
+}
+
+#[derive(Component, Clone, Copy)]
+pub struct Pickup {
+    pub kind: ItemKind,
+    pub amount: u32,
+}
+
+#[derive(Component, Default)]
+pub struct Inventory {
+    items: HashMap<ItemKind, u32>,
+}
+
+impl Inventory {
+    pub fn iter(&self) -> impl Iterator<Item = (ItemKind, u32)> + '_ {
+        self.items.iter().map(|(kind, count)| (*kind, *count))
+    }
+
+    pub fn count(&self, kind: ItemKind) -> u32 {
+        self.items.get(&kind).copied().unwrap_or(0)
+    }
+
+    pub fn add(&mut self, kind: ItemKind, amount: u32) {
+        *self.items.entry(kind).or_default() += amount;
+    }
+
+    // Returns false and leaves the inventory untouched if there aren't enough items
+    pub fn remove(&mut self, kind: ItemKind, amount: u32) -> bool {
+        match self.items.get_mut(&kind) {
+            Some(count) if *count >= amount => {
+                *count -= amount;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+// Custom properties exported from Blender, e.g. {"pickup": "coin", "amount": 5}
+#[derive(Deserialize)]
+struct PickupExtras {
+    pickup: Option<String>,
+    amount: Option<u32>,
+}
+
+#[derive(Resource)]
+struct PickupAssets {
+    meshes: HashMap<ItemKind, Handle<Mesh>>,
+    materials: HashMap<ItemKind, Handle<StandardMaterial>>,
+}
+
+pub struct ItemPlugin;
+
+impl Plugin for ItemPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(setup_pickups.after("main_setup"))
+            .add_system(spawn_dungeon_pickups)
+            .add_system(attach_pickups)
+            .add_system(spawn_pickup_visuals.after(attach_pickups))
+            .add_system(spin_pickups)
+            .add_system(collect_pickups);
+    }
+}
+
+fn setup_pickups(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let mut pickup_assets = PickupAssets {
+        meshes: HashMap::default(),
+        materials: HashMap::default(),
+    };
+    for kind in ItemKind::ALL {
+        let (mesh, color) = match kind {
+            ItemKind::Key => (Mesh::from(shape::Box::new(0.3, 0.08, 0.03)), COLOR_KEY),
+            ItemKind::Coin => (
+                Mesh::from(shape::Torus {
+                    radius: 0.1,
+                    ring_radius: 0.03,
+                    ..default()
+                }),
+                COLOR_COIN,
+            ),
+            ItemKind::Torch => (
+                Mesh::from(shape::Capsule {
+                    radius: 0.04,
+                    depth: 0.4,
+                    ..default()
+                }),
+                COLOR_TORCH,
+            ),
+        };
+        let material = StandardMaterial {
+            base_color: Color::hex(color).unwrap(),
+            metallic: if kind == ItemKind::Torch { 0.0 } else { 0.9 },
+            perceptual_roughness: 0.3,
+            ..default()
+        };
+        pickup_assets.meshes.insert(kind, meshes.add(mesh));
+        pickup_assets
+            .materials
+            .insert(kind, materials.add(material));
+    }
+    commands.insert_resource(pickup_assets);
+}
+
+// Part of the level, so that they go away with it
+fn spawn_dungeon_pickups(
+    mut commands: Commands,
+    level: Res<CurrentLevel>,
+    query_new_roots: Query<Entity, Added<LevelRoot>>,
+) {
+    if level.0 != DUNGEON_LEVEL {
+        return;
+    }
+    for root in query_new_roots.iter() {
+        commands.entity(root).with_children(|parent| {
+            for (kind, position) in DUNGEON_PICKUPS {
+                parent.spawn((
+                    SpatialBundle::from_transform(Transform::from_translation(position)),
+                    Pickup { kind, amount: 1 },
+                ));
+            }
+        });
+    }
+}
+
+fn attach_pickups(
+    mut commands: Commands,
+    query_nodes: Query<(Entity, &Name, Option<&GltfExtras>), Added<Name>>,
+    query_meshes: Query<&Handle<Mesh>>,
+) {
+    for (entity, name, extras) in query_nodes.iter() {
+        if query_meshes.contains(entity) {
+            continue;
+        }
+
+        let extras: Option<PickupExtras> =
+            extras.and_then(|extras| serde_json::from_str(&extras.value).ok());
+        let (kind, amount) = match extras {
+            Some(PickupExtras {
+                pickup: Some(kind),
+                amount,
+            }) => (ItemKind::from_name(&kind), amount),
+            _ => {
+                // "pickup_coin.003" -> "coin"
+                let name = name.to_ascii_lowercase();
+                let kind = name
+                    .strip_prefix(PICKUP_NAME_PREFIX)
+                    .and_then(|kind| kind.split('.').next())
+                    .and_then(ItemKind::from_name);
+                (kind, None)
+            }
+        };
+        let Some(kind) = kind else {
+            continue;
+        };
+
+        commands.entity(entity).insert(Pickup {
+            kind,
+            amount: amount.unwrap_or(1),
+        });
+    }
+}
+
+fn spawn_pickup_visuals(
+    mut commands: Commands,
+    pickup_assets: Res<PickupAssets>,
+    query_pickups: Query<(Entity, &Pickup), Added<Pickup>>,
+) {
+    for (entity, pickup) in query_pickups.iter() {
+        commands.entity(entity).with_children(|parent| {
+            parent.spawn(PbrBundle {
+                mesh: pickup_assets.meshes[&pickup.kind].clone(),
+                material: pickup_assets.materials[&pickup.kind].clone(),
+                ..default()
+            });
+        });
+    }
+}
+
+fn spin_pickups(time: Res<Time>, mut query_pickups: Query<&mut Transform, With<Pickup>>) {
+    let angle = (time.delta_seconds() * PICKUP_SPIN_SPEED) % TAU;
+    for mut transform in query_pickups.iter_mut() {
+        transform.rotate_y(angle);
+    }
+}
+
+fn collect_pickups(
+    mut commands: Commands,
+    query_pickups: Query<(Entity, &Pickup, &GlobalTransform)>,
+    mut query_players: Query<(&GlobalTransform, &mut Inventory), With<Player>>,
+) {
+    for (entity, pickup, transform) in query_pickups.iter() {
+        let position = transform.translation();
+        let collector = query_players.iter_mut().find(|(player_transform, _)| {
+            player_transform.translation().distance(position) <= PICKUP_RADIUS
+        });
+        if let Some((_, mut inventory)) = collector {
+            inventory.add(pickup.kind, pickup.amount);
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
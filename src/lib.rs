@@ -1,9 +1,55 @@
-use bevy::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*, scene::SceneInstance};
+use std::marker::PhantomData;
 // use bevy_rapier3d::prelude::*;
 
+mod appearance;
+mod audio;
+mod avatar;
+mod camera;
+mod camera_effects;
+mod dungeon_generator;
+mod editor;
+mod flythrough;
+mod health;
+mod interaction;
+mod items;
+mod light_animation;
+mod lighting_presets;
+mod map;
+mod materials;
+mod navigation;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod network;
 mod player;
+mod ron_asset;
+mod save;
+mod shading;
+mod shadows;
+mod triggers;
 mod ui;
-use player::PlayerPlugin;
+use appearance::AppearancePlugin;
+use audio::GameAudioPlugin;
+use avatar::AvatarPlugin;
+use camera::CameraPlugin;
+use camera_effects::CameraEffectsPlugin;
+use dungeon_generator::{build_dungeon_scene, generate_dungeon, procedural_seed, DungeonSettings};
+use editor::{EditorObject, EditorPlugin};
+use flythrough::FlythroughPlugin;
+use health::{HealthPlugin, RespawnCause, RespawnPoint, Respawned};
+use interaction::InteractionPlugin;
+use items::ItemPlugin;
+use light_animation::LightAnimationPlugin;
+use lighting_presets::LightingPresetsPlugin;
+use map::{MapFog, MapPlugin};
+use materials::MaterialsPlugin;
+use navigation::NavigationPlugin;
+#[cfg(not(target_arch = "wasm32"))]
+use network::NetworkPlugin;
+use player::{LocalPlayer, PlayerPlugin, PLAYER_INITIAL_POS, PLAYER_JOIN_OFFSET};
+use save::SavePlugin;
+use shading::ShadingPlugin;
+use shadows::ShadowPlugin;
+use triggers::TriggerPlugin;
 use ui::UIPlugin;
 
 const COLOR_BACKGROUND: Color = Color::rgb_linear(0.008, 0.008, 0.011);
@@ -13,6 +59,78 @@ enum AppState {
     Start,
     InGame,
     Menu,
+    Editor,
+}
+
+// The hand-made dungeon, its pickups and NPCs are placed by code
+pub const DUNGEON_LEVEL: &str = "dungeon.gltf";
+
+// The glTF file the dungeon is loaded from, or a generated level like "procedural:42"
+#[derive(Resource)]
+pub struct CurrentLevel(pub String);
+
+impl Default for CurrentLevel {
+    fn default() -> Self {
+        CurrentLevel(DUNGEON_LEVEL.to_string())
+    }
+}
+
+// Root of the scene spawned for the current level, everything that belongs to the level is below it
+#[derive(Component)]
+pub struct LevelRoot;
+
+// Asks for another level, the players are moved to its spawn point
+pub struct ChangeLevel(pub String);
+
+// Set while waiting for a new level to be spawned before placing the players
+#[derive(Resource, Default)]
+struct LevelStart {
+    pending: bool,
+    frames_ready: u32,
+}
+
+// Replaces the current level with another one, loaded or generated
+#[derive(SystemParam)]
+pub struct LevelSpawner<'w, 's> {
+    asset_server: Res<'w, AssetServer>,
+    scenes: ResMut<'w, Assets<Scene>>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+    materials: ResMut<'w, Assets<StandardMaterial>>,
+    current: ResMut<'w, CurrentLevel>,
+    plight_settings: ResMut<'w, PointLightSettings>,
+    query_roots: Query<'w, 's, Entity, With<LevelRoot>>,
+    #[system_param(ignore)]
+    _marker: PhantomData<&'s ()>,
+}
+
+impl<'w, 's> LevelSpawner<'w, 's> {
+    pub fn current(&self) -> &str {
+        &self.current.0
+    }
+
+    pub fn load(&mut self, commands: &mut Commands, level: &str) {
+        for entity in self.query_roots.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        let scene = match procedural_seed(level) {
+            Some(seed) => {
+                let settings = DungeonSettings::default();
+                let layout = generate_dungeon(seed, &settings);
+                let scene =
+                    build_dungeon_scene(&layout, &settings, &mut self.meshes, &mut self.materials);
+                self.scenes.add(scene)
+            }
+            None => self.asset_server.load(format!("{level}#Scene0")),
+        };
+        commands.spawn((SceneBundle { scene, ..default() }, LevelRoot));
+
+        if self.current.0 != level {
+            self.current.0 = level.to_string();
+        }
+        // Pick up the light colors of the level again once the new lights are there
+        self.plight_settings.initialized = false;
+    }
 }
 
 #[derive(Resource)]
@@ -40,22 +158,50 @@ impl Plugin for GamePlugin {
         app.insert_resource(Msaa::default())
             .insert_resource(ClearColor(COLOR_BACKGROUND))
             .insert_resource(PointLightSettings::default())
+            .init_resource::<CurrentLevel>()
+            .init_resource::<LevelStart>()
+            .add_event::<ChangeLevel>()
             // .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
             // .add_plugin(RapierDebugRenderPlugin::default())
+            .add_plugin(AppearancePlugin)
+            .add_plugin(GameAudioPlugin)
+            .add_plugin(AvatarPlugin)
+            .add_plugin(CameraPlugin)
+            .add_plugin(CameraEffectsPlugin)
+            .add_plugin(EditorPlugin)
+            .add_plugin(FlythroughPlugin)
+            .add_plugin(HealthPlugin)
+            .add_plugin(InteractionPlugin)
+            .add_plugin(ItemPlugin)
+            .add_plugin(LightAnimationPlugin)
+            .add_plugin(LightingPresetsPlugin)
+            .add_plugin(MapPlugin)
+            .add_plugin(MaterialsPlugin)
+            .add_plugin(NavigationPlugin)
             .add_plugin(PlayerPlugin)
+            .add_plugin(SavePlugin)
+            .add_plugin(ShadingPlugin)
+            .add_plugin(ShadowPlugin)
+            .add_plugin(TriggerPlugin)
             .add_plugin(UIPlugin)
             .add_state(AppState::Start)
             .add_startup_system(setup.label("main_setup"))
-            .add_system_set(SystemSet::on_update(AppState::Start).with_system(setup_lights));
+            // Also after the level has been reloaded
+            .add_system(setup_lights)
+            .add_system_to_stage(CoreStage::PreUpdate, reload_level)
+            .add_system_to_stage(CoreStage::PreUpdate, change_level)
+            // Once the spawn point of the new level has been found
+            .add_system_to_stage(CoreStage::PostUpdate, place_players_in_level);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_plugin(NetworkPlugin);
     }
 }
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn setup(mut commands: Commands, mut levels: LevelSpawner) {
     // Dungeon
-    commands.spawn(SceneBundle {
-        scene: asset_server.load("dungeon.gltf#Scene0"),
-        ..default()
-    });
+    let level = levels.current().to_string();
+    levels.load(&mut commands, &level);
 
     commands.insert_resource(AmbientLight {
         brightness: 0.1,
@@ -63,8 +209,88 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     });
 }
 
+// Spawns the level again when its glTF file changes on disk. The player isn't part of the level,
+// so they stay where they are, but doors, levers, pickups and NPCs of the level start over
+fn reload_level(
+    mut commands: Commands,
+    mut levels: LevelSpawner,
+    mut scene_events: EventReader<AssetEvent<Scene>>,
+    query_levels: Query<&Handle<Scene>, With<LevelRoot>>,
+) {
+    let reloaded = scene_events.iter().any(|event| match event {
+        AssetEvent::Modified { handle } => query_levels.iter().any(|level| level == handle),
+        _ => false,
+    });
+    if reloaded {
+        let level = levels.current().to_string();
+        info!("Reloading {level}");
+        levels.load(&mut commands, &level);
+    }
+}
+
+fn change_level(
+    mut commands: Commands,
+    mut levels: LevelSpawner,
+    mut level_events: EventReader<ChangeLevel>,
+    mut level_start: ResMut<LevelStart>,
+    mut fog: ResMut<MapFog>,
+    mut respawn_point: ResMut<RespawnPoint>,
+) {
+    let Some(ChangeLevel(level)) = level_events.iter().last() else {
+        return;
+    };
+    info!("Changing to level {level}");
+    levels.load(&mut commands, level);
+    fog.clear();
+    *respawn_point = RespawnPoint::default();
+    *level_start = LevelStart {
+        pending: true,
+        frames_ready: 0,
+    };
+}
+
+fn place_players_in_level(
+    scene_spawner: Res<SceneSpawner>,
+    respawn_point: Res<RespawnPoint>,
+    mut level_start: ResMut<LevelStart>,
+    mut respawned_events: EventWriter<Respawned>,
+    query_levels: Query<Option<&SceneInstance>, With<LevelRoot>>,
+    mut query_players: Query<(Entity, &LocalPlayer, &mut Transform)>,
+) {
+    if !level_start.pending {
+        return;
+    }
+    let level_ready = query_levels.iter().all(|instance| {
+        instance.is_some_and(|instance| scene_spawner.instance_is_ready(**instance))
+    });
+    if !level_ready {
+        return;
+    }
+    // The spawn points of the level are found the frame after it has been spawned
+    level_start.frames_ready += 1;
+    if level_start.frames_ready < 2 {
+        return;
+    }
+    level_start.pending = false;
+
+    // Levels without a spawn point start where the hand-made dungeon does
+    let start = respawn_point.transform.unwrap_or_else(|| {
+        Transform::from_translation(PLAYER_INITIAL_POS)
+            .looking_at(Vec3::new(0.0, PLAYER_INITIAL_POS.y, 0.0), Vec3::Y)
+    });
+    for (entity, player, mut transform) in query_players.iter_mut() {
+        *transform = start;
+        transform.translation += start.rotation * (PLAYER_JOIN_OFFSET * player.index as f32);
+        respawned_events.send(Respawned {
+            player: entity,
+            cause: RespawnCause::LevelStart,
+        });
+    }
+}
+
+// Lights placed with the editor keep their own settings, and can be there before the level's lights
 fn setup_lights(
-    mut point_lights: Query<&mut PointLight>,
+    mut point_lights: Query<&mut PointLight, Without<EditorObject>>,
     mut settings: ResMut<PointLightSettings>,
     mut ambient_light: ResMut<AmbientLight>,
 ) {
@@ -0,0 +1,296 @@
+use bevy::{gltf::GltfExtras, prelude::*};
+use serde::Deserialize;
+use std::f32::consts::TAU;
+
+use crate::PointLightSettings;
+
+// Lights whose name (or whose parent node's name) contains one of these get animated
+const NAME_RULES: [(&str, LightAnimationKind); 3] = [
+    ("torch", LightAnimationKind::Flicker),
+    ("candle", LightAnimationKind::Flicker),
+    ("crystal", LightAnimationKind::Pulse),
+];
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum LightAnimationKind {
+    Flicker,
+    Pulse,
+    ColorCycle,
+}
+
+impl LightAnimationKind {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "flicker" => Some(LightAnimationKind::Flicker),
+            "pulse" => Some(LightAnimationKind::Pulse),
+            "color_cycle" | "colour_cycle" => Some(LightAnimationKind::ColorCycle),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Component, Clone, Copy, Debug)]
+pub struct LightAnimation {
+    pub kind: LightAnimationKind,
+    pub seed: u32,
+}
+
+#[derive(Resource)]
+pub struct LightAnimationSettings {
+    pub enabled: bool,
+    pub flicker_speed: f32,
+    pub flicker_amount: f32,
+    pub pulse_speed: f32,
+    pub pulse_amount: f32,
+    pub cycle_speed: f32,
+}
+
+impl Default for LightAnimationSettings {
+    fn default() -> Self {
+        LightAnimationSettings {
+            enabled: true,
+            flicker_speed: 8.0,
+            flicker_amount: 0.35,
+            pulse_speed: 0.5,
+            pulse_amount: 0.5,
+            cycle_speed: 0.05,
+        }
+    }
+}
+
+// Custom properties exported from Blender, e.g. {"light_animation": "flicker", "light_seed": 3}
+#[derive(Deserialize)]
+struct LightAnimationExtras {
+    light_animation: Option<String>,
+    light_seed: Option<u32>,
+}
+
+pub struct LightAnimationPlugin;
+
+impl Plugin for LightAnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(LightAnimationSettings::default())
+            .add_system(attach_light_animations)
+            .add_system(animate_lights.after(attach_light_animations));
+    }
+}
+
+fn attach_light_animations(
+    mut commands: Commands,
+    query_new_lights: Query<(Entity, Option<&Parent>), Added<PointLight>>,
+    query_nodes: Query<(Option<&Name>, Option<&GltfExtras>)>,
+) {
+    for (entity, parent) in query_new_lights.iter() {
+        let (name, extras) = query_nodes.get(entity).unwrap();
+        let (parent_name, parent_extras) = parent
+            .and_then(|parent| query_nodes.get(parent.get()).ok())
+            .unwrap_or((None, None));
+
+        // Extras take precedence over naming rules, the light's own over its node's
+        let extras = extras
+            .and_then(parse_extras)
+            .or_else(|| parent_extras.and_then(parse_extras));
+
+        let kind = match &extras {
+            Some(LightAnimationExtras {
+                light_animation: Some(kind),
+                ..
+            }) => LightAnimationKind::from_name(kind),
+            _ => [name, parent_name]
+                .into_iter()
+                .flatten()
+                .find_map(|name| kind_from_name_rules(name.as_str())),
+        };
+
+        let Some(kind) = kind else {
+            continue;
+        };
+
+        // Seed from the node name so that the same light always animates the same way
+        let seed = extras
+            .and_then(|extras| extras.light_seed)
+            .unwrap_or_else(|| {
+                let name = parent_name.or(name).map(|name| name.as_str());
+                hash_name(name.unwrap_or_default())
+            });
+
+        commands
+            .entity(entity)
+            .insert(LightAnimation { kind, seed });
+    }
+}
+
+fn parse_extras(extras: &GltfExtras) -> Option<LightAnimationExtras> {
+    serde_json::from_str(&extras.value).ok()
+}
+
+fn kind_from_name_rules(name: &str) -> Option<LightAnimationKind> {
+    let name = name.to_ascii_lowercase();
+    NAME_RULES
+        .iter()
+        .find(|(pattern, _)| name.contains(pattern))
+        .map(|(_, kind)| *kind)
+}
+
+// Applies the point light color and intensity to all lights, modulated by their animation if any
+// Shadows are handled in shadows.rs
+fn animate_lights(
+    time: Res<Time>,
+    plight_settings: Res<PointLightSettings>,
+    anim_settings: Res<LightAnimationSettings>,
+    mut query_lights: Query<(&mut PointLight, Option<&LightAnimation>)>,
+) {
+    if !plight_settings.initialized {
+        return;
+    }
+
+    let t = time.elapsed_seconds();
+    let base = &plight_settings.light;
+    for (mut light, animation) in query_lights.iter_mut() {
+        let (color, intensity_factor) = match animation {
+            Some(animation) if anim_settings.enabled => {
+                animation_sample(animation, &anim_settings, base.color, t)
+            }
+            _ => (base.color, 1.0),
+        };
+
+        light.color = color;
+        light.intensity = base.intensity * intensity_factor;
+    }
+}
+
+// Returns the color and the intensity factor of an animated light at time t
+fn animation_sample(
+    animation: &LightAnimation,
+    settings: &LightAnimationSettings,
+    base_color: Color,
+    t: f32,
+) -> (Color, f32) {
+    let phase = hash(animation.seed, u32::MAX);
+    match animation.kind {
+        LightAnimationKind::Flicker => {
+            let noise = value_noise(animation.seed, t * settings.flicker_speed);
+            (base_color, 1.0 - settings.flicker_amount * noise)
+        }
+        LightAnimationKind::Pulse => {
+            let wave = 0.5 * (1.0 - ((t * settings.pulse_speed + phase) * TAU).cos());
+            (base_color, 1.0 - settings.pulse_amount * wave)
+        }
+        LightAnimationKind::ColorCycle => {
+            let [hue, saturation, lightness, alpha] = base_color.as_hsla_f32();
+            let hue = (hue + (t * settings.cycle_speed + phase) * 360.0).rem_euclid(360.0);
+            (Color::hsla(hue, saturation, lightness, alpha), 1.0)
+        }
+    }
+}
+
+// Smoothly interpolated 1D value noise in [0, 1]
+pub fn value_noise(seed: u32, x: f32) -> f32 {
+    let cell = x.floor();
+    let frac = x - cell;
+    let smooth = frac * frac * (3.0 - 2.0 * frac);
+    let cell = cell as i64 as u32;
+    let a = hash(seed, cell);
+    let b = hash(seed, cell.wrapping_add(1));
+    a + (b - a) * smooth
+}
+
+// Integer hash mapped to [0, 1]
+fn hash(seed: u32, n: u32) -> f32 {
+    let mut x = seed.wrapping_mul(0x9E37_79B9) ^ n.wrapping_mul(0x85EB_CA6B);
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7FEB_352D);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846C_A68B);
+    x ^= x >> 16;
+    x as f32 / u32::MAX as f32
+}
+
+// FNV-1a
+fn hash_name(name: &str) -> u32 {
+    name.bytes().fold(0x811C_9DC5, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [LightAnimationKind; 3] = [
+        LightAnimationKind::Flicker,
+        LightAnimationKind::Pulse,
+        LightAnimationKind::ColorCycle,
+    ];
+
+    fn samples(animation: LightAnimation, settings: &LightAnimationSettings) -> Vec<(Color, f32)> {
+        (0..200)
+            .map(|step| animation_sample(&animation, settings, Color::ORANGE, step as f32 * 0.05))
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_same_samples() {
+        let settings = LightAnimationSettings::default();
+        for kind in KINDS {
+            let animation = LightAnimation { kind, seed: 7 };
+            assert_eq!(samples(animation, &settings), samples(animation, &settings));
+        }
+    }
+
+    #[test]
+    fn different_seeds_different_phases() {
+        let settings = LightAnimationSettings::default();
+        for kind in KINDS {
+            let a = samples(LightAnimation { kind, seed: 1 }, &settings);
+            let b = samples(LightAnimation { kind, seed: 2 }, &settings);
+            assert_ne!(a, b, "{kind:?} should depend on the seed");
+        }
+    }
+
+    #[test]
+    fn flicker_and_pulse_stay_in_range() {
+        let settings = LightAnimationSettings::default();
+        for (kind, amount) in [
+            (LightAnimationKind::Flicker, settings.flicker_amount),
+            (LightAnimationKind::Pulse, settings.pulse_amount),
+        ] {
+            for seed in 0..20 {
+                for (color, factor) in samples(LightAnimation { kind, seed }, &settings) {
+                    assert_eq!(color, Color::ORANGE);
+                    assert!(
+                        (1.0 - amount..=1.0).contains(&factor),
+                        "{kind:?} seed {seed}: {factor}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn color_cycle_keeps_intensity_and_lightness() {
+        let settings = LightAnimationSettings::default();
+        let [_, base_saturation, base_lightness, _] = Color::ORANGE.as_hsla_f32();
+        let animation = LightAnimation {
+            kind: LightAnimationKind::ColorCycle,
+            seed: 3,
+        };
+        for (color, factor) in samples(animation, &settings) {
+            let [hue, saturation, lightness, _] = color.as_hsla_f32();
+            assert_eq!(factor, 1.0);
+            assert!((0.0..360.0).contains(&hue));
+            assert!((saturation - base_saturation).abs() < 1e-3);
+            assert!((lightness - base_lightness).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn value_noise_is_in_unit_range() {
+        for seed in 0..10 {
+            for step in 0..1000 {
+                let noise = value_noise(seed, step as f32 * 0.037 - 10.0);
+                assert!((0.0..=1.0).contains(&noise));
+            }
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use bevy::{prelude::*, reflect::TypeUuid};
+use serde::Deserialize;
+
+use crate::{ron_asset::RonAssetPlugin, triggers::TriggerEntered, PointLightSettings};
+
+const PRESETS_PATH: &str = "lighting.presets.ron";
+const TRANSITION_DURATION: f32 = 1.5;
+
+// Colors are stored as linear rgba, just like the color pickers in the Graphics window
+#[derive(Deserialize, Clone, PartialEq)]
+pub struct LightingPreset {
+    pub name: String,
+    pub clear_color: [f32; 4],
+    pub ambient_color: [f32; 4],
+    pub ambient_brightness: f32,
+    pub point_color: [f32; 4],
+    pub point_intensity: f32,
+}
+
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "5b0e6c3e-3f4e-4c8a-9a55-8f5e3f0a2d11"]
+pub struct LightingPresets {
+    pub presets: Vec<LightingPreset>,
+}
+
+#[derive(Resource)]
+pub struct LightingPresetSettings {
+    pub handle: Handle<LightingPresets>,
+    pub selected: Option<String>,
+    pub duration: f32,
+    transition: Option<LightingTransition>,
+}
+
+impl LightingPresetSettings {
+    pub fn start_transition(
+        &mut self,
+        preset: &LightingPreset,
+        clear_color: &ClearColor,
+        ambient_light: &AmbientLight,
+        plight_settings: &PointLightSettings,
+    ) {
+        let from = LightingPreset {
+            name: String::new(),
+            clear_color: clear_color.as_linear_rgba_f32(),
+            ambient_color: ambient_light.color.as_linear_rgba_f32(),
+            ambient_brightness: ambient_light.brightness,
+            point_color: plight_settings.light.color.as_linear_rgba_f32(),
+            point_intensity: plight_settings.light.intensity,
+        };
+
+        self.selected = Some(preset.name.clone());
+        self.transition = Some(LightingTransition {
+            from,
+            to: preset.clone(),
+            elapsed: 0.0,
+        });
+    }
+}
+
+// Switches to the named preset when the player walks into the trigger volume
+#[derive(Component)]
+pub struct LightingPresetTrigger(pub String);
+
+struct LightingTransition {
+    from: LightingPreset,
+    to: LightingPreset,
+    elapsed: f32,
+}
+
+pub struct LightingPresetsPlugin;
+
+impl Plugin for LightingPresetsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(RonAssetPlugin::<LightingPresets>::new(&["presets.ron"]))
+            .add_startup_system(setup_presets)
+            .add_system(reload_presets.before(transition_system))
+            .add_system(preset_trigger_system.before(transition_system))
+            .add_system(transition_system);
+    }
+}
+
+fn setup_presets(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(LightingPresetSettings {
+        handle: asset_server.load(PRESETS_PATH),
+        selected: None,
+        duration: TRANSITION_DURATION,
+        transition: None,
+    });
+}
+
+// Editing the presets file while the game runs blends into the new values of the selected preset
+fn reload_presets(
+    mut preset_events: EventReader<AssetEvent<LightingPresets>>,
+    presets: Res<Assets<LightingPresets>>,
+    mut preset_settings: ResMut<LightingPresetSettings>,
+    clear_color: Res<ClearColor>,
+    ambient_light: Res<AmbientLight>,
+    plight_settings: Res<PointLightSettings>,
+) {
+    for event in preset_events.iter() {
+        let AssetEvent::Modified { handle } = event else {
+            continue;
+        };
+        if *handle != preset_settings.handle {
+            continue;
+        }
+        info!("Reloaded {PRESETS_PATH}");
+
+        let Some(name) = preset_settings.selected.clone() else {
+            continue;
+        };
+        let Some(preset) = presets
+            .get(handle)
+            .and_then(|presets| presets.presets.iter().find(|preset| preset.name == name))
+        else {
+            warn!("The selected lighting preset {name} is gone");
+            continue;
+        };
+        preset_settings.start_transition(preset, &clear_color, &ambient_light, &plight_settings);
+    }
+}
+
+fn preset_trigger_system(
+    mut trigger_events: EventReader<TriggerEntered>,
+    query_triggers: Query<&LightingPresetTrigger>,
+    presets: Res<Assets<LightingPresets>>,
+    mut preset_settings: ResMut<LightingPresetSettings>,
+    clear_color: Res<ClearColor>,
+    ambient_light: Res<AmbientLight>,
+    plight_settings: Res<PointLightSettings>,
+) {
+    for event in trigger_events.iter() {
+        let Ok(LightingPresetTrigger(name)) = query_triggers.get(event.trigger) else {
+            continue;
+        };
+        let Some(preset) = presets
+            .get(&preset_settings.handle)
+            .and_then(|presets| presets.presets.iter().find(|preset| &preset.name == name))
+        else {
+            warn!("Unknown lighting preset {name}");
+            continue;
+        };
+        if preset_settings.selected.as_ref() == Some(name) {
+            continue;
+        }
+
+        preset_settings.start_transition(preset, &clear_color, &ambient_light, &plight_settings);
+    }
+}
+
+fn transition_system(
+    time: Res<Time>,
+    mut preset_settings: ResMut<LightingPresetSettings>,
+    mut clear_color: ResMut<ClearColor>,
+    mut ambient_light: ResMut<AmbientLight>,
+    mut plight_settings: ResMut<PointLightSettings>,
+) {
+    let duration = preset_settings.duration;
+    let Some(transition) = &mut preset_settings.transition else {
+        return;
+    };
+
+    transition.elapsed += time.delta_seconds();
+    let t = if duration > 0.0 {
+        (transition.elapsed / duration).min(1.0)
+    } else {
+        1.0
+    };
+    // Smoothstep so that the mood change eases in and out
+    let t = t * t * (3.0 - 2.0 * t);
+
+    let (from, to) = (&transition.from, &transition.to);
+    clear_color.0 = lerp_color(from.clear_color, to.clear_color, t);
+    ambient_light.color = lerp_color(from.ambient_color, to.ambient_color, t);
+    ambient_light.brightness = lerp(from.ambient_brightness, to.ambient_brightness, t);
+    plight_settings.light.color = lerp_color(from.point_color, to.point_color, t);
+    plight_settings.light.intensity = lerp(from.point_intensity, to.point_intensity, t);
+
+    if t >= 1.0 {
+        preset_settings.transition = None;
+    }
+}
+
+#[inline]
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+#[inline]
+fn lerp_color(a: [f32; 4], b: [f32; 4], t: f32) -> Color {
+    Color::rgba_linear(
+        lerp(a[0], b[0], t),
+        lerp(a[1], b[1], t),
+        lerp(a[2], b[2], t),
+        lerp(a[3], b[3], t),
+    )
+}
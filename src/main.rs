@@ -8,17 +8,48 @@ use bevy::{
 use bevy_3d_test::GamePlugin;
 
 fn main() {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(address) = bevy_3d_test::network::server_address_from_args() {
+        run_server(address);
+        return;
+    }
+
     App::new()
-        .add_plugins(DefaultPlugins.set(WindowPlugin {
-            // window: WindowDescriptor {
-            //     present_mode: PresentMode::AutoNoVsync,
-            //     ..default()
-            // },
-            ..default()
-        }))
+        .add_plugins(
+            DefaultPlugins
+                .set(WindowPlugin {
+                    // window: WindowDescriptor {
+                    //     present_mode: PresentMode::AutoNoVsync,
+                    //     ..default()
+                    // },
+                    ..default()
+                })
+                .set(AssetPlugin {
+                    // Changes to the dungeon and the settings files show up without restarting,
+                    // there are no files to watch on the web
+                    watch_for_changes: !cfg!(target_arch = "wasm32"),
+                    ..default()
+                }),
+        )
         .add_plugin(GamePlugin)
         // .add_plugin(LogDiagnosticsPlugin::default())
         // .add_plugin(FrameTimeDiagnosticsPlugin::default())
         // .init_resource::<ReportExecutionOrderAmbiguities>()
         .run();
 }
+
+// A server without a window, it only moves the players around
+#[cfg(not(target_arch = "wasm32"))]
+fn run_server(address: std::net::SocketAddr) {
+    use bevy::{app::ScheduleRunnerSettings, log::LogPlugin, utils::Duration};
+    use bevy_3d_test::network::NetworkServerPlugin;
+
+    App::new()
+        .insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f64(
+            1.0 / 60.0,
+        )))
+        .add_plugins(MinimalPlugins)
+        .add_plugin(LogPlugin::default())
+        .add_plugin(NetworkServerPlugin { address })
+        .run();
+}
@@ -0,0 +1,260 @@
+use bevy::{
+    core_pipeline::clear_color::ClearColorConfig,
+    prelude::*,
+    render::{
+        camera::{RenderTarget, ScalingMode},
+        render_resource::{
+            Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+        },
+    },
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    navigation::NavGrid,
+    player::{Player, PrimaryPlayer},
+    AppState,
+};
+
+const MINIMAP_RESOLUTION: u32 = 256;
+const MAP_RESOLUTION: u32 = 1024;
+// Height of the map cameras, above everything in the dungeon
+const MAP_CAMERA_HEIGHT: f32 = 30.0;
+// Space left around the dungeon on the full map
+const MAP_MARGIN: f32 = 2.0;
+
+pub const FOG_CELL_SIZE: f32 = 1.0;
+// Everything this close to the player counts as explored
+const REVEAL_RADIUS: f32 = 4.0;
+
+#[derive(Resource)]
+pub struct MapSettings {
+    pub show_minimap: bool,
+    // World units shown across the minimap
+    pub minimap_extent: f32,
+    pub show_full_map: bool,
+}
+
+impl Default for MapSettings {
+    fn default() -> Self {
+        MapSettings {
+            show_minimap: true,
+            minimap_extent: 20.0,
+            show_full_map: false,
+        }
+    }
+}
+
+// What a map camera renders, north (-Z) is up
+pub struct MapView {
+    pub image: Handle<Image>,
+    pub center: Vec2,
+    pub extent: f32,
+}
+
+#[derive(Resource)]
+pub struct MapViews {
+    pub minimap: MapView,
+    pub map: MapView,
+}
+
+impl FromWorld for MapViews {
+    fn from_world(world: &mut World) -> Self {
+        let mut images = world.resource_mut::<Assets<Image>>();
+        MapViews {
+            minimap: MapView {
+                image: images.add(render_target_image(MINIMAP_RESOLUTION)),
+                center: Vec2::ZERO,
+                extent: MapSettings::default().minimap_extent,
+            },
+            map: MapView {
+                image: images.add(render_target_image(MAP_RESOLUTION)),
+                center: Vec2::ZERO,
+                extent: 1.0,
+            },
+        }
+    }
+}
+
+// Cells of the dungeon the player has been close to
+#[derive(Resource, Default)]
+pub struct MapFog {
+    explored: HashSet<IVec2>,
+}
+
+impl MapFog {
+    pub fn cell(position: Vec2) -> IVec2 {
+        (position / FOG_CELL_SIZE).floor().as_ivec2()
+    }
+
+    pub fn is_explored(&self, cell: IVec2) -> bool {
+        self.explored.contains(&cell)
+    }
+
+    // For a new level
+    pub fn clear(&mut self) {
+        self.explored.clear();
+    }
+
+    fn reveal(&mut self, position: Vec2, radius: f32) {
+        let min = MapFog::cell(position - radius);
+        let max = MapFog::cell(position + radius);
+        for z in min.y..=max.y {
+            for x in min.x..=max.x {
+                let center = (Vec2::new(x as f32, z as f32) + 0.5) * FOG_CELL_SIZE;
+                if center.distance(position) <= radius {
+                    self.explored.insert(IVec2::new(x, z));
+                }
+            }
+        }
+    }
+}
+
+#[derive(Component, Clone, Copy)]
+enum MapCamera {
+    Minimap,
+    Map,
+}
+
+pub struct MapPlugin;
+
+impl Plugin for MapPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(MapSettings::default())
+            .insert_resource(MapFog::default())
+            .init_resource::<MapViews>()
+            .add_startup_system(spawn_map_cameras)
+            .add_system(reveal_map)
+            .add_system(update_map_views)
+            .add_system(update_map_cameras.after(update_map_views));
+    }
+}
+
+fn render_target_image(resolution: u32) -> Image {
+    let size = Extent3d {
+        width: resolution,
+        height: resolution,
+        ..default()
+    };
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: None,
+            size,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Bgra8UnormSrgb,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+        },
+        ..default()
+    };
+    // Fills the image with zeroes
+    image.resize(size);
+    image
+}
+
+fn spawn_map_cameras(mut commands: Commands, views: Res<MapViews>) {
+    for (kind, view) in [
+        (MapCamera::Minimap, &views.minimap),
+        (MapCamera::Map, &views.map),
+    ] {
+        commands.spawn((
+            Camera3dBundle {
+                camera: Camera {
+                    target: RenderTarget::Image(view.image.clone()),
+                    // Render before the main camera, which shows the image in the UI
+                    priority: -1,
+                    is_active: false,
+                    ..default()
+                },
+                camera_3d: Camera3d {
+                    clear_color: ClearColorConfig::Custom(Color::BLACK),
+                    ..default()
+                },
+                projection: OrthographicProjection {
+                    scaling_mode: ScalingMode::FixedVertical(1.0),
+                    ..default()
+                }
+                .into(),
+                ..default()
+            },
+            kind,
+        ));
+    }
+}
+
+// Every local player explores the same map
+fn reveal_map(
+    mut fog: ResMut<MapFog>,
+    query_players: Query<(Entity, &GlobalTransform), With<Player>>,
+    mut last_cells: Local<HashMap<Entity, IVec2>>,
+) {
+    for (player, transform) in query_players.iter() {
+        let position = transform.translation();
+        let position = Vec2::new(position.x, position.z);
+        // Only touch the resource when the player has moved to another cell
+        let cell = MapFog::cell(position);
+        if last_cells.insert(player, cell) == Some(cell) {
+            continue;
+        }
+        fog.reveal(position, REVEAL_RADIUS);
+    }
+}
+
+// The minimap follows the player, the full map frames the whole dungeon
+fn update_map_views(
+    map_settings: Res<MapSettings>,
+    nav_grid: Res<NavGrid>,
+    mut views: ResMut<MapViews>,
+    query_player: Query<&GlobalTransform, With<PrimaryPlayer>>,
+) {
+    if let Ok(transform) = query_player.get_single() {
+        let position = transform.translation();
+        views.minimap.center = Vec2::new(position.x, position.z);
+    }
+    views.minimap.extent = map_settings.minimap_extent;
+
+    if nav_grid.is_changed() {
+        if let Some((min, max)) = nav_grid.area() {
+            views.map.center = (min + max) / 2.0;
+            views.map.extent = (max - min).max_element() + 2.0 * MAP_MARGIN;
+        }
+    }
+}
+
+fn update_map_cameras(
+    map_settings: Res<MapSettings>,
+    views: Res<MapViews>,
+    app_state: Res<State<AppState>>,
+    mut query_cams: Query<(&MapCamera, &mut Camera, &mut Transform, &mut Projection)>,
+) {
+    for (kind, mut cam, mut transform, mut projection) in query_cams.iter_mut() {
+        let (view, is_active) = match kind {
+            MapCamera::Minimap => (
+                &views.minimap,
+                map_settings.show_minimap && *app_state.current() == AppState::InGame,
+            ),
+            MapCamera::Map => (
+                &views.map,
+                map_settings.show_full_map && *app_state.current() == AppState::Menu,
+            ),
+        };
+        if cam.is_active != is_active {
+            cam.is_active = is_active;
+        }
+        if !is_active {
+            continue;
+        }
+
+        let target = Vec3::new(view.center.x, 0.0, view.center.y);
+        *transform = Transform::from_translation(target + Vec3::Y * MAP_CAMERA_HEIGHT)
+            .looking_at(target, -Vec3::Z);
+        if let Projection::Orthographic(ortho) = &mut *projection {
+            if ortho.scale != view.extent {
+                ortho.scale = view.extent;
+            }
+        }
+    }
+}
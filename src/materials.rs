@@ -0,0 +1,240 @@
+use bevy::{gltf::Gltf, prelude::*, reflect::TypeUuid};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+
+use crate::{dungeon_generator::procedural_seed, ron_asset::RonAssetPlugin, CurrentLevel};
+
+// Every field left out keeps the value exported from Blender
+#[derive(Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct MaterialOverride {
+    // Linear rgba, just like the color pickers in the Graphics window
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_color: Option<[f32; 4]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub perceptual_roughness: Option<f32>,
+    // Multiplies the exported emissive color, Bevy ignores the strength set in Blender
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub emissive_strength: Option<f32>,
+    // An image in the assets folder, replacing the base color texture
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_color_texture: Option<String>,
+}
+
+// Overrides for the materials of a level by name, read from e.g. "dungeon.materials.ron" next to
+// "dungeon.gltf"
+#[derive(Serialize, Deserialize, TypeUuid, Clone, PartialEq, Default)]
+#[uuid = "9a3e51c2-7d84-4b0f-8e26-1f4c6d2a9b73"]
+pub struct MaterialOverrides {
+    pub materials: BTreeMap<String, MaterialOverride>,
+}
+
+// Generated levels don't use named materials, only the ones made in Blender can be overridden
+fn overrides_path(level: &str) -> Option<String> {
+    if procedural_seed(level).is_some() {
+        return None;
+    }
+    let path = Path::new(level).with_extension("materials.ron");
+    Some(path.to_string_lossy().replace('\\', "/"))
+}
+
+#[derive(Resource, Default)]
+pub struct MaterialOverrideSettings {
+    // What the Materials window edits, applied whenever it changes
+    pub overrides: MaterialOverrides,
+    pub status: Option<String>,
+    handle: Option<Handle<MaterialOverrides>>,
+    gltf: Option<Handle<Gltf>>,
+    // The materials as exported, overrides are always applied on top of these
+    originals: BTreeMap<String, (Handle<StandardMaterial>, StandardMaterial)>,
+}
+
+impl MaterialOverrideSettings {
+    pub fn originals(&self) -> impl Iterator<Item = (&String, &StandardMaterial)> {
+        self.originals
+            .iter()
+            .map(|(name, (_, material))| (name, material))
+    }
+}
+
+pub struct SaveMaterialOverrides;
+
+pub struct MaterialsPlugin;
+
+impl Plugin for MaterialsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(RonAssetPlugin::<MaterialOverrides>::new(&["materials.ron"]))
+            .add_event::<SaveMaterialOverrides>()
+            .init_resource::<MaterialOverrideSettings>()
+            .add_system(load_material_overrides)
+            .add_system(capture_original_materials.after(load_material_overrides))
+            .add_system(reload_material_overrides.after(load_material_overrides))
+            .add_system(
+                apply_material_overrides
+                    .after(capture_original_materials)
+                    .after(reload_material_overrides),
+            )
+            .add_system(save_material_overrides);
+    }
+}
+
+// Starts loading the materials and their overrides whenever another level is loaded
+fn load_material_overrides(
+    asset_server: Res<AssetServer>,
+    level: Res<CurrentLevel>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut settings: ResMut<MaterialOverrideSettings>,
+) {
+    if !level.is_changed() {
+        return;
+    }
+
+    // The glTF stays loaded when coming back to the same level, so undo the overrides first
+    for (handle, original) in settings.originals.values() {
+        if let Some(material) = materials.get_mut(handle) {
+            *material = original.clone();
+        }
+    }
+
+    let path = overrides_path(&level.0);
+    *settings = MaterialOverrideSettings {
+        gltf: path.as_ref().map(|_| asset_server.load(level.0.as_str())),
+        // Asking for a file that doesn't exist logs an error, most levels have no overrides
+        handle: path
+            .filter(|path| asset_server.asset_io().is_file(Path::new(path)))
+            .map(|path| asset_server.load(path.as_str())),
+        ..default()
+    };
+}
+
+// Remembers the materials once the level is loaded, and again after it was re-exported
+fn capture_original_materials(
+    mut gltf_events: EventReader<AssetEvent<Gltf>>,
+    gltfs: Res<Assets<Gltf>>,
+    materials: Res<Assets<StandardMaterial>>,
+    mut settings: ResMut<MaterialOverrideSettings>,
+) {
+    let modified = gltf_events.iter().any(|event| match event {
+        AssetEvent::Modified { handle } => Some(handle) == settings.gltf.as_ref(),
+        _ => false,
+    });
+    if !settings.originals.is_empty() && !modified {
+        return;
+    }
+    let Some(gltf) = settings.gltf.as_ref().and_then(|handle| gltfs.get(handle)) else {
+        return;
+    };
+
+    let originals: BTreeMap<_, _> = gltf
+        .named_materials
+        .iter()
+        .filter_map(|(name, handle)| {
+            let material = materials.get(handle)?.clone();
+            Some((name.clone(), (handle.clone(), material)))
+        })
+        .collect();
+    if !originals.is_empty() {
+        settings.originals = originals;
+    }
+}
+
+// Editing the overrides file while the game runs replaces what the Materials window shows
+fn reload_material_overrides(
+    mut override_events: EventReader<AssetEvent<MaterialOverrides>>,
+    overrides: Res<Assets<MaterialOverrides>>,
+    mut settings: ResMut<MaterialOverrideSettings>,
+) {
+    for event in override_events.iter() {
+        let (AssetEvent::Created { handle } | AssetEvent::Modified { handle }) = event else {
+            continue;
+        };
+        if Some(handle) != settings.handle.as_ref() {
+            continue;
+        }
+        if let Some(overrides) = overrides.get(handle) {
+            settings.overrides = overrides.clone();
+        }
+    }
+}
+
+fn apply_material_overrides(
+    asset_server: Res<AssetServer>,
+    settings: Res<MaterialOverrideSettings>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    for (name, (handle, original)) in settings.originals.iter() {
+        let mut overridden = original.clone();
+        if let Some(material_override) = settings.overrides.materials.get(name) {
+            if let Some([r, g, b, a]) = material_override.base_color {
+                overridden.base_color = Color::rgba_linear(r, g, b, a);
+            }
+            if let Some(roughness) = material_override.perceptual_roughness {
+                overridden.perceptual_roughness = roughness;
+            }
+            if let Some(strength) = material_override.emissive_strength {
+                overridden.emissive = original.emissive * strength;
+            }
+            // Paths are typed in the Materials window, so skip the ones that aren't complete yet
+            if let Some(path) = &material_override.base_color_texture {
+                if asset_server.asset_io().is_file(Path::new(path)) {
+                    overridden.base_color_texture = Some(asset_server.load(path.as_str()));
+                }
+            }
+        }
+        if let Some(material) = materials.get_mut(handle) {
+            *material = overridden;
+        }
+    }
+}
+
+fn save_material_overrides(
+    mut save_events: EventReader<SaveMaterialOverrides>,
+    asset_server: Res<AssetServer>,
+    level: Res<CurrentLevel>,
+    mut settings: ResMut<MaterialOverrideSettings>,
+) {
+    if save_events.iter().last().is_none() {
+        return;
+    }
+    let Some(path) = overrides_path(&level.0) else {
+        settings.status = Some("Generated levels can't have material overrides".to_string());
+        return;
+    };
+
+    let status = match write_overrides(&path, &settings.overrides) {
+        Ok(()) => {
+            // Watch the new file, so that editing it by hand works from now on
+            if settings.handle.is_none() {
+                settings.handle = Some(asset_server.load(path.as_str()));
+            }
+            format!(
+                "Saved {} overrides to {path}",
+                settings.overrides.materials.len()
+            )
+        }
+        Err(error) => {
+            warn!("Could not save {path}: {error}");
+            format!("Could not save {path}: {error}")
+        }
+    };
+    settings.status = Some(status);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_overrides(path: &str, overrides: &MaterialOverrides) -> Result<(), String> {
+    let text = ron::ser::to_string_pretty(overrides, ron::ser::PrettyConfig::default())
+        .map_err(|error| error.to_string())?;
+    let path = bevy::asset::FileAssetIo::get_base_path()
+        .join("assets")
+        .join(path);
+    std::fs::write(path, text).map_err(|error| error.to_string())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_overrides(_path: &str, _overrides: &MaterialOverrides) -> Result<(), String> {
+    Err("there are no files to write to on the web".to_string())
+}
@@ -0,0 +1,681 @@
+use bevy::{gltf::GltfExtras, prelude::*, render::primitives::Aabb};
+use serde::Deserialize;
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use crate::{items::Pickup, player::Player, AppState, CurrentLevel, LevelRoot, DUNGEON_LEVEL};
+
+const CELL_SIZE: f32 = 0.5;
+const AGENT_RADIUS: f32 = 0.3;
+const AGENT_HEIGHT: f32 = 1.8;
+// Anything lower than this can be walked over, anything thinner is a floor
+const STEP_HEIGHT: f32 = 0.3;
+// How far away from the grid a position may be and still find a cell to start from
+const MAX_SNAP_CELLS: i32 = 4;
+
+const NPC_SPEED: f32 = 2.0;
+const NPC_RADIUS: f32 = 0.3;
+const NPC_HEIGHT_2: f32 = AGENT_HEIGHT / 2.0;
+const NPC_FOLLOW_DISTANCE: f32 = 2.0;
+const NPC_REPATH_INTERVAL: f32 = 0.5;
+const NPC_WAYPOINT_TOLERANCE: f32 = 0.05;
+
+// Empties named like "npc_wander" or "npc_follow.001" become NPCs, standing where the empty is
+const NPC_NAME_PREFIX: &str = "npc_";
+
+const COLOR_NPC_WANDER: &str = "8E7DBE"; // Purple Mountain Majesty
+const COLOR_NPC_FOLLOW: &str = "C05746"; // Medium Carmine
+
+// The dungeon rooms are walled off from each other, so the wanderer stays in the first one
+// and the follower starts next to the player
+const DUNGEON_WANDER_WAYPOINTS: [Vec3; 5] = [
+    Vec3::new(-4.0, 0.0, 6.0),
+    Vec3::new(4.0, 0.0, 6.0),
+    Vec3::new(4.0, 0.0, 10.0),
+    Vec3::new(0.0, 0.0, 2.0),
+    Vec3::new(-4.0, 0.0, 9.0),
+];
+const DUNGEON_FOLLOWER_POS: Vec3 = Vec3::new(4.0, 0.0, -6.0);
+
+#[derive(Clone, Copy, Debug)]
+pub struct Bounds {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Bounds {
+    fn from_aabb(aabb: &Aabb, transform: &GlobalTransform) -> Self {
+        let (center, half) = (Vec3::from(aabb.center), Vec3::from(aabb.half_extents));
+        let affine = transform.affine();
+        let mut bounds = Bounds {
+            min: Vec3::splat(f32::MAX),
+            max: Vec3::splat(f32::MIN),
+        };
+        for corner in [
+            Vec3::new(-1.0, -1.0, -1.0),
+            Vec3::new(-1.0, -1.0, 1.0),
+            Vec3::new(-1.0, 1.0, -1.0),
+            Vec3::new(-1.0, 1.0, 1.0),
+            Vec3::new(1.0, -1.0, -1.0),
+            Vec3::new(1.0, -1.0, 1.0),
+            Vec3::new(1.0, 1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+        ] {
+            let point = affine.transform_point3(center + corner * half);
+            bounds.min = bounds.min.min(point);
+            bounds.max = bounds.max.max(point);
+        }
+        bounds
+    }
+
+    fn contains_xz(&self, point: Vec2, margin: f32) -> bool {
+        point.x >= self.min.x - margin
+            && point.x <= self.max.x + margin
+            && point.y >= self.min.z - margin
+            && point.y <= self.max.z + margin
+    }
+}
+
+// A walkability grid over the floor, with the floor height of every walkable cell
+#[derive(Resource, Default)]
+pub struct NavGrid {
+    origin: Vec2,
+    cell_size: f32,
+    width: i32,
+    depth: i32,
+    cells: Vec<Option<f32>>,
+}
+
+impl NavGrid {
+    pub fn build(floors: &[Bounds], obstacles: &[Bounds], cell_size: f32) -> Self {
+        let Some(first) = floors.first() else {
+            return NavGrid::default();
+        };
+
+        let (mut min, mut max) = (first.min, first.max);
+        for floor in floors {
+            min = min.min(floor.min);
+            max = max.max(floor.max);
+        }
+        let origin = Vec2::new(min.x, min.z);
+        let width = ((max.x - min.x) / cell_size).ceil() as i32;
+        let depth = ((max.z - min.z) / cell_size).ceil() as i32;
+
+        let mut cells = Vec::with_capacity((width * depth) as usize);
+        for z in 0..depth {
+            for x in 0..width {
+                let center = origin + (Vec2::new(x as f32, z as f32) + 0.5) * cell_size;
+                let floor_y = floors
+                    .iter()
+                    .filter(|floor| floor.contains_xz(center, 0.0))
+                    .map(|floor| floor.max.y)
+                    .reduce(f32::max);
+                let blocked = floor_y.is_none_or(|floor_y| {
+                    obstacles.iter().any(|obstacle| {
+                        obstacle.contains_xz(center, AGENT_RADIUS)
+                            && obstacle.max.y > floor_y + STEP_HEIGHT
+                            && obstacle.min.y < floor_y + AGENT_HEIGHT
+                    })
+                });
+                cells.push(if blocked { None } else { floor_y });
+            }
+        }
+
+        NavGrid {
+            origin,
+            cell_size,
+            width,
+            depth,
+            cells,
+        }
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    // Corners of the grid on the XZ plane
+    pub fn area(&self) -> Option<(Vec2, Vec2)> {
+        if self.width <= 0 || self.depth <= 0 {
+            return None;
+        }
+        let size = Vec2::new(self.width as f32, self.depth as f32) * self.cell_size;
+        Some((self.origin, self.origin + size))
+    }
+
+    pub fn walkable_cells(&self) -> impl Iterator<Item = Vec3> + '_ {
+        (0..self.depth)
+            .flat_map(move |z| (0..self.width).map(move |x| IVec2::new(x, z)))
+            .filter_map(|cell| self.cell_center(cell))
+    }
+
+    // A* over the grid, returning the corners of the path after the start position
+    pub fn find_path(&self, start: Vec3, goal: Vec3) -> Option<Vec<Vec3>> {
+        let start = self.nearest_walkable(start)?;
+        let goal = self.nearest_walkable(goal)?;
+
+        let mut open = BinaryHeap::new();
+        let mut came_from: Vec<Option<usize>> = vec![None; self.cells.len()];
+        let mut costs = vec![f32::INFINITY; self.cells.len()];
+        let start_index = self.index(start);
+        let goal_index = self.index(goal);
+
+        costs[start_index] = 0.0;
+        open.push(OpenNode {
+            estimate: octile_distance(start, goal),
+            index: start_index,
+        });
+
+        while let Some(OpenNode { index, .. }) = open.pop() {
+            if index == goal_index {
+                return Some(self.reconstruct_path(&came_from, start_index, goal_index));
+            }
+
+            let cell = self.cell(index);
+            for (neighbor, step_cost) in self.neighbors(cell) {
+                let neighbor_index = self.index(neighbor);
+                let cost = costs[index] + step_cost;
+                if cost < costs[neighbor_index] {
+                    costs[neighbor_index] = cost;
+                    came_from[neighbor_index] = Some(index);
+                    open.push(OpenNode {
+                        estimate: cost + octile_distance(neighbor, goal),
+                        index: neighbor_index,
+                    });
+                }
+            }
+        }
+
+        None
+    }
+
+    fn reconstruct_path(
+        &self,
+        came_from: &[Option<usize>],
+        start_index: usize,
+        goal_index: usize,
+    ) -> Vec<Vec3> {
+        let mut cells = vec![self.cell(goal_index)];
+        let mut index = goal_index;
+        while let Some(previous) = came_from[index] {
+            if previous == start_index {
+                break;
+            }
+            cells.push(self.cell(previous));
+            index = previous;
+        }
+        cells.reverse();
+
+        // Only keep the cells where the path changes direction
+        let mut previous = self.cell(start_index);
+        let mut corners = Vec::new();
+        for (i, cell) in cells.iter().enumerate() {
+            let direction = *cell - previous;
+            let next_direction = cells.get(i + 1).map(|next| *next - *cell);
+            if next_direction != Some(direction) {
+                corners.push(*cell);
+            }
+            previous = *cell;
+        }
+
+        corners
+            .into_iter()
+            .filter_map(|cell| self.cell_center(cell))
+            .collect()
+    }
+
+    fn neighbors(&self, cell: IVec2) -> impl Iterator<Item = (IVec2, f32)> + '_ {
+        [
+            IVec2::new(1, 0),
+            IVec2::new(-1, 0),
+            IVec2::new(0, 1),
+            IVec2::new(0, -1),
+            IVec2::new(1, 1),
+            IVec2::new(1, -1),
+            IVec2::new(-1, 1),
+            IVec2::new(-1, -1),
+        ]
+        .into_iter()
+        .filter(move |offset| {
+            // No cutting corners past obstacles
+            self.is_walkable(cell + *offset)
+                && self.is_walkable(cell + IVec2::new(offset.x, 0))
+                && self.is_walkable(cell + IVec2::new(0, offset.y))
+        })
+        .map(move |offset| {
+            let cost = if offset.x != 0 && offset.y != 0 {
+                std::f32::consts::SQRT_2
+            } else {
+                1.0
+            };
+            (cell + offset, cost)
+        })
+    }
+
+    fn nearest_walkable(&self, position: Vec3) -> Option<IVec2> {
+        if self.cells.is_empty() {
+            return None;
+        }
+
+        let local = (Vec2::new(position.x, position.z) - self.origin) / self.cell_size;
+        let cell = local.floor().as_ivec2();
+        (-MAX_SNAP_CELLS..=MAX_SNAP_CELLS)
+            .flat_map(|z| (-MAX_SNAP_CELLS..=MAX_SNAP_CELLS).map(move |x| cell + IVec2::new(x, z)))
+            .filter(|candidate| self.is_walkable(*candidate))
+            .min_by(|a, b| {
+                let distance = |c: &IVec2| (c.as_vec2() + 0.5).distance_squared(local);
+                distance(a).total_cmp(&distance(b))
+            })
+    }
+
+    fn is_walkable(&self, cell: IVec2) -> bool {
+        self.contains(cell) && self.cells[self.index(cell)].is_some()
+    }
+
+    fn contains(&self, cell: IVec2) -> bool {
+        cell.x >= 0 && cell.y >= 0 && cell.x < self.width && cell.y < self.depth
+    }
+
+    fn index(&self, cell: IVec2) -> usize {
+        (cell.y * self.width + cell.x) as usize
+    }
+
+    fn cell(&self, index: usize) -> IVec2 {
+        IVec2::new(index as i32 % self.width, index as i32 / self.width)
+    }
+
+    fn cell_center(&self, cell: IVec2) -> Option<Vec3> {
+        let floor_y = self.cells[self.index(cell)]?;
+        let center = self.origin + (cell.as_vec2() + 0.5) * self.cell_size;
+        Some(Vec3::new(center.x, floor_y, center.y))
+    }
+}
+
+struct OpenNode {
+    estimate: f32,
+    index: usize,
+}
+
+// Reversed so that the BinaryHeap pops the lowest estimate first
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for OpenNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.estimate == other.estimate
+    }
+}
+
+impl Eq for OpenNode {}
+
+fn octile_distance(a: IVec2, b: IVec2) -> f32 {
+    let d = (a - b).abs();
+    let (min, max) = (d.x.min(d.y) as f32, d.x.max(d.y) as f32);
+    max + (std::f32::consts::SQRT_2 - 1.0) * min
+}
+
+#[derive(Resource, Default)]
+pub struct NavigationSettings {
+    pub show_overlay: bool,
+}
+
+pub enum NpcBehavior {
+    Wander { waypoints: Vec<Vec3>, next: usize },
+    FollowPlayer,
+}
+
+impl NpcBehavior {
+    fn from_name(name: &str, waypoints: Vec<Vec3>) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "wander" => Some(NpcBehavior::Wander { waypoints, next: 0 }),
+            "follow" => Some(NpcBehavior::FollowPlayer),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Npc {
+    pub behavior: NpcBehavior,
+    pub speed: f32,
+}
+
+#[derive(Component, Default)]
+pub struct NpcPath {
+    pub points: Vec<Vec3>,
+    repath_timer: f32,
+}
+
+// Custom properties exported from Blender, e.g. {"npc": "wander", "waypoints": [[0, 0, 2], [4, 0, 6]]}
+#[derive(Deserialize)]
+struct NpcExtras {
+    npc: Option<String>,
+    #[serde(default)]
+    waypoints: Vec<[f32; 3]>,
+}
+
+#[derive(Resource)]
+struct NpcAssets {
+    mesh: Handle<Mesh>,
+    material_wander: Handle<StandardMaterial>,
+    material_follow: Handle<StandardMaterial>,
+}
+
+impl NpcAssets {
+    fn bundle(&self, behavior: NpcBehavior, transform: Transform) -> impl Bundle {
+        let material = match behavior {
+            NpcBehavior::Wander { .. } => self.material_wander.clone(),
+            NpcBehavior::FollowPlayer => self.material_follow.clone(),
+        };
+        (
+            PbrBundle {
+                transform,
+                mesh: self.mesh.clone(),
+                material,
+                ..default()
+            },
+            Npc {
+                behavior,
+                speed: NPC_SPEED,
+            },
+            NpcPath::default(),
+        )
+    }
+}
+
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(NavGrid::default())
+            .insert_resource(NavigationSettings::default())
+            .add_startup_system(setup_npc_assets)
+            .add_system(spawn_dungeon_npcs)
+            .add_system(attach_npcs)
+            .add_system(build_nav_grid)
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(update_npc_paths.after(build_nav_grid))
+                    .with_system(move_npcs.after(update_npc_paths)),
+            );
+    }
+}
+
+fn setup_npc_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(NpcAssets {
+        mesh: meshes.add(Mesh::from(shape::Capsule {
+            radius: NPC_RADIUS,
+            depth: AGENT_HEIGHT - NPC_RADIUS * 2.0,
+            ..default()
+        })),
+        material_wander: materials.add(Color::hex(COLOR_NPC_WANDER).unwrap().into()),
+        material_follow: materials.add(Color::hex(COLOR_NPC_FOLLOW).unwrap().into()),
+    });
+}
+
+// Part of the level, so that they go away with it
+fn spawn_dungeon_npcs(
+    mut commands: Commands,
+    level: Res<CurrentLevel>,
+    npc_assets: Res<NpcAssets>,
+    query_new_roots: Query<Entity, Added<LevelRoot>>,
+) {
+    if level.0 != DUNGEON_LEVEL {
+        return;
+    }
+    for root in query_new_roots.iter() {
+        let npcs = [
+            (
+                DUNGEON_WANDER_WAYPOINTS[0],
+                NpcBehavior::Wander {
+                    waypoints: DUNGEON_WANDER_WAYPOINTS.to_vec(),
+                    next: 1,
+                },
+            ),
+            (DUNGEON_FOLLOWER_POS, NpcBehavior::FollowPlayer),
+        ];
+        commands.entity(root).with_children(|parent| {
+            for (position, behavior) in npcs {
+                let transform = Transform::from_translation(position + Vec3::Y * NPC_HEIGHT_2);
+                parent.spawn(npc_assets.bundle(behavior, transform));
+            }
+        });
+    }
+}
+
+fn attach_npcs(
+    mut commands: Commands,
+    npc_assets: Res<NpcAssets>,
+    query_nodes: Query<(Entity, &Name, &Transform, Option<&GltfExtras>), Added<Name>>,
+    query_meshes: Query<&Handle<Mesh>>,
+) {
+    for (entity, name, transform, extras) in query_nodes.iter() {
+        if query_meshes.contains(entity) {
+            continue;
+        }
+
+        let extras: Option<NpcExtras> =
+            extras.and_then(|extras| serde_json::from_str(&extras.value).ok());
+        let (kind, waypoints) = match extras {
+            Some(NpcExtras {
+                npc: Some(kind),
+                waypoints,
+            }) => (Some(kind), waypoints),
+            _ => {
+                // "npc_follow.001" -> "follow"
+                let name = name.to_ascii_lowercase();
+                let kind = name
+                    .strip_prefix(NPC_NAME_PREFIX)
+                    .and_then(|kind| kind.split('.').next())
+                    .map(str::to_string);
+                (kind, Vec::new())
+            }
+        };
+        let waypoints = waypoints.into_iter().map(Vec3::from).collect();
+        let Some(behavior) = kind.and_then(|kind| NpcBehavior::from_name(&kind, waypoints)) else {
+            continue;
+        };
+
+        commands
+            .entity(entity)
+            .insert(npc_assets.bundle(behavior, *transform));
+    }
+}
+
+// Rebuilds the grid whenever meshes of the dungeon scene get their bounds. NPCs and pickups are
+// part of the level too, but they don't block anyone
+fn build_nav_grid(
+    mut nav_grid: ResMut<NavGrid>,
+    query_new_bounds: Query<Entity, (Added<Aabb>, With<Handle<Mesh>>)>,
+    query_bounds: Query<(Entity, &Aabb, &GlobalTransform), With<Handle<Mesh>>>,
+    query_parents: Query<&Parent>,
+    query_scenes: Query<(), With<Handle<Scene>>>,
+    query_npcs: Query<(), With<Npc>>,
+    query_pickups: Query<(), With<Pickup>>,
+) {
+    let in_scene = |mut entity: Entity| loop {
+        if query_npcs.contains(entity) || query_pickups.contains(entity) {
+            return false;
+        }
+        if query_scenes.contains(entity) {
+            return true;
+        }
+        match query_parents.get(entity) {
+            Ok(parent) => entity = parent.get(),
+            Err(_) => return false,
+        }
+    };
+
+    if !query_new_bounds.iter().any(in_scene) {
+        return;
+    }
+
+    let mut floors = Vec::new();
+    let mut obstacles = Vec::new();
+    for (entity, aabb, transform) in query_bounds.iter() {
+        if !in_scene(entity) {
+            continue;
+        }
+        let bounds = Bounds::from_aabb(aabb, transform);
+        if bounds.max.y - bounds.min.y <= STEP_HEIGHT && bounds.max.y <= STEP_HEIGHT {
+            floors.push(bounds);
+        } else {
+            obstacles.push(bounds);
+        }
+    }
+
+    *nav_grid = NavGrid::build(&floors, &obstacles, CELL_SIZE);
+    debug!(
+        "Built navigation grid with {} walkable cells",
+        nav_grid.walkable_cells().count()
+    );
+}
+
+fn update_npc_paths(
+    time: Res<Time>,
+    nav_grid: Res<NavGrid>,
+    query_player: Query<&GlobalTransform, With<Player>>,
+    mut query_npcs: Query<(&mut Npc, &mut NpcPath, &Transform)>,
+) {
+    for (mut npc, mut path, transform) in query_npcs.iter_mut() {
+        let position = transform.translation;
+        path.repath_timer -= time.delta_seconds();
+
+        match &mut npc.behavior {
+            NpcBehavior::Wander { waypoints, next } => {
+                if !path.points.is_empty() || waypoints.is_empty() {
+                    continue;
+                }
+                let goal = waypoints[*next];
+                *next = (*next + 1) % waypoints.len();
+                path.points = nav_grid.find_path(position, goal).unwrap_or_default();
+            }
+            NpcBehavior::FollowPlayer => {
+                // Follows whichever player is closest
+                let Some(player_position) = query_player
+                    .iter()
+                    .map(|player| player.translation())
+                    .min_by(|a, b| a.distance(position).total_cmp(&b.distance(position)))
+                else {
+                    continue;
+                };
+                if position.distance(player_position) <= NPC_FOLLOW_DISTANCE {
+                    path.points.clear();
+                    continue;
+                }
+                if path.repath_timer > 0.0 {
+                    continue;
+                }
+                path.repath_timer = NPC_REPATH_INTERVAL;
+                path.points = nav_grid
+                    .find_path(position, player_position)
+                    .unwrap_or_default();
+            }
+        }
+    }
+}
+
+fn move_npcs(time: Res<Time>, mut query_npcs: Query<(&Npc, &mut NpcPath, &mut Transform)>) {
+    for (npc, mut path, mut transform) in query_npcs.iter_mut() {
+        let Some(point) = path.points.first() else {
+            continue;
+        };
+
+        let target = *point + Vec3::Y * NPC_HEIGHT_2;
+        let to_target = target - transform.translation;
+        let step = npc.speed * time.delta_seconds();
+        if to_target.length() <= step.max(NPC_WAYPOINT_TOLERANCE) {
+            transform.translation = target;
+            path.points.remove(0);
+            continue;
+        }
+
+        transform.translation += to_target.normalize() * step;
+        let facing = Vec3::new(target.x, transform.translation.y, target.z);
+        if facing != transform.translation {
+            transform.look_at(facing, Vec3::Y);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds(min: (f32, f32, f32), max: (f32, f32, f32)) -> Bounds {
+        Bounds {
+            min: Vec3::new(min.0, min.1, min.2),
+            max: Vec3::new(max.0, max.1, max.2),
+        }
+    }
+
+    fn floor() -> Bounds {
+        bounds((0.0, 0.0, 0.0), (10.0, 0.0, 10.0))
+    }
+
+    #[test]
+    fn straight_path_on_open_floor() {
+        let grid = NavGrid::build(&[floor()], &[], 1.0);
+        let path = grid
+            .find_path(Vec3::new(0.5, 0.0, 0.5), Vec3::new(0.5, 0.0, 9.5))
+            .unwrap();
+        assert_eq!(path, vec![Vec3::new(0.5, 0.0, 9.5)]);
+    }
+
+    #[test]
+    fn path_goes_through_gap_in_wall() {
+        // Wall across the floor at z = 5, with a gap at x = 7..10
+        let wall = bounds((-1.0, 0.0, 4.5), (7.0, 2.0, 5.5));
+        let grid = NavGrid::build(&[floor()], &[wall], 1.0);
+        let path = grid
+            .find_path(Vec3::new(0.5, 0.0, 0.5), Vec3::new(0.5, 0.0, 9.5))
+            .unwrap();
+
+        assert_eq!(path.last(), Some(&Vec3::new(0.5, 0.0, 9.5)));
+        assert!(path.iter().any(|point| point.x > 7.0));
+        assert!(path
+            .windows(2)
+            .all(|segment| segment[0].distance(segment[1]) > 0.0));
+    }
+
+    #[test]
+    fn no_path_through_closed_wall() {
+        let wall = bounds((-1.0, 0.0, 4.5), (11.0, 2.0, 5.5));
+        let grid = NavGrid::build(&[floor()], &[wall], 1.0);
+        assert!(grid
+            .find_path(Vec3::new(0.5, 0.0, 0.5), Vec3::new(0.5, 0.0, 9.5))
+            .is_none());
+    }
+
+    #[test]
+    fn low_obstacles_and_overhangs_are_walkable() {
+        let step = bounds((-1.0, 0.0, 4.9), (11.0, 0.2, 5.1));
+        let beam = bounds((-1.0, 2.5, 4.9), (11.0, 3.0, 5.1));
+        let grid = NavGrid::build(&[floor()], &[step, beam], 1.0);
+        assert_eq!(grid.walkable_cells().count(), 100);
+    }
+
+    #[test]
+    fn snaps_to_nearest_walkable_cell() {
+        let grid = NavGrid::build(&[floor()], &[], 1.0);
+        let path = grid
+            .find_path(Vec3::new(-1.5, 0.0, 0.5), Vec3::new(5.5, 3.0, 11.2))
+            .unwrap();
+        assert_eq!(path.last(), Some(&Vec3::new(5.5, 0.0, 9.5)));
+        assert!(grid
+            .find_path(Vec3::new(-50.0, 0.0, 0.0), Vec3::ONE)
+            .is_none());
+    }
+}
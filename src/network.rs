@@ -0,0 +1,938 @@
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    io::ErrorKind,
+    net::{Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+};
+
+use crate::{
+    health::{Checkpoint, RespawnCause, Respawned, SpawnPoint},
+    player::{
+        player_translation, spawn_box_avatar, HeadState, PlayerMotion, PrimaryPlayer,
+        PLAYER_INITIAL_POS,
+    },
+};
+
+pub const DEFAULT_PORT: u16 = 5000;
+const MAX_PACKET_SIZE: usize = 16 * 1024;
+const SNAPSHOT_INTERVAL: f32 = 0.05;
+const INPUT_INTERVAL: f32 = 1.0 / 30.0;
+const HELLO_INTERVAL: f32 = 1.0;
+// Clients that haven't sent anything for this long are dropped
+const CLIENT_TIMEOUT: f64 = 5.0;
+// Remote players are shown this far in the past, so that there are two snapshots to blend
+const INTERPOLATION_DELAY: f64 = 0.1;
+const SNAPSHOT_BUFFER: usize = 32;
+// The local player is moved to where the server has them when they get further apart than this
+const RECONCILE_DISTANCE: f32 = 1.0;
+// How far from a spawn point or a checkpoint a client may teleport its player
+const TELEPORT_TOLERANCE: f32 = 0.5;
+// Inputs kept for replaying on top of the server's position, older ones are dropped
+const INPUT_HISTORY: usize = 64;
+
+// What a client wants its player to do, the server moves the player accordingly
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug, PartialEq)]
+pub struct NetInput {
+    pub movement_axes: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+// Why a client moved its player somewhere without walking there
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum TeleportKind {
+    // Back to a spawn point or a checkpoint after dying, checked by the server
+    Respawn,
+    // To the start of a new level or where a loaded game left off, which the server can't check
+    Placed,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+enum ClientMessage {
+    Hello,
+    Input {
+        sequence: u32,
+        input: NetInput,
+        // Respawning moves the player on the client, sent until the server has seen it
+        teleport: Option<(u32, [f32; 3], TeleportKind)>,
+    },
+    Goodbye,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PlayerState {
+    pub id: u32,
+    pub translation: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    // Last input of this player the server has applied
+    pub last_sequence: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+enum ServerMessage {
+    Welcome {
+        id: u32,
+    },
+    Snapshot {
+        time: f64,
+        players: Vec<PlayerState>,
+    },
+}
+
+fn send_message<T: Serialize>(socket: &UdpSocket, address: SocketAddr, message: &T) {
+    let Ok(bytes) = serde_json::to_vec(message) else {
+        return;
+    };
+    if let Err(error) = socket.send_to(&bytes, address) {
+        // Nobody listening on the other end is reported on the next receive, not worth a warning
+        if error.kind() != ErrorKind::ConnectionRefused {
+            warn!("Could not send to {address}: {error}");
+        }
+    }
+}
+
+// Everything that arrived since the last call
+fn receive_messages<T: for<'de> Deserialize<'de>>(socket: &UdpSocket) -> Vec<(SocketAddr, T)> {
+    let mut messages = Vec::new();
+    let mut buffer = [0; MAX_PACKET_SIZE];
+    loop {
+        match socket.recv_from(&mut buffer) {
+            Ok((size, address)) => {
+                if let Ok(message) = serde_json::from_slice(&buffer[..size]) {
+                    messages.push((address, message));
+                }
+            }
+            Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+            // On some platforms an unreachable peer shows up as an error here, just carry on
+            Err(error) if error.kind() == ErrorKind::ConnectionReset => continue,
+            Err(error) => {
+                warn!("Could not receive: {error}");
+                break;
+            }
+        }
+    }
+    messages
+}
+
+struct ServerClient {
+    id: u32,
+    translation: Vec3,
+    input: NetInput,
+    last_sequence: u32,
+    last_heard: f64,
+}
+
+// Owns the transforms of all the players, moving them with the input their clients send
+#[derive(Resource)]
+pub struct NetServer {
+    socket: UdpSocket,
+    clients: HashMap<SocketAddr, ServerClient>,
+    next_id: u32,
+    time: f64,
+    snapshot_timer: f32,
+    // Where clients may respawn their players besides the start of the hand-made dungeon: spawn
+    // points and checkpoints. Empty without a level, then respawns aren't checked
+    destinations: Vec<Vec3>,
+}
+
+impl NetServer {
+    pub fn bind(address: SocketAddr) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(NetServer {
+            socket,
+            clients: HashMap::default(),
+            next_id: 1,
+            time: 0.0,
+            snapshot_timer: 0.0,
+            destinations: Vec::new(),
+        })
+    }
+
+    pub fn set_destinations(&mut self, destinations: impl IntoIterator<Item = Vec3>) {
+        self.destinations.clear();
+        self.destinations.extend(destinations);
+    }
+
+    // Respawns that don't end up at a known destination go to the nearest one instead, so that a
+    // client can't put its player anywhere
+    fn respawn_destination(&self, requested: Vec3) -> Vec3 {
+        if self.destinations.is_empty() {
+            return requested;
+        }
+        let nearest =
+            self.destinations
+                .iter()
+                .copied()
+                .fold(PLAYER_INITIAL_POS, |nearest, destination| {
+                    if destination.distance(requested) < nearest.distance(requested) {
+                        destination
+                    } else {
+                        nearest
+                    }
+                });
+        if nearest.distance(requested) <= TELEPORT_TOLERANCE {
+            requested
+        } else {
+            warn!("Rejected a respawn at {requested}, moving to {nearest} instead");
+            nearest
+        }
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt as f64;
+
+        for (address, message) in receive_messages::<ClientMessage>(&self.socket) {
+            self.handle_message(address, message);
+        }
+
+        for client in self.clients.values_mut() {
+            let rotation = Quat::from_rotation_y(client.input.yaw);
+            let movement_axes = Vec3::from(client.input.movement_axes).clamp_length_max(1.0);
+            client.translation += player_translation(rotation, movement_axes, dt);
+        }
+
+        let time = self.time;
+        self.clients.retain(|_, client| {
+            let alive = time - client.last_heard < CLIENT_TIMEOUT;
+            if !alive {
+                info!("Client {} timed out", client.id);
+            }
+            alive
+        });
+
+        self.snapshot_timer -= dt;
+        if self.snapshot_timer <= 0.0 {
+            self.snapshot_timer = SNAPSHOT_INTERVAL;
+            self.send_snapshot();
+        }
+    }
+
+    fn handle_message(&mut self, address: SocketAddr, message: ClientMessage) {
+        match message {
+            ClientMessage::Hello => {
+                let client = self.clients.entry(address).or_insert_with(|| {
+                    let id = self.next_id;
+                    self.next_id += 1;
+                    info!("Client {id} connected from {address}");
+                    ServerClient {
+                        id,
+                        translation: PLAYER_INITIAL_POS,
+                        input: NetInput::default(),
+                        last_sequence: 0,
+                        last_heard: self.time,
+                    }
+                });
+                client.last_heard = self.time;
+                let welcome = ServerMessage::Welcome { id: client.id };
+                send_message(&self.socket, address, &welcome);
+            }
+            ClientMessage::Input {
+                sequence,
+                input,
+                teleport,
+            } => {
+                let teleport = teleport.map(|(sequence, translation, kind)| {
+                    let translation = Vec3::from(translation);
+                    match kind {
+                        TeleportKind::Respawn => (sequence, self.respawn_destination(translation)),
+                        TeleportKind::Placed => (sequence, translation),
+                    }
+                });
+                let Some(client) = self.clients.get_mut(&address) else {
+                    return;
+                };
+                client.last_heard = self.time;
+                // Packets can arrive out of order
+                if sequence <= client.last_sequence {
+                    return;
+                }
+                if let Some((teleport_sequence, translation)) = teleport {
+                    if teleport_sequence > client.last_sequence {
+                        client.translation = translation;
+                    }
+                }
+                client.input = input;
+                client.last_sequence = sequence;
+            }
+            ClientMessage::Goodbye => {
+                if let Some(client) = self.clients.remove(&address) {
+                    info!("Client {} disconnected", client.id);
+                }
+            }
+        }
+    }
+
+    fn send_snapshot(&self) {
+        let snapshot = ServerMessage::Snapshot {
+            time: self.time,
+            players: self
+                .clients
+                .values()
+                .map(|client| PlayerState {
+                    id: client.id,
+                    translation: client.translation.to_array(),
+                    yaw: client.input.yaw,
+                    pitch: client.input.pitch,
+                    last_sequence: client.last_sequence,
+                })
+                .collect(),
+        };
+        for address in self.clients.keys() {
+            send_message(&self.socket, *address, &snapshot);
+        }
+    }
+}
+
+struct Snapshot {
+    time: f64,
+    players: Vec<PlayerState>,
+}
+
+#[derive(Resource)]
+pub struct NetClient {
+    socket: UdpSocket,
+    server: SocketAddr,
+    id: Option<u32>,
+    sequence: u32,
+    pending_teleport: Option<(u32, [f32; 3], TeleportKind)>,
+    // Inputs sent to the server that it may not have applied yet
+    inputs: VecDeque<(u32, NetInput)>,
+    snapshots: VecDeque<Snapshot>,
+    // Local time at which the newest snapshot arrived
+    last_received: f64,
+    hello_timer: f32,
+}
+
+impl NetClient {
+    pub fn connect(server: SocketAddr) -> std::io::Result<Self> {
+        let local: SocketAddr = if server.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            "[::]:0".parse().unwrap()
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.set_nonblocking(true)?;
+        Ok(NetClient {
+            socket,
+            server,
+            id: None,
+            sequence: 0,
+            pending_teleport: None,
+            inputs: VecDeque::new(),
+            snapshots: VecDeque::new(),
+            last_received: 0.0,
+            hello_timer: 0.0,
+        })
+    }
+
+    pub fn id(&self) -> Option<u32> {
+        self.id
+    }
+
+    pub fn update(&mut self, now: f64, dt: f32) {
+        if self.id.is_none() {
+            self.hello_timer -= dt;
+            if self.hello_timer <= 0.0 {
+                self.hello_timer = HELLO_INTERVAL;
+                send_message(&self.socket, self.server, &ClientMessage::Hello);
+            }
+        }
+
+        for (address, message) in receive_messages::<ServerMessage>(&self.socket) {
+            if address != self.server {
+                continue;
+            }
+            match message {
+                ServerMessage::Welcome { id } => {
+                    if self.id.is_none() {
+                        info!("Connected to {} as player {id}", self.server);
+                    }
+                    self.id = Some(id);
+                }
+                ServerMessage::Snapshot { time, players } => {
+                    if self.snapshots.back().is_some_and(|last| last.time >= time) {
+                        continue;
+                    }
+                    self.snapshots.push_back(Snapshot { time, players });
+                    if self.snapshots.len() > SNAPSHOT_BUFFER {
+                        self.snapshots.pop_front();
+                    }
+                    self.last_received = now;
+                }
+            }
+        }
+
+        // The teleport has been applied once the server has seen an input sent after it
+        if let (Some((sequence, _, _)), Some(state)) = (self.pending_teleport, self.own_state()) {
+            if state.last_sequence >= sequence {
+                self.pending_teleport = None;
+            }
+        }
+        if let Some(acknowledged) = self.own_state().map(|state| state.last_sequence) {
+            while self
+                .inputs
+                .front()
+                .is_some_and(|(sequence, _)| *sequence <= acknowledged)
+            {
+                self.inputs.pop_front();
+            }
+        }
+    }
+
+    pub fn send_input(&mut self, input: NetInput) {
+        if self.id.is_none() {
+            return;
+        }
+        self.sequence += 1;
+        self.inputs.push_back((self.sequence, input));
+        if self.inputs.len() > INPUT_HISTORY {
+            self.inputs.pop_front();
+        }
+        let message = ClientMessage::Input {
+            sequence: self.sequence,
+            input,
+            teleport: self.pending_teleport,
+        };
+        send_message(&self.socket, self.server, &message);
+    }
+
+    pub fn teleport(&mut self, translation: Vec3, kind: TeleportKind) {
+        self.pending_teleport = Some((self.sequence + 1, translation.to_array(), kind));
+    }
+
+    pub fn disconnect(&mut self) {
+        if self.id.take().is_some() {
+            send_message(&self.socket, self.server, &ClientMessage::Goodbye);
+        }
+    }
+
+    // Where the server has this client's player in the newest snapshot
+    pub fn own_state(&self) -> Option<&PlayerState> {
+        let id = self.id?;
+        let snapshot = self.snapshots.back()?;
+        snapshot.players.iter().find(|player| player.id == id)
+    }
+
+    // Where this client's player should be now: the server's position with the inputs it hasn't
+    // applied yet played on top, each for as long as it was sent for
+    pub fn predicted_translation(&self) -> Option<Vec3> {
+        let state = self.own_state()?;
+        let replayed = self
+            .inputs
+            .iter()
+            .filter(|(sequence, _)| *sequence > state.last_sequence)
+            .map(|(_, input)| {
+                let rotation = Quat::from_rotation_y(input.yaw);
+                let movement_axes = Vec3::from(input.movement_axes).clamp_length_max(1.0);
+                player_translation(rotation, movement_axes, INPUT_INTERVAL)
+            })
+            .sum::<Vec3>();
+        Some(Vec3::from(state.translation) + replayed)
+    }
+
+    // Every other player, blended between the two snapshots around the delayed time
+    pub fn remote_players(&self, now: f64) -> Vec<PlayerState> {
+        let Some(newest) = self.snapshots.back() else {
+            return Vec::new();
+        };
+        let render_time = newest.time + (now - self.last_received) - INTERPOLATION_DELAY;
+
+        let next = self
+            .snapshots
+            .iter()
+            .position(|snapshot| snapshot.time >= render_time);
+        let (before, after, t) = match next {
+            Some(0) | None => {
+                let snapshot = if next.is_some() {
+                    self.snapshots.front()
+                } else {
+                    Some(newest)
+                };
+                (snapshot, None, 0.0)
+            }
+            Some(i) => {
+                let (before, after) = (&self.snapshots[i - 1], &self.snapshots[i]);
+                let t = (render_time - before.time) / (after.time - before.time);
+                (Some(before), Some(after), t as f32)
+            }
+        };
+        let Some(before) = before else {
+            return Vec::new();
+        };
+
+        before
+            .players
+            .iter()
+            .filter(|player| Some(player.id) != self.id)
+            .map(|player| {
+                let Some(next) =
+                    after.and_then(|after| after.players.iter().find(|p| p.id == player.id))
+                else {
+                    return player.clone();
+                };
+                PlayerState {
+                    translation: Vec3::from(player.translation)
+                        .lerp(Vec3::from(next.translation), t)
+                        .to_array(),
+                    yaw: lerp_angle(player.yaw, next.yaw, t),
+                    pitch: player.pitch + (next.pitch - player.pitch) * t,
+                    ..next.clone()
+                }
+            })
+            .collect()
+    }
+}
+
+fn lerp_angle(from: f32, to: f32, t: f32) -> f32 {
+    let delta =
+        (to - from + std::f32::consts::PI).rem_euclid(std::f32::consts::TAU) - std::f32::consts::PI;
+    from + delta * t
+}
+
+// Command line options: `--server [address]` runs a headless server (see main.rs),
+// `--connect <address>` joins one and `--loopback [port]` runs both in this process
+pub enum NetworkMode {
+    Connect(SocketAddr),
+    Loopback(u16),
+}
+
+impl NetworkMode {
+    pub fn from_args() -> Option<Self> {
+        let args: Vec<String> = std::env::args().collect();
+        let value = |name: &str| {
+            let i = args.iter().position(|arg| arg == name)?;
+            Some(
+                args.get(i + 1)
+                    .filter(|value| !value.starts_with("--"))
+                    .cloned(),
+            )
+        };
+
+        if let Some(address) = value("--connect") {
+            let address = address.unwrap_or_default();
+            match resolve(&address) {
+                Some(address) => return Some(NetworkMode::Connect(address)),
+                None => error!("Could not resolve the server address {address:?}"),
+            }
+        }
+        if let Some(port) = value("--loopback") {
+            let port = port.and_then(|port| port.parse().ok());
+            return Some(NetworkMode::Loopback(port.unwrap_or(DEFAULT_PORT)));
+        }
+        None
+    }
+}
+
+// The address of the headless server from `--server [address]`
+pub fn server_address_from_args() -> Option<SocketAddr> {
+    let args: Vec<String> = std::env::args().collect();
+    let i = args.iter().position(|arg| arg == "--server")?;
+    match args.get(i + 1).filter(|value| !value.starts_with("--")) {
+        Some(address) => resolve(address),
+        None => Some((Ipv4Addr::UNSPECIFIED, DEFAULT_PORT).into()),
+    }
+}
+
+// Accepts "host:port" or just "host", which uses the default port
+fn resolve(address: &str) -> Option<SocketAddr> {
+    let mut addresses = match address.to_socket_addrs() {
+        Ok(addresses) => addresses,
+        Err(_) => (address, DEFAULT_PORT).to_socket_addrs().ok()?,
+    };
+    addresses.next()
+}
+
+// Runs the server, either headless or next to a client for the loopback mode
+pub struct NetworkServerPlugin {
+    pub address: SocketAddr,
+}
+
+impl Plugin for NetworkServerPlugin {
+    fn build(&self, app: &mut App) {
+        match NetServer::bind(self.address) {
+            Ok(server) => {
+                info!("Server listening on {}", self.address);
+                app.insert_resource(server)
+                    .add_system(update_server_destinations.before(update_server))
+                    .add_system(update_server);
+            }
+            Err(error) => error!("Could not start the server on {}: {error}", self.address),
+        }
+    }
+}
+
+// Only a server running next to a client has a level to take the destinations from, a headless
+// one lets players respawn anywhere
+fn update_server_destinations(
+    mut server: ResMut<NetServer>,
+    query_spawn_points: Query<&GlobalTransform, With<SpawnPoint>>,
+    query_checkpoints: Query<&GlobalTransform, With<Checkpoint>>,
+) {
+    server.set_destinations(
+        query_spawn_points
+            .iter()
+            .chain(query_checkpoints.iter())
+            .map(|transform| transform.translation()),
+    );
+}
+
+fn update_server(time: Res<Time>, mut server: ResMut<NetServer>) {
+    server.update(time.delta_seconds());
+}
+
+// The client side, only does anything when started with --connect or --loopback
+pub struct NetworkPlugin;
+
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut App) {
+        let server = match NetworkMode::from_args() {
+            None => return,
+            Some(NetworkMode::Connect(address)) => address,
+            Some(NetworkMode::Loopback(port)) => {
+                let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+                app.add_plugin(NetworkServerPlugin { address });
+                address
+            }
+        };
+
+        match NetClient::connect(server) {
+            Ok(client) => {
+                app.insert_resource(client)
+                    .add_system(update_client)
+                    .add_system(send_client_input.after(update_client))
+                    .add_system(reconcile_primary_player.after(update_client))
+                    .add_system(update_remote_players.after(update_client))
+                    .add_system_to_stage(CoreStage::Last, disconnect_client);
+            }
+            Err(error) => error!("Could not connect to {server}: {error}"),
+        }
+    }
+}
+
+// Another client's player, shown with the box avatar
+#[derive(Component)]
+struct RemotePlayer {
+    id: u32,
+    head: Entity,
+}
+
+fn update_client(time: Res<Time>, mut client: ResMut<NetClient>) {
+    client.update(time.elapsed_seconds_f64(), time.delta_seconds());
+}
+
+fn send_client_input(
+    time: Res<Time>,
+    mut client: ResMut<NetClient>,
+    mut respawned_events: EventReader<Respawned>,
+    query_primary: Query<(Entity, &Transform, &PlayerMotion, &Children), With<PrimaryPlayer>>,
+    query_heads: Query<&GlobalTransform, With<HeadState>>,
+    mut input_timer: Local<f32>,
+) {
+    let Ok((entity, transform, motion, children)) = query_primary.get_single() else {
+        return;
+    };
+    if let Some(event) = respawned_events
+        .iter()
+        .rev()
+        .find(|event| event.player == entity)
+    {
+        let kind = match event.cause {
+            RespawnCause::Died => TeleportKind::Respawn,
+            RespawnCause::LevelStart | RespawnCause::Loaded => TeleportKind::Placed,
+        };
+        client.teleport(transform.translation, kind);
+    }
+
+    *input_timer -= time.delta_seconds();
+    if *input_timer > 0.0 {
+        return;
+    }
+    *input_timer = INPUT_INTERVAL;
+
+    let Some(head) = children
+        .iter()
+        .find_map(|child| query_heads.get(*child).ok())
+    else {
+        return;
+    };
+    let forward = head.forward();
+    client.send_input(NetInput {
+        movement_axes: motion.movement_axes.to_array(),
+        yaw: (-forward.x).atan2(-forward.z),
+        pitch: forward.y.clamp(-1.0, 1.0).asin(),
+    });
+}
+
+// The player moves right away on this client, and is put back if the server disagrees even after
+// replaying the inputs it hasn't seen yet
+fn reconcile_primary_player(
+    client: Res<NetClient>,
+    mut query_primary: Query<&mut Transform, With<PrimaryPlayer>>,
+) {
+    if client.pending_teleport.is_some() {
+        return;
+    }
+    let (Some(predicted), Ok(mut transform)) = (
+        client.predicted_translation(),
+        query_primary.get_single_mut(),
+    ) else {
+        return;
+    };
+    if transform.translation.distance(predicted) > RECONCILE_DISTANCE {
+        transform.translation = predicted;
+    }
+}
+
+fn update_remote_players(
+    mut commands: Commands,
+    time: Res<Time>,
+    client: Res<NetClient>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    query_remote: Query<(Entity, &RemotePlayer)>,
+    mut query_transforms: Query<&mut Transform>,
+) {
+    let states = client.remote_players(time.elapsed_seconds_f64());
+
+    for (entity, remote) in query_remote.iter() {
+        if !states.iter().any(|state| state.id == remote.id) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    for state in states {
+        let body_transform = Transform::from_translation(Vec3::from(state.translation))
+            .with_rotation(Quat::from_rotation_y(state.yaw));
+        let head_rotation = Quat::from_rotation_x(state.pitch);
+
+        let remote = query_remote
+            .iter()
+            .find(|(_, remote)| remote.id == state.id);
+        let Some((entity, remote)) = remote else {
+            let avatar =
+                spawn_box_avatar(&mut commands, &mut meshes, &mut materials, body_transform);
+            commands.entity(avatar.body).insert((
+                Name::new(format!("Remote player {}", state.id)),
+                RemotePlayer {
+                    id: state.id,
+                    head: avatar.head,
+                },
+            ));
+            continue;
+        };
+
+        if let Ok(mut transform) = query_transforms.get_mut(entity) {
+            *transform = body_transform;
+        }
+        if let Ok(mut transform) = query_transforms.get_mut(remote.head) {
+            transform.rotation = head_rotation;
+        }
+    }
+}
+
+fn disconnect_client(mut client: ResMut<NetClient>, exit_events: EventReader<bevy::app::AppExit>) {
+    if !exit_events.is_empty() {
+        client.disconnect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time::Duration};
+
+    // Runs a server and a client over the loopback interface until `done` or a timeout
+    fn run_loopback(
+        server: &mut NetServer,
+        client: &mut NetClient,
+        mut done: impl FnMut(&mut NetClient) -> bool,
+    ) -> bool {
+        let dt = 0.01;
+        for step in 0..500 {
+            server.update(dt);
+            client.update(step as f64 * dt as f64, dt);
+            if done(client) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        false
+    }
+
+    fn loopback() -> (NetServer, NetClient) {
+        let server = NetServer::bind((Ipv4Addr::LOCALHOST, 0).into()).unwrap();
+        let client = NetClient::connect(server.local_addr().unwrap()).unwrap();
+        (server, client)
+    }
+
+    #[test]
+    fn client_gets_welcomed() {
+        let (mut server, mut client) = loopback();
+        assert!(run_loopback(&mut server, &mut client, |client| client
+            .id()
+            .is_some()));
+    }
+
+    #[test]
+    fn server_moves_player_with_input() {
+        let (mut server, mut client) = loopback();
+        assert!(run_loopback(&mut server, &mut client, |client| client
+            .own_state()
+            .is_some()));
+
+        let start = Vec3::from(client.own_state().unwrap().translation);
+        let moved = run_loopback(&mut server, &mut client, |client| {
+            client.send_input(NetInput {
+                movement_axes: [0.0, 0.0, 1.0],
+                yaw: 0.0,
+                ..default()
+            });
+            client
+                .own_state()
+                .is_some_and(|state| Vec3::from(state.translation).z < start.z - 0.5)
+        });
+        assert!(moved, "the player should walk towards -Z");
+    }
+
+    #[test]
+    fn teleport_is_applied_once() {
+        let (mut server, mut client) = loopback();
+        let target = Vec3::new(10.0, PLAYER_INITIAL_POS.y, 10.0);
+        server.set_destinations([target]);
+        assert!(run_loopback(&mut server, &mut client, |client| client
+            .own_state()
+            .is_some()));
+
+        client.teleport(target, TeleportKind::Respawn);
+        let teleported = run_loopback(&mut server, &mut client, |client| {
+            client.send_input(NetInput::default());
+            client.pending_teleport.is_none()
+        });
+        assert!(teleported);
+        let state = client.own_state().unwrap();
+        assert!(Vec3::from(state.translation).distance(target) < 0.01);
+    }
+
+    #[test]
+    fn remote_players_are_interpolated() {
+        let (mut server, mut client) = loopback();
+        let mut other = NetClient::connect(server.local_addr().unwrap()).unwrap();
+        let mut step = 0;
+        let connected = run_loopback(&mut server, &mut client, |client| {
+            step += 1;
+            other.update(step as f64 * 0.01, 0.01);
+            other.send_input(NetInput {
+                movement_axes: [1.0, 0.0, 0.0],
+                ..default()
+            });
+            client.snapshots.len() >= 4 && !client.remote_players(step as f64 * 0.01).is_empty()
+        });
+        assert!(connected);
+
+        let remote = client.remote_players(step as f64 * 0.01);
+        assert_eq!(remote.len(), 1);
+        assert_eq!(Some(remote[0].id), other.id());
+    }
+
+    #[test]
+    fn angles_are_blended_the_short_way() {
+        let angle = lerp_angle(3.0, -3.0, 0.5);
+        assert!(angle.abs() > 3.0);
+    }
+
+    #[test]
+    fn teleport_only_goes_to_known_destinations() {
+        let (mut server, mut client) = loopback();
+        let checkpoint = Vec3::new(10.0, PLAYER_INITIAL_POS.y, 10.0);
+        server.set_destinations([checkpoint]);
+        assert!(run_loopback(&mut server, &mut client, |client| client
+            .own_state()
+            .is_some()));
+
+        client.teleport(Vec3::new(40.0, 50.0, 40.0), TeleportKind::Respawn);
+        assert!(run_loopback(&mut server, &mut client, |client| {
+            client.send_input(NetInput::default());
+            client.pending_teleport.is_none()
+        }));
+        let state = client.own_state().unwrap();
+        assert!(Vec3::from(state.translation).distance(checkpoint) < 0.01);
+
+        let near_start = PLAYER_INITIAL_POS + Vec3::new(0.2, 0.0, 0.0);
+        client.teleport(near_start, TeleportKind::Respawn);
+        assert!(run_loopback(&mut server, &mut client, |client| {
+            client.send_input(NetInput::default());
+            client.pending_teleport.is_none()
+        }));
+        let state = client.own_state().unwrap();
+        assert!(Vec3::from(state.translation).distance(near_start) < 0.01);
+    }
+
+    #[test]
+    fn loaded_games_and_new_levels_place_the_player_anywhere() {
+        let (mut server, mut client) = loopback();
+        server.set_destinations([Vec3::new(10.0, PLAYER_INITIAL_POS.y, 10.0)]);
+        assert!(run_loopback(&mut server, &mut client, |client| client
+            .own_state()
+            .is_some()));
+
+        let saved = Vec3::new(40.0, 3.0, -25.0);
+        client.teleport(saved, TeleportKind::Placed);
+        assert!(run_loopback(&mut server, &mut client, |client| {
+            client.send_input(NetInput::default());
+            client.pending_teleport.is_none()
+        }));
+        let state = client.own_state().unwrap();
+        assert!(Vec3::from(state.translation).distance(saved) < 0.01);
+    }
+
+    #[test]
+    fn respawns_are_not_checked_without_a_level() {
+        let (mut server, mut client) = loopback();
+        assert!(run_loopback(&mut server, &mut client, |client| client
+            .own_state()
+            .is_some()));
+
+        let checkpoint = Vec3::new(-30.0, PLAYER_INITIAL_POS.y, 12.0);
+        client.teleport(checkpoint, TeleportKind::Respawn);
+        assert!(run_loopback(&mut server, &mut client, |client| {
+            client.send_input(NetInput::default());
+            client.pending_teleport.is_none()
+        }));
+        let state = client.own_state().unwrap();
+        assert!(Vec3::from(state.translation).distance(checkpoint) < 0.01);
+    }
+
+    #[test]
+    fn prediction_replays_unacknowledged_inputs() {
+        let (mut server, mut client) = loopback();
+        assert!(run_loopback(&mut server, &mut client, |client| client
+            .own_state()
+            .is_some()));
+        let acknowledged = Vec3::from(client.own_state().unwrap().translation);
+        assert_eq!(client.predicted_translation(), Some(acknowledged));
+
+        // The server doesn't run, so none of these are applied yet
+        let input = NetInput {
+            movement_axes: [0.0, 0.0, 1.0],
+            ..default()
+        };
+        for _ in 0..10 {
+            client.send_input(input);
+        }
+        let step = player_translation(Quat::IDENTITY, Vec3::Z, INPUT_INTERVAL);
+        let predicted = client.predicted_translation().unwrap();
+        assert!(predicted.distance(acknowledged + step * 10.0) < 1e-4);
+
+        // Inputs the server has applied are dropped
+        assert!(run_loopback(&mut server, &mut client, |client| {
+            client.inputs.is_empty()
+        }));
+    }
+}
@@ -1,37 +1,81 @@
 use bevy::{input::mouse::MouseMotion, prelude::*, window::close_when_requested};
 use std::f32::consts::FRAC_PI_2;
 
-use crate::{ui::CameraSettings, AppState};
-
-const PLAYER_SPEED: f32 = 3.0;
+use crate::{
+    camera::{CameraRig, FirstPersonCamera, ThirdPersonCamera},
+    health::{Dead, Health},
+    items::Inventory,
+    ui::{CameraSettings, CameraType},
+    AppState,
+};
+
+pub const PLAYER_SPEED: f32 = 3.0;
 const PLAYER_HEIGHT: f32 = 1.8;
-const PLAYER_HEIGHT_2: f32 = PLAYER_HEIGHT / 2.0;
+pub const PLAYER_HEIGHT_2: f32 = PLAYER_HEIGHT / 2.0;
 const PLAYER_HEAD_ALT: f32 = 1.6;
-const PLAYER_INITIAL_POS: Vec3 = Vec3::new(-5.0, PLAYER_HEIGHT_2, -4.0);
+pub const PLAYER_INITIAL_POS: Vec3 = Vec3::new(-5.0, PLAYER_HEIGHT_2, -4.0);
 const HEAD_SIZE_2: f32 = PLAYER_HEIGHT - PLAYER_HEAD_ALT;
 pub const HEAD_SIZE: f32 = HEAD_SIZE_2 * 2.0;
-const TORSO_WIDTH: f32 = HEAD_SIZE * 2.0;
-const TORSO_HEIGHT: f32 = PLAYER_HEAD_ALT / 2.0;
-const TORSO_ALT_RELATIVE: f32 = 0.0;
+pub const TORSO_WIDTH: f32 = HEAD_SIZE * 2.0;
+pub const TORSO_HEIGHT: f32 = PLAYER_HEAD_ALT / 2.0;
 const MOUSE_SENSITIVITY: f32 = 100.0;
+// Radians per second with the stick pushed all the way
+const GAMEPAD_LOOK_SPEED: f32 = 2.5;
+pub const MAX_LOCAL_PLAYERS: usize = 4;
+// Players who join later start next to the first one
+pub const PLAYER_JOIN_OFFSET: Vec3 = Vec3::new(1.5, 0.0, 0.0);
 const CAMERA_FPS_POS_RELATIVE: Vec3 = Vec3::new(0.0, 0.0, -HEAD_SIZE_2 * 3.0 / 4.0);
 pub const CAMERA_TPS_POS_RELATIVE: Vec3 = Vec3::new(0.0, 2.0, 5.0);
 
-const COLOR_PLAYER_BODY: &str = "A1C084"; // Olivine
-const COLOR_PLAYER_HEAD: &str = "F6F740"; // Maximum Yellow
+pub const COLOR_PLAYER_BODY: &str = "A1C084"; // Olivine
+pub const COLOR_PLAYER_HEAD: &str = "F6F740"; // Maximum Yellow
 
 // To tag player entity
 #[derive(Component)]
-struct Player;
+pub struct Player;
+
+// Which device controls a local player
+#[derive(Clone, Copy, PartialEq)]
+pub enum PlayerInput {
+    KeyboardMouse,
+    Gamepad(Gamepad),
+}
+
+// One of the players sharing the screen, the index decides where their view goes
+#[derive(Component)]
+pub struct LocalPlayer {
+    pub index: usize,
+    pub input: PlayerInput,
+}
+
+// The keyboard and mouse player, who the menus, the map and the interactions are for
+#[derive(Component)]
+pub struct PrimaryPlayer;
 
 // To specify which entities should rotate
 #[derive(Component)]
 struct Rotator;
 
+// Speed of the player, updated by player_move_system
+#[derive(Component, Default)]
+pub struct PlayerMotion {
+    // Horizontal
+    pub speed: f32,
+    pub vertical_speed: f32,
+    // What the player asked for this frame, relative to where they are facing
+    pub movement_axes: Vec3,
+}
+
+// The box torso and head, hidden when a glTF avatar is used instead
+#[derive(Component)]
+pub struct AvatarBox;
+
 #[derive(Component, Default)]
-struct HeadState {
-    pitch: f32,
-    yaw: f32,
+pub struct HeadState {
+    pub pitch: f32,
+    // Turned relative to the body. While the player moves the body is rotated by this much and it
+    // goes back to 0, standing still lets the head look around on its own
+    pub yaw: f32,
 }
 
 pub struct PlayerPlugin;
@@ -39,8 +83,10 @@ pub struct PlayerPlugin;
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app.add_startup_system(setup_player.after("main_setup"))
+            .add_system(leave_players)
             .add_system_set(
                 SystemSet::on_update(AppState::InGame)
+                    .with_system(join_players)
                     .with_system(
                         player_look_system
                             .before(player_move_system)
@@ -58,48 +104,102 @@ fn setup_player(
     mut materials: ResMut<Assets<StandardMaterial>>,
     cam_settings: Res<CameraSettings>,
 ) {
-    let transform_player = Transform::from_translation(PLAYER_INITIAL_POS)
-        .looking_at(Vec3::new(0.0, PLAYER_INITIAL_POS.y, 0.0), Vec3::Y);
+    let player = spawn_player(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        &cam_settings,
+        LocalPlayer {
+            index: 0,
+            input: PlayerInput::KeyboardMouse,
+        },
+    );
+    commands.entity(player).insert(PrimaryPlayer);
+}
 
-    let transform_head = Transform::from_xyz(0.0, PLAYER_HEAD_ALT - PLAYER_HEIGHT_2, 0.0);
+// Pressing start on a gamepad that isn't used yet adds a player
+fn join_players(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    cam_settings: Res<CameraSettings>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    query_players: Query<&LocalPlayer>,
+) {
+    for gamepad in gamepads.iter() {
+        if !gamepad_buttons.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::Start)) {
+            continue;
+        }
+        let input = PlayerInput::Gamepad(gamepad);
+        if query_players.iter().any(|player| player.input == input) {
+            continue;
+        }
+        let Some(index) =
+            (0..MAX_LOCAL_PLAYERS).find(|i| query_players.iter().all(|player| player.index != *i))
+        else {
+            continue;
+        };
+
+        info!("Player {} joined", index + 1);
+        spawn_player(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            &cam_settings,
+            LocalPlayer { index, input },
+        );
+    }
+}
+
+fn leave_players(
+    mut commands: Commands,
+    mut gamepad_events: EventReader<GamepadEvent>,
+    query_players: Query<(Entity, &LocalPlayer)>,
+) {
+    for event in gamepad_events.iter() {
+        if event.event_type != GamepadEventType::Disconnected {
+            continue;
+        }
+        let input = PlayerInput::Gamepad(event.gamepad);
+        for (entity, player) in query_players.iter() {
+            if player.input == input {
+                info!("Player {} left", player.index + 1);
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+fn spawn_player(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    cam_settings: &CameraSettings,
+    local_player: LocalPlayer,
+) -> Entity {
+    let position = PLAYER_INITIAL_POS + PLAYER_JOIN_OFFSET * local_player.index as f32;
+    let transform_player =
+        Transform::from_translation(position).looking_at(Vec3::new(0.0, position.y, 0.0), Vec3::Y);
 
     let transform_third_person_cam =
         Transform::from_translation(CAMERA_TPS_POS_RELATIVE).looking_at(Vec3::ZERO, Vec3::Y);
 
-    let player = commands
-        .spawn((
-            SpatialBundle {
-                transform: transform_player,
-                ..default()
-            },
-            Player,
-            Rotator,
-        ))
-        .with_children(|parent| {
-            parent.spawn(PbrBundle {
-                transform: Transform::from_xyz(0.0, TORSO_ALT_RELATIVE, 0.0),
-                mesh: meshes.add(Mesh::from(shape::Box::new(
-                    TORSO_WIDTH,
-                    TORSO_HEIGHT,
-                    HEAD_SIZE,
-                ))),
-                material: materials.add(Color::hex(COLOR_PLAYER_BODY).unwrap().into()),
-                ..default()
-            });
-        })
-        .id();
+    let avatar = spawn_box_avatar(commands, meshes, materials, transform_player);
+    let player = avatar.body;
+    commands.entity(player).insert((
+        Player,
+        local_player,
+        PlayerMotion::default(),
+        Health::default(),
+        Inventory::default(),
+        Rotator,
+    ));
+    commands.entity(avatar.torso).insert(AvatarBox);
 
     let head = commands
-        .spawn((
-            PbrBundle {
-                transform: transform_head,
-                mesh: meshes.add(Mesh::from(shape::Cube::new(HEAD_SIZE))),
-                material: materials.add(Color::hex(COLOR_PLAYER_HEAD).unwrap().into()),
-                ..default()
-            },
-            HeadState::default(),
-            Rotator,
-        ))
+        .entity(avatar.head)
+        .insert((HeadState::default(), AvatarBox, Rotator))
         .with_children(|parent| {
             parent.spawn((
                 Camera3dBundle {
@@ -109,9 +209,15 @@ fn setup_player(
                         hdr: cam_settings.bloom_enabled,
                         ..default()
                     },
+                    tonemapping: cam_settings.tonemapping.clone(),
                     ..default()
                 },
                 cam_settings.bloom.clone(),
+                FirstPersonCamera,
+                CameraRig {
+                    c_type: CameraType::FirstPerson,
+                    player: Some(player),
+                },
             ));
         })
         .id();
@@ -124,24 +230,136 @@ fn setup_player(
                     hdr: cam_settings.bloom_enabled,
                     ..default()
                 },
+                tonemapping: cam_settings.tonemapping.clone(),
                 ..default()
             },
             cam_settings.bloom.clone(),
+            ThirdPersonCamera,
+            CameraRig {
+                c_type: CameraType::ThirdPerson,
+                player: Some(player),
+            },
         ))
         .id();
 
     commands.entity(head).push_children(&[third_person_cam]);
-    commands.entity(player).push_children(&[head]);
+    player
+}
+
+pub struct BoxAvatar {
+    pub body: Entity,
+    pub torso: Entity,
+    pub head: Entity,
+}
+
+// Altitudes of the torso and the head relative to the center of the player. The top of the head
+// stays at the top of the player and the torso hangs below it, keeping the gap of the neck.
+pub fn box_avatar_altitudes(torso_height: f32, head_size: f32) -> (f32, f32) {
+    const NECK: f32 = PLAYER_HEAD_ALT - HEAD_SIZE_2 - PLAYER_HEIGHT_2 - TORSO_HEIGHT / 2.0;
+    let head_alt = PLAYER_HEIGHT_2 - head_size / 2.0;
+    let torso_alt = head_alt - head_size / 2.0 - NECK - torso_height / 2.0;
+    (torso_alt, head_alt)
+}
+
+// The body is at the center of the player, the head is its child so that it can look up and down
+pub fn spawn_box_avatar(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    transform: Transform,
+) -> BoxAvatar {
+    let (torso_alt, head_alt) = box_avatar_altitudes(TORSO_HEIGHT, HEAD_SIZE);
+
+    let torso = commands
+        .spawn(PbrBundle {
+            transform: Transform::from_xyz(0.0, torso_alt, 0.0),
+            mesh: meshes.add(Mesh::from(shape::Box::new(
+                TORSO_WIDTH,
+                TORSO_HEIGHT,
+                HEAD_SIZE,
+            ))),
+            material: materials.add(Color::hex(COLOR_PLAYER_BODY).unwrap().into()),
+            ..default()
+        })
+        .id();
+
+    let head = commands
+        .spawn(PbrBundle {
+            transform: Transform::from_xyz(0.0, head_alt, 0.0),
+            mesh: meshes.add(Mesh::from(shape::Cube::new(HEAD_SIZE))),
+            material: materials.add(Color::hex(COLOR_PLAYER_HEAD).unwrap().into()),
+            ..default()
+        })
+        .id();
+
+    let body = commands
+        .spawn(SpatialBundle {
+            transform,
+            ..default()
+        })
+        .push_children(&[torso, head])
+        .id();
+
+    BoxAvatar { body, torso, head }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn player_move_system(
     time: Res<Time>,
     keyboard_input: Res<Input<KeyCode>>,
-    query_player: Query<Entity, With<Player>>,
-    query_head: Query<Entity, With<HeadState>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    mut query_players: Query<(Entity, &LocalPlayer, &mut PlayerMotion, Option<&Dead>)>,
+    query_children: Query<&Children>,
     mut query_transforms: Query<&mut Transform, With<Rotator>>,
     mut query_head_state: Query<&mut HeadState>,
 ) {
+    for (entity_player, local_player, mut motion, dead) in query_players.iter_mut() {
+        let movement_axes = match local_player.input {
+            PlayerInput::KeyboardMouse => keyboard_movement(&keyboard_input),
+            PlayerInput::Gamepad(gamepad) => {
+                gamepad_movement(gamepad, &gamepad_buttons, &gamepad_axes)
+            }
+        };
+        // No moving around while fading out after dying
+        let movement_axes = if dead.is_some() {
+            Vec3::ZERO
+        } else {
+            movement_axes
+        };
+        *motion = PlayerMotion {
+            movement_axes,
+            ..default()
+        };
+        if movement_axes == Vec3::ZERO {
+            continue;
+        }
+
+        let Some(entity_head) = query_children.get(entity_player).ok().and_then(|children| {
+            children
+                .iter()
+                .copied()
+                .find(|child| query_head_state.contains(*child))
+        }) else {
+            continue;
+        };
+        rotate_player_to_head_yaw(
+            entity_player,
+            entity_head,
+            &mut query_transforms,
+            &mut query_head_state,
+        );
+
+        let translation =
+            translate_player(entity_player, &mut query_transforms, movement_axes, &time);
+        if time.delta_seconds() > 0.0 {
+            motion.speed = Vec2::new(translation.x, translation.z).length() / time.delta_seconds();
+            motion.vertical_speed = translation.y / time.delta_seconds();
+        }
+    }
+}
+
+fn keyboard_movement(keyboard_input: &Input<KeyCode>) -> Vec3 {
     let mut movement_axes = Vec3::ZERO;
     if keyboard_input.any_pressed([KeyCode::W, KeyCode::Up]) {
         movement_axes.z += 1.0
@@ -161,30 +379,45 @@ fn player_move_system(
     if keyboard_input.any_pressed([KeyCode::Q, KeyCode::RControl]) {
         movement_axes.y += -1.0;
     }
+    movement_axes.normalize_or_zero()
+}
 
-    if movement_axes == Vec3::ZERO {
-        return;
-    }
-
-    let entity_player = query_player.single();
-
-    rotate_player_to_head_yaw(
-        entity_player,
-        &query_head,
-        &mut query_transforms,
-        &mut query_head_state,
+// Left stick to move, shoulder buttons to go up and down
+fn gamepad_movement(
+    gamepad: Gamepad,
+    gamepad_buttons: &Input<GamepadButton>,
+    gamepad_axes: &Axis<GamepadAxis>,
+) -> Vec3 {
+    let axis = |axis_type| {
+        gamepad_axes
+            .get(GamepadAxis::new(gamepad, axis_type))
+            .unwrap_or(0.0)
+    };
+    let button = |button_type| gamepad_buttons.pressed(GamepadButton::new(gamepad, button_type));
+
+    let mut movement_axes = Vec3::new(
+        axis(GamepadAxisType::LeftStickX),
+        0.0,
+        axis(GamepadAxisType::LeftStickY),
     );
-
-    translate_player(entity_player, &mut query_transforms, movement_axes, &time);
+    if button(GamepadButtonType::RightTrigger) {
+        movement_axes.y += 1.0;
+    }
+    if button(GamepadButtonType::LeftTrigger) {
+        movement_axes.y -= 1.0;
+    }
+    movement_axes
 }
 
 fn rotate_player_to_head_yaw(
     entity_player: Entity,
-    query_head: &Query<Entity, With<HeadState>>,
+    entity_head: Entity,
     query_transforms: &mut Query<&mut Transform, With<Rotator>>,
     query_head_state: &mut Query<&mut HeadState>,
 ) {
-    let mut head_state = query_head_state.single_mut();
+    let Ok(mut head_state) = query_head_state.get_mut(entity_head) else {
+        return;
+    };
     if head_state.yaw == 0.0 {
         return;
     }
@@ -194,7 +427,6 @@ fn rotate_player_to_head_yaw(
     transform_player.rotate_y(head_state.yaw);
 
     // Reset head yaw
-    let entity_head = query_head.single();
     let mut transform_head = query_transforms.get_mut(entity_head).unwrap();
     transform_head.rotation = Quat::from_rotation_x(head_state.pitch);
 
@@ -206,45 +438,87 @@ fn translate_player(
     query_transforms: &mut Query<&mut Transform, With<Rotator>>,
     movement_axes: Vec3,
     time: &Res<Time>,
-) {
+) -> Vec3 {
     let mut transform_player = query_transforms.get_mut(entity_player).unwrap();
+    let translation = player_translation(
+        transform_player.rotation,
+        movement_axes,
+        time.delta_seconds(),
+    );
+    transform_player.translation += translation;
+    translation
+}
+
+// How far a player facing `rotation` moves in `dt`, the network server moves players the same way
+pub fn player_translation(rotation: Quat, movement_axes: Vec3, dt: f32) -> Vec3 {
+    if movement_axes == Vec3::ZERO {
+        return Vec3::ZERO;
+    }
 
     //  Calculate movement direction
-    let movement_direction = movement_axes.z * transform_player.forward()
-        + movement_axes.x * transform_player.right()
-        + movement_axes.y * transform_player.up();
+    let movement_direction = movement_axes.z * (rotation * Vec3::NEG_Z)
+        + movement_axes.x * (rotation * Vec3::X)
+        + movement_axes.y * (rotation * Vec3::Y);
 
     let movement_direction = movement_direction.normalize();
 
-    // Apply translation
-    transform_player.translation += movement_direction * PLAYER_SPEED * time.delta_seconds();
+    // Sticks can be pushed part of the way
+    let speed = PLAYER_SPEED * movement_axes.length().min(1.0);
+    movement_direction * speed * dt
 }
 
 fn player_look_system(
+    time: Res<Time>,
     mut mouse_motion_events: EventReader<MouseMotion>,
-    mut query: Query<(&mut Transform, &mut HeadState), With<HeadState>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    mut query: Query<(&Parent, &mut Transform, &mut HeadState)>,
+    query_players: Query<&LocalPlayer>,
     windows: Res<Windows>,
 ) {
-    let mut delta = Vec2::ZERO;
+    let mut mouse_delta = Vec2::ZERO;
     for event in mouse_motion_events.iter() {
-        delta += event.delta;
-    }
-
-    if delta == Vec2::ZERO {
-        return;
+        mouse_delta += event.delta;
     }
-
-    let (mut transform, mut head_state) = query.single_mut();
-
     let window = windows.get_primary().unwrap();
 
-    let mut yaw = head_state.yaw;
-    let mut pitch = head_state.pitch;
-    yaw -= ((delta.x / window.width()) * MOUSE_SENSITIVITY).to_radians();
-    pitch -= ((delta.y / window.height()) * MOUSE_SENSITIVITY).to_radians();
-    pitch = pitch.clamp(-0.9 * FRAC_PI_2, 0.9 * FRAC_PI_2);
-
-    transform.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.0);
-    head_state.yaw = yaw;
-    head_state.pitch = pitch;
+    for (parent, mut transform, mut head_state) in query.iter_mut() {
+        let Ok(local_player) = query_players.get(parent.get()) else {
+            continue;
+        };
+        // Yaw and pitch to add, in radians
+        let delta = match local_player.input {
+            PlayerInput::KeyboardMouse => {
+                Vec2::new(
+                    mouse_delta.x / window.width(),
+                    mouse_delta.y / window.height(),
+                ) * MOUSE_SENSITIVITY.to_radians()
+            }
+            PlayerInput::Gamepad(gamepad) => {
+                let axis = |axis_type| {
+                    gamepad_axes
+                        .get(GamepadAxis::new(gamepad, axis_type))
+                        .unwrap_or(0.0)
+                };
+                // Pushing the stick up looks up, moving the mouse up looks up too
+                Vec2::new(
+                    axis(GamepadAxisType::RightStickX),
+                    -axis(GamepadAxisType::RightStickY),
+                ) * GAMEPAD_LOOK_SPEED
+                    * time.delta_seconds()
+            }
+        };
+        if delta == Vec2::ZERO {
+            continue;
+        }
+
+        let mut yaw = head_state.yaw;
+        let mut pitch = head_state.pitch;
+        yaw -= delta.x;
+        pitch -= delta.y;
+        pitch = pitch.clamp(-0.9 * FRAC_PI_2, 0.9 * FRAC_PI_2);
+
+        transform.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.0);
+        head_state.yaw = yaw;
+        head_state.pitch = pitch;
+    }
 }
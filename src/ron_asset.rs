@@ -0,0 +1,60 @@
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+};
+use serde::de::DeserializeOwned;
+use std::marker::PhantomData;
+
+// Loads settings files like "lighting.presets.ron" as assets, so that they also work on the web
+pub struct RonAssetPlugin<A> {
+    extensions: &'static [&'static str],
+    _marker: PhantomData<A>,
+}
+
+impl<A> RonAssetPlugin<A> {
+    pub fn new(extensions: &'static [&'static str]) -> Self {
+        RonAssetPlugin {
+            extensions,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<A> Plugin for RonAssetPlugin<A>
+where
+    A: TypeUuid + DeserializeOwned + Send + Sync + 'static,
+{
+    fn build(&self, app: &mut App) {
+        app.add_asset::<A>().add_asset_loader(RonAssetLoader::<A> {
+            extensions: self.extensions,
+            _marker: PhantomData,
+        });
+    }
+}
+
+struct RonAssetLoader<A> {
+    extensions: &'static [&'static str],
+    _marker: PhantomData<A>,
+}
+
+impl<A> AssetLoader for RonAssetLoader<A>
+where
+    A: TypeUuid + DeserializeOwned + Send + Sync + 'static,
+{
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let asset = ron::de::from_bytes::<A>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(asset));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        self.extensions
+    }
+}
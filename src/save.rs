@@ -0,0 +1,519 @@
+use bevy::{prelude::*, scene::SceneInstance};
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, io, path::PathBuf};
+
+use crate::{
+    camera::CameraRigs,
+    health::{Dead, Health, RespawnCause, RespawnPoint, Respawned, ScreenFade},
+    interaction::{Door, Lever},
+    items::{Inventory, ItemKind, Pickup},
+    map::MapFog,
+    player::{HeadState, LocalPlayer, PrimaryPlayer, PLAYER_JOIN_OFFSET},
+    ui::CameraSettings,
+    CurrentLevel, LevelRoot, LevelSpawner,
+};
+
+// Bump when the format changes, and teach `migrate` to read the previous version
+const SAVE_VERSION: u32 = 1;
+const SAVE_DIRECTORY: &str = "saves";
+pub const SAVE_SLOTS: usize = 5;
+
+const KEY_QUICK_SAVE: KeyCode = KeyCode::F5;
+const KEY_QUICK_LOAD: KeyCode = KeyCode::F9;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SaveSlot {
+    Quick,
+    Slot(usize),
+}
+
+impl SaveSlot {
+    pub fn label(&self) -> String {
+        match self {
+            SaveSlot::Quick => "Quick Save".to_string(),
+            SaveSlot::Slot(i) => format!("Slot {}", i + 1),
+        }
+    }
+
+    fn path(&self) -> PathBuf {
+        let file_name = match self {
+            SaveSlot::Quick => "quicksave.ron".to_string(),
+            SaveSlot::Slot(i) => format!("slot{}.ron", i + 1),
+        };
+        PathBuf::from(SAVE_DIRECTORY).join(file_name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct TransformSave {
+    translation: [f32; 3],
+    rotation: [f32; 4],
+}
+
+impl From<&Transform> for TransformSave {
+    fn from(transform: &Transform) -> Self {
+        TransformSave {
+            translation: transform.translation.to_array(),
+            rotation: transform.rotation.to_array(),
+        }
+    }
+}
+
+impl From<TransformSave> for Transform {
+    fn from(save: TransformSave) -> Self {
+        Transform::from_translation(Vec3::from(save.translation))
+            .with_rotation(Quat::from_array(save.rotation).normalize())
+    }
+}
+
+// Only the primary player is saved, the other local players are put next to them when loading
+#[derive(Serialize, Deserialize)]
+struct PlayerSave {
+    transform: TransformSave,
+    head_pitch: f32,
+    head_yaw: f32,
+    health: f32,
+    inventory: Vec<(ItemKind, u32)>,
+}
+
+// Doors and levers come from the level, they are found again by name
+#[derive(Serialize, Deserialize)]
+struct DoorSave {
+    name: String,
+    open: bool,
+    locked: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LeverSave {
+    name: String,
+    on: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PickupSave {
+    kind: ItemKind,
+    amount: u32,
+    translation: [f32; 3],
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SaveGame {
+    version: u32,
+    level: String,
+    camera: String,
+    player: PlayerSave,
+    respawn_point: Option<TransformSave>,
+    checkpoint_reached: bool,
+    doors: Vec<DoorSave>,
+    levers: Vec<LeverSave>,
+    // The pickups that haven't been collected yet
+    pickups: Vec<PickupSave>,
+}
+
+impl SaveGame {
+    // Shown next to the slot in the menu
+    fn summary(&self) -> String {
+        let level = self
+            .level
+            .rsplit('/')
+            .next()
+            .and_then(|file| file.split('.').next())
+            .unwrap_or(&self.level);
+        format!(
+            "{level}, {:.0} health, {} items",
+            self.player.health,
+            self.player
+                .inventory
+                .iter()
+                .map(|(_, count)| count)
+                .sum::<u32>()
+        )
+    }
+}
+
+// Only the version is read first, to know how to read the rest
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Format(String),
+    TooOld(u32),
+    TooNew(u32),
+    NoPlayer,
+    PlayerDead,
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Io(error) if error.kind() == io::ErrorKind::NotFound => {
+                write!(f, "the save file doesn't exist")
+            }
+            SaveError::Io(error) => write!(f, "{error}"),
+            SaveError::Format(error) => write!(f, "the save file is damaged ({error})"),
+            SaveError::TooOld(version) => write!(
+                f,
+                "the save file is from an old version of the game (format {version}) and can't \
+                 be loaded anymore"
+            ),
+            SaveError::TooNew(version) => write!(
+                f,
+                "the save file is from a newer version of the game (format {version}, this one \
+                 reads up to {SAVE_VERSION})"
+            ),
+            SaveError::NoPlayer => write!(f, "there is no player"),
+            SaveError::PlayerDead => write!(f, "the player is dead"),
+        }
+    }
+}
+
+impl From<io::Error> for SaveError {
+    fn from(error: io::Error) -> Self {
+        SaveError::Io(error)
+    }
+}
+
+impl From<ron::error::SpannedError> for SaveError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        SaveError::Format(error.to_string())
+    }
+}
+
+impl From<ron::Error> for SaveError {
+    fn from(error: ron::Error) -> Self {
+        SaveError::Format(error.to_string())
+    }
+}
+
+fn read_save(slot: SaveSlot) -> Result<SaveGame, SaveError> {
+    let text = fs::read_to_string(slot.path())?;
+    let header: SaveHeader = ron::from_str(&text)?;
+    migrate(header.version, &text)
+}
+
+// Reads a save file of any version the game still understands
+fn migrate(version: u32, text: &str) -> Result<SaveGame, SaveError> {
+    match version {
+        SAVE_VERSION => Ok(ron::from_str(text)?),
+        version if version > SAVE_VERSION => Err(SaveError::TooNew(version)),
+        version => Err(SaveError::TooOld(version)),
+    }
+}
+
+fn write_save(slot: SaveSlot, save: &SaveGame) -> Result<(), SaveError> {
+    let text = ron::ser::to_string_pretty(save, ron::ser::PrettyConfig::default())?;
+    fs::create_dir_all(SAVE_DIRECTORY)?;
+    fs::write(slot.path(), text)?;
+    Ok(())
+}
+
+pub struct SaveRequest(pub SaveSlot);
+
+pub struct LoadRequest(pub SaveSlot);
+
+// What is in each slot, or why it can't be loaded
+#[derive(Resource, Default)]
+pub struct SaveSlots {
+    pub slots: Vec<(SaveSlot, Option<Result<String, String>>)>,
+}
+
+impl SaveSlots {
+    fn refresh(&mut self) {
+        let slots = std::iter::once(SaveSlot::Quick).chain((0..SAVE_SLOTS).map(SaveSlot::Slot));
+        self.slots = slots
+            .map(|slot| {
+                let summary = match read_save(slot) {
+                    Ok(save) => Some(Ok(save.summary())),
+                    Err(SaveError::Io(error)) if error.kind() == io::ErrorKind::NotFound => None,
+                    Err(error) => Some(Err(error.to_string())),
+                };
+                (slot, summary)
+            })
+            .collect();
+    }
+}
+
+// The outcome of the last save or load, shown for a few seconds
+#[derive(Resource, Default)]
+pub struct SaveStatus {
+    pub message: Option<(String, f64)>,
+}
+
+// A loaded game waiting for its level to be spawned
+#[derive(Resource, Default)]
+struct PendingLoad(Option<SaveGame>);
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SaveRequest>()
+            .add_event::<LoadRequest>()
+            .init_resource::<SaveSlots>()
+            .init_resource::<SaveStatus>()
+            .init_resource::<PendingLoad>()
+            .add_startup_system(refresh_save_slots)
+            .add_system(quick_save_keys)
+            .add_system(save_game.after(quick_save_keys))
+            .add_system(load_game.after(quick_save_keys))
+            // Once the commands of the level setup systems have been applied
+            .add_system_to_stage(CoreStage::PostUpdate, apply_pending_load);
+    }
+}
+
+fn refresh_save_slots(mut slots: ResMut<SaveSlots>) {
+    slots.refresh();
+}
+
+fn quick_save_keys(
+    key: Res<Input<KeyCode>>,
+    mut save_requests: EventWriter<SaveRequest>,
+    mut load_requests: EventWriter<LoadRequest>,
+) {
+    if key.just_pressed(KEY_QUICK_SAVE) {
+        save_requests.send(SaveRequest(SaveSlot::Quick));
+    }
+    if key.just_pressed(KEY_QUICK_LOAD) {
+        load_requests.send(LoadRequest(SaveSlot::Quick));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn save_game(
+    time: Res<Time>,
+    mut save_requests: EventReader<SaveRequest>,
+    mut slots: ResMut<SaveSlots>,
+    mut status: ResMut<SaveStatus>,
+    level: Res<CurrentLevel>,
+    cam_settings: Res<CameraSettings>,
+    respawn_point: Res<RespawnPoint>,
+    query_player: Query<(Entity, &Transform, &Health, &Inventory, &Children), With<PrimaryPlayer>>,
+    query_dead: Query<(), With<Dead>>,
+    query_heads: Query<&HeadState>,
+    query_doors: Query<(&Name, &Door)>,
+    query_levers: Query<(&Name, &Lever)>,
+    query_pickups: Query<(&Pickup, &GlobalTransform)>,
+) {
+    for SaveRequest(slot) in save_requests.iter() {
+        let save = || {
+            let Ok((entity, transform, health, inventory, children)) = query_player.get_single()
+            else {
+                return Err(SaveError::NoPlayer);
+            };
+            if query_dead.contains(entity) {
+                return Err(SaveError::PlayerDead);
+            }
+            let head = children
+                .iter()
+                .find_map(|child| query_heads.get(*child).ok());
+
+            let save = SaveGame {
+                version: SAVE_VERSION,
+                level: level.0.clone(),
+                camera: cam_settings.c_type.label().to_string(),
+                player: PlayerSave {
+                    transform: transform.into(),
+                    head_pitch: head.map(|head| head.pitch).unwrap_or_default(),
+                    head_yaw: head.map(|head| head.yaw).unwrap_or_default(),
+                    health: health.current,
+                    inventory: inventory.iter().collect(),
+                },
+                respawn_point: respawn_point.transform.as_ref().map(TransformSave::from),
+                checkpoint_reached: respawn_point.from_checkpoint,
+                doors: query_doors
+                    .iter()
+                    .map(|(name, door)| DoorSave {
+                        name: name.to_string(),
+                        open: door.open,
+                        locked: door.locked,
+                    })
+                    .collect(),
+                levers: query_levers
+                    .iter()
+                    .map(|(name, lever)| LeverSave {
+                        name: name.to_string(),
+                        on: lever.on,
+                    })
+                    .collect(),
+                pickups: query_pickups
+                    .iter()
+                    .map(|(pickup, transform)| PickupSave {
+                        kind: pickup.kind,
+                        amount: pickup.amount,
+                        translation: transform.translation().to_array(),
+                    })
+                    .collect(),
+            };
+            write_save(*slot, &save)
+        };
+
+        let message = match save() {
+            Ok(()) => format!("Saved to {}", slot.label()),
+            Err(error) => {
+                warn!("Could not save to {}: {error}", slot.label());
+                format!("Could not save: {error}")
+            }
+        };
+        status.message = Some((message, time.elapsed_seconds_f64()));
+        slots.refresh();
+    }
+}
+
+fn load_game(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut levels: LevelSpawner,
+    mut load_requests: EventReader<LoadRequest>,
+    mut status: ResMut<SaveStatus>,
+    mut pending_load: ResMut<PendingLoad>,
+    mut fog: ResMut<MapFog>,
+) {
+    // Only the last request counts if there are several in a frame
+    let Some(LoadRequest(slot)) = load_requests.iter().last() else {
+        return;
+    };
+
+    let save = match read_save(*slot) {
+        Ok(save) => save,
+        Err(error) => {
+            warn!("Could not load {}: {error}", slot.label());
+            let message = format!("Could not load {}: {error}", slot.label());
+            status.message = Some((message, time.elapsed_seconds_f64()));
+            return;
+        }
+    };
+
+    if save.level != levels.current() {
+        info!("Switching to level {}", save.level);
+        levels.load(&mut commands, &save.level);
+        // What has been explored of the other level doesn't belong on this map
+        fog.clear();
+    }
+
+    let message = format!("Loaded {}", slot.label());
+    status.message = Some((message, time.elapsed_seconds_f64()));
+    pending_load.0 = Some(save);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn apply_pending_load(
+    mut commands: Commands,
+    scene_spawner: Res<SceneSpawner>,
+    rigs: Res<CameraRigs>,
+    mut pending_load: ResMut<PendingLoad>,
+    mut cam_settings: ResMut<CameraSettings>,
+    mut respawn_point: ResMut<RespawnPoint>,
+    mut screen_fade: ResMut<ScreenFade>,
+    mut respawned_events: EventWriter<Respawned>,
+    query_levels: Query<(Entity, Option<&SceneInstance>), With<LevelRoot>>,
+    mut query_player: Query<(Entity, &mut Health, &mut Inventory, &Children), With<PrimaryPlayer>>,
+    query_others: Query<(Entity, &LocalPlayer), Without<PrimaryPlayer>>,
+    mut query_heads: Query<&mut HeadState>,
+    mut query_transforms: Query<&mut Transform>,
+    mut query_doors: Query<(&Name, &mut Door)>,
+    mut query_levers: Query<(&Name, &mut Lever)>,
+    query_pickups: Query<Entity, With<Pickup>>,
+) {
+    if pending_load.0.is_none() {
+        return;
+    }
+    // Doors, levers and pickups are only there once the level has been spawned
+    let level_ready = query_levels.iter().all(|(_, instance)| {
+        instance.is_some_and(|instance| scene_spawner.instance_is_ready(**instance))
+    });
+    if !level_ready {
+        return;
+    }
+    let Some(save) = pending_load.0.take() else {
+        return;
+    };
+
+    if let Ok((entity, mut health, mut inventory, children)) = query_player.get_single_mut() {
+        if let Ok(mut transform) = query_transforms.get_mut(entity) {
+            *transform = save.player.transform.into();
+        }
+        for child in children.iter() {
+            if let Ok(mut head_state) = query_heads.get_mut(*child) {
+                head_state.pitch = save.player.head_pitch;
+                head_state.yaw = save.player.head_yaw;
+                if let Ok(mut transform) = query_transforms.get_mut(*child) {
+                    transform.rotation = Quat::from_euler(
+                        EulerRot::YXZ,
+                        save.player.head_yaw,
+                        save.player.head_pitch,
+                        0.0,
+                    );
+                }
+            }
+        }
+        health.current = save.player.health.min(health.max);
+        *inventory = Inventory::default();
+        for (kind, count) in save.player.inventory.iter() {
+            inventory.add(*kind, *count);
+        }
+        // Loading while fading out after dying brings the player back
+        commands.entity(entity).remove::<Dead>();
+        screen_fade.alpha = 0.0;
+        respawned_events.send(Respawned {
+            player: entity,
+            cause: RespawnCause::Loaded,
+        });
+    }
+    let start = Transform::from(save.player.transform);
+    for (entity, player) in query_others.iter() {
+        if let Ok(mut transform) = query_transforms.get_mut(entity) {
+            *transform = start;
+            transform.translation += start.rotation * (PLAYER_JOIN_OFFSET * player.index as f32);
+            respawned_events.send(Respawned {
+                player: entity,
+                cause: RespawnCause::Loaded,
+            });
+        }
+    }
+
+    match rigs.iter().find(|c_type| c_type.label() == save.camera) {
+        Some(c_type) => cam_settings.c_type = c_type,
+        None => warn!("Unknown camera {:?} in the save file", save.camera),
+    }
+
+    respawn_point.transform = save.respawn_point.map(Transform::from);
+    respawn_point.from_checkpoint = save.checkpoint_reached;
+
+    for (name, mut door) in query_doors.iter_mut() {
+        if let Some(saved) = save.doors.iter().find(|saved| saved.name == name.as_str()) {
+            door.open = saved.open;
+            door.locked = saved.locked;
+        }
+    }
+    for (name, mut lever) in query_levers.iter_mut() {
+        if let Some(saved) = save.levers.iter().find(|saved| saved.name == name.as_str()) {
+            lever.on = saved.on;
+        }
+    }
+
+    // Collected pickups stay gone, the others are put back where they were
+    for entity in query_pickups.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let Some((root, _)) = query_levels.iter().next() else {
+        return;
+    };
+    commands.entity(root).with_children(|parent| {
+        for pickup in save.pickups {
+            parent.spawn((
+                SpatialBundle::from_transform(Transform::from_translation(Vec3::from(
+                    pickup.translation,
+                ))),
+                Pickup {
+                    kind: pickup.kind,
+                    amount: pickup.amount,
+                },
+            ));
+        }
+    });
+}
@@ -0,0 +1,132 @@
+// Copy of bevy_pbr 0.9 render/pbr.wgsl with exposure and fog applied on top
+#import bevy_pbr::mesh_view_bindings
+#import bevy_pbr::pbr_bindings
+#import bevy_pbr::mesh_bindings
+
+#import bevy_pbr::utils
+#import bevy_pbr::clustered_forward
+#import bevy_pbr::lighting
+#import bevy_pbr::shadows
+#import bevy_pbr::pbr_functions
+
+// Generated from the Graphics and Camera settings, see shading.rs
+#import bevy_3d_test::shading_params
+
+fn apply_fog(color: vec4<f32>, world_position: vec3<f32>) -> vec4<f32> {
+    if (FOG_MODE == FOG_MODE_OFF) {
+        return color;
+    }
+
+    let view_distance = length(world_position - view.world_position);
+    var visibility: f32;
+    if (FOG_MODE == FOG_MODE_LINEAR) {
+        visibility = clamp((FOG_END - view_distance) / (FOG_END - FOG_START), 0.0, 1.0);
+    } else if (FOG_MODE == FOG_MODE_EXPONENTIAL) {
+        visibility = exp(-FOG_DENSITY * view_distance);
+    } else {
+        let d = FOG_DENSITY * view_distance;
+        visibility = exp(-d * d);
+    }
+
+    return vec4<f32>(mix(FOG_COLOR, color.rgb, visibility), color.a);
+}
+
+struct FragmentInput {
+    @builtin(front_facing) is_front: bool,
+    @builtin(position) frag_coord: vec4<f32>,
+    #import bevy_pbr::mesh_vertex_output
+};
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    var output_color: vec4<f32> = material.base_color;
+#ifdef VERTEX_COLORS
+    output_color = output_color * in.color;
+#endif
+#ifdef VERTEX_UVS
+    if ((material.flags & STANDARD_MATERIAL_FLAGS_BASE_COLOR_TEXTURE_BIT) != 0u) {
+        output_color = output_color * textureSample(base_color_texture, base_color_sampler, in.uv);
+    }
+#endif
+
+    // NOTE: Unlit bit not set means == 0 is true, so the true case is if lit
+    if ((material.flags & STANDARD_MATERIAL_FLAGS_UNLIT_BIT) == 0u) {
+        // Prepare a 'processed' StandardMaterial by sampling all textures to resolve
+        // the material members
+        var pbr_input: PbrInput;
+
+        pbr_input.material.base_color = output_color;
+        pbr_input.material.reflectance = material.reflectance;
+        pbr_input.material.flags = material.flags;
+        pbr_input.material.alpha_cutoff = material.alpha_cutoff;
+
+        // TODO use .a for exposure compensation in HDR
+        var emissive: vec4<f32> = material.emissive;
+#ifdef VERTEX_UVS
+        if ((material.flags & STANDARD_MATERIAL_FLAGS_EMISSIVE_TEXTURE_BIT) != 0u) {
+            emissive = vec4<f32>(emissive.rgb * textureSample(emissive_texture, emissive_sampler, in.uv).rgb, 1.0);
+        }
+#endif
+        pbr_input.material.emissive = emissive;
+
+        var metallic: f32 = material.metallic;
+        var perceptual_roughness: f32 = material.perceptual_roughness;
+#ifdef VERTEX_UVS
+        if ((material.flags & STANDARD_MATERIAL_FLAGS_METALLIC_ROUGHNESS_TEXTURE_BIT) != 0u) {
+            let metallic_roughness = textureSample(metallic_roughness_texture, metallic_roughness_sampler, in.uv);
+            // Sampling from GLTF standard channels for now
+            metallic = metallic * metallic_roughness.b;
+            perceptual_roughness = perceptual_roughness * metallic_roughness.g;
+        }
+#endif
+        pbr_input.material.metallic = metallic;
+        pbr_input.material.perceptual_roughness = perceptual_roughness;
+
+        var occlusion: f32 = 1.0;
+#ifdef VERTEX_UVS
+        if ((material.flags & STANDARD_MATERIAL_FLAGS_OCCLUSION_TEXTURE_BIT) != 0u) {
+            occlusion = textureSample(occlusion_texture, occlusion_sampler, in.uv).r;
+        }
+#endif
+        pbr_input.occlusion = occlusion;
+
+        pbr_input.frag_coord = in.frag_coord;
+        pbr_input.world_position = in.world_position;
+        pbr_input.world_normal = prepare_world_normal(
+            in.world_normal,
+            (material.flags & STANDARD_MATERIAL_FLAGS_DOUBLE_SIDED_BIT) != 0u,
+            in.is_front,
+        );
+
+        pbr_input.is_orthographic = view.projection[3].w == 1.0;
+
+        pbr_input.N = apply_normal_mapping(
+            material.flags,
+            pbr_input.world_normal,
+#ifdef VERTEX_TANGENTS
+#ifdef STANDARDMATERIAL_NORMAL_MAP
+            in.world_tangent,
+#endif
+#endif
+#ifdef VERTEX_UVS
+            in.uv,
+#endif
+        );
+        pbr_input.V = calculate_view(in.world_position, pbr_input.is_orthographic);
+        output_color = pbr(pbr_input);
+    } else {
+        output_color = alpha_discard(material, output_color);
+    }
+
+    // Exposure before fog, so that the fog keeps matching the clear color
+    output_color = vec4<f32>(output_color.rgb * EXPOSURE, output_color.a);
+    output_color = apply_fog(output_color, in.world_position.xyz);
+
+#ifdef TONEMAP_IN_SHADER
+        output_color = tone_mapping(output_color);
+#endif
+#ifdef DEBAND_DITHER
+        output_color = dither(output_color, in.frag_coord.xy);
+#endif
+    return output_color;
+}
@@ -0,0 +1,119 @@
+use bevy::{
+    asset::HandleUntyped, pbr::PBR_SHADER_HANDLE, prelude::*, reflect::TypeUuid,
+    render::render_resource::Shader,
+};
+
+use crate::{ui::CameraSettings, COLOR_BACKGROUND};
+
+// Bevy 0.9 has neither fog nor exposure yet, so StandardMaterial's fragment shader is replaced with a copy that
+// imports its parameters from a small generated shader module.
+const SHADING_PARAMS_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x3d7e_5a1c_9b24_e6f0);
+const SHADING_PARAMS_IMPORT_PATH: &str = "bevy_3d_test::shading_params";
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum FogMode {
+    Linear,
+    Exponential,
+    ExponentialSquared,
+}
+
+#[derive(Resource)]
+pub struct FogSettings {
+    pub enabled: bool,
+    pub mode: FogMode,
+    pub color: Color,
+    pub density: f32,
+    pub start: f32,
+    pub end: f32,
+}
+
+impl Default for FogSettings {
+    fn default() -> Self {
+        FogSettings {
+            enabled: false,
+            mode: FogMode::Linear,
+            color: COLOR_BACKGROUND,
+            density: 0.1,
+            start: 5.0,
+            end: 25.0,
+        }
+    }
+}
+
+pub struct ShadingPlugin;
+
+impl Plugin for ShadingPlugin {
+    fn build(&self, app: &mut App) {
+        let mut shaders = app.world.resource_mut::<Assets<Shader>>();
+        shaders.set_untracked(
+            PBR_SHADER_HANDLE,
+            Shader::from_wgsl(include_str!("shaders/pbr.wgsl")),
+        );
+        shaders.set_untracked(
+            SHADING_PARAMS_HANDLE,
+            Shader::from_wgsl(shading_params_source(
+                &FogSettings::default(),
+                &CameraSettings::default(),
+            ))
+            .with_import_path(SHADING_PARAMS_IMPORT_PATH),
+        );
+
+        app.insert_resource(FogSettings::default())
+            .add_system(update_shading_params);
+    }
+}
+
+// Regenerating the module recompiles the pipelines, so only do it when the source actually changes
+fn update_shading_params(
+    fog: Res<FogSettings>,
+    cam_settings: Res<CameraSettings>,
+    mut shaders: ResMut<Assets<Shader>>,
+    mut source_prev: Local<String>,
+) {
+    if !fog.is_changed() && !cam_settings.is_changed() {
+        return;
+    }
+
+    let source = shading_params_source(&fog, &cam_settings);
+    if source == *source_prev {
+        return;
+    }
+
+    shaders.set_untracked(
+        SHADING_PARAMS_HANDLE,
+        Shader::from_wgsl(source.clone()).with_import_path(SHADING_PARAMS_IMPORT_PATH),
+    );
+    *source_prev = source;
+}
+
+fn shading_params_source(fog: &FogSettings, cam_settings: &CameraSettings) -> String {
+    let fog_mode = match (fog.enabled, fog.mode) {
+        (false, _) => 0,
+        (true, FogMode::Linear) => 1,
+        (true, FogMode::Exponential) => 2,
+        (true, FogMode::ExponentialSquared) => 3,
+    };
+    let [r, g, b, _] = fog.color.as_linear_rgba_f32();
+    let exposure = 2.0_f32.powf(cam_settings.exposure);
+
+    format!(
+        "let FOG_MODE_OFF: u32 = 0u;
+let FOG_MODE_LINEAR: u32 = 1u;
+let FOG_MODE_EXPONENTIAL: u32 = 2u;
+let FOG_MODE_EXPONENTIAL_SQUARED: u32 = 3u;
+
+let FOG_MODE: u32 = {fog_mode}u;
+let FOG_COLOR: vec3<f32> = vec3<f32>({r:?}, {g:?}, {b:?});
+let FOG_DENSITY: f32 = {:?};
+let FOG_START: f32 = {:?};
+let FOG_END: f32 = {:?};
+
+let EXPOSURE: f32 = {exposure:?};
+",
+        fog.density,
+        fog.start,
+        // Keep the linear fog division defined
+        fog.end.max(fog.start + 0.01),
+    )
+}
@@ -0,0 +1,93 @@
+use bevy::{pbr::PointLightShadowMap, prelude::*};
+
+use crate::{camera::CameraRig, PointLightSettings};
+
+pub const SHADOW_MAP_SIZES: [usize; 4] = [512, 1024, 2048, 4096];
+
+// WebGL2 has no cube map arrays, so only one point light can have a shadow map there
+#[cfg(not(target_arch = "wasm32"))]
+pub const MAX_SHADOW_CASTERS: usize = 16;
+#[cfg(target_arch = "wasm32")]
+pub const MAX_SHADOW_CASTERS: usize = 1;
+
+#[derive(Resource)]
+pub struct ShadowSettings {
+    pub map_size: usize,
+    // Only the nearest lights to the active camera cast shadows
+    pub budget: usize,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        ShadowSettings {
+            map_size: PointLightShadowMap::default().size,
+            budget: MAX_SHADOW_CASTERS.min(8),
+        }
+    }
+}
+
+pub struct ShadowPlugin;
+
+impl Plugin for ShadowPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ShadowSettings::default())
+            .add_system(update_shadow_map)
+            .add_system(select_shadow_casters);
+    }
+}
+
+fn update_shadow_map(
+    shadow_settings: Res<ShadowSettings>,
+    mut shadow_map: ResMut<PointLightShadowMap>,
+) {
+    if shadow_map.size != shadow_settings.map_size {
+        shadow_map.size = shadow_settings.map_size;
+    }
+}
+
+// Only touches the lights whose settings change, so that the others don't count as changed
+fn select_shadow_casters(
+    plight_settings: Res<PointLightSettings>,
+    shadow_settings: Res<ShadowSettings>,
+    query_cams: Query<(&Camera, &GlobalTransform), With<CameraRig>>,
+    mut query_lights: Query<(Entity, &mut PointLight, &GlobalTransform)>,
+    // Kept between frames so that it isn't allocated every time
+    mut lights_by_distance: Local<Vec<(Entity, f32)>>,
+) {
+    if !plight_settings.initialized {
+        return;
+    }
+
+    let Some(cam_position) = query_cams
+        .iter()
+        .find(|(cam, _)| cam.is_active)
+        .map(|(_, transform)| transform.translation())
+    else {
+        return;
+    };
+
+    lights_by_distance.clear();
+    lights_by_distance.extend(query_lights.iter().map(|(entity, _, transform)| {
+        (
+            entity,
+            transform.translation().distance_squared(cam_position),
+        )
+    }));
+    lights_by_distance.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+    let budget = shadow_settings.budget.min(MAX_SHADOW_CASTERS);
+    let casters = &lights_by_distance[..budget.min(lights_by_distance.len())];
+
+    let base = &plight_settings.light;
+    for (entity, mut light, _) in query_lights.iter_mut() {
+        let shadows_enabled =
+            base.shadows_enabled && casters.iter().any(|(caster, _)| *caster == entity);
+        if light.shadows_enabled != shadows_enabled
+            || light.shadow_depth_bias != base.shadow_depth_bias
+            || light.shadow_normal_bias != base.shadow_normal_bias
+        {
+            light.shadows_enabled = shadows_enabled;
+            light.shadow_depth_bias = base.shadow_depth_bias;
+            light.shadow_normal_bias = base.shadow_normal_bias;
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use bevy::{gltf::GltfExtras, prelude::*, transform::TransformSystem};
+use serde::Deserialize;
+
+use crate::{
+    health::{Checkpoint, DamageZone},
+    lighting_presets::LightingPresetTrigger,
+    player::Player,
+};
+
+// Empties named like this become axis-aligned trigger volumes
+const TRIGGER_NAME_PREFIX: &str = "trigger";
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TriggerShape {
+    // Ignores the rotation of the volume
+    Aabb,
+    Oriented,
+}
+
+// A box around the entity's origin, scaled by its transform like a Blender cube empty
+#[derive(Component, Clone, Copy, Debug)]
+pub struct TriggerVolume {
+    pub shape: TriggerShape,
+    pub half_extents: Vec3,
+}
+
+impl TriggerVolume {
+    pub fn contains(&self, transform: &GlobalTransform, point: Vec3) -> bool {
+        match self.shape {
+            TriggerShape::Aabb => {
+                let (scale, _, translation) = transform.to_scale_rotation_translation();
+                let half_extents = self.half_extents * scale.abs();
+                (point - translation).abs().cmple(half_extents).all()
+            }
+            TriggerShape::Oriented => {
+                let local = transform.affine().inverse().transform_point3(point);
+                local.abs().cmple(self.half_extents).all()
+            }
+        }
+    }
+}
+
+// Entities currently inside the volume
+#[derive(Component, Default)]
+pub struct TriggerOccupants(pub Vec<Entity>);
+
+pub struct TriggerEntered {
+    pub trigger: Entity,
+    pub entity: Entity,
+}
+
+pub struct TriggerExited {
+    pub trigger: Entity,
+    pub entity: Entity,
+}
+
+#[derive(Resource, Default)]
+pub struct TriggerSettings {
+    pub log_events: bool,
+}
+
+// Custom properties exported from Blender, e.g. {"trigger": "oriented", "lighting_preset": "Dark"}
+#[derive(Deserialize, Default)]
+struct TriggerExtras {
+    trigger: Option<String>,
+    lighting_preset: Option<String>,
+    damage_per_second: Option<f32>,
+    checkpoint: Option<bool>,
+}
+
+pub struct TriggerPlugin;
+
+impl Plugin for TriggerPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TriggerSettings::default())
+            .add_event::<TriggerEntered>()
+            .add_event::<TriggerExited>()
+            .add_system(attach_triggers)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                update_triggers.after(TransformSystem::TransformPropagate),
+            )
+            .add_system(log_triggers);
+    }
+}
+
+fn attach_triggers(
+    mut commands: Commands,
+    query_nodes: Query<(Entity, &Name, Option<&GltfExtras>), Added<Name>>,
+    query_meshes: Query<&Handle<Mesh>>,
+) {
+    for (entity, name, extras) in query_nodes.iter() {
+        if query_meshes.contains(entity) {
+            continue;
+        }
+
+        let extras: TriggerExtras = extras
+            .and_then(|extras| serde_json::from_str(&extras.value).ok())
+            .unwrap_or_default();
+        let shape = match &extras.trigger {
+            Some(shape) => match shape.to_ascii_lowercase().as_str() {
+                "aabb" => TriggerShape::Aabb,
+                "oriented" | "obb" => TriggerShape::Oriented,
+                _ => continue,
+            },
+            None if name.to_ascii_lowercase().starts_with(TRIGGER_NAME_PREFIX) => {
+                TriggerShape::Aabb
+            }
+            None => continue,
+        };
+
+        let mut entity_commands = commands.entity(entity);
+        entity_commands.insert(TriggerVolume {
+            shape,
+            half_extents: Vec3::ONE,
+        });
+        if let Some(preset) = extras.lighting_preset {
+            entity_commands.insert(LightingPresetTrigger(preset));
+        }
+        if let Some(damage_per_second) = extras.damage_per_second {
+            entity_commands.insert(DamageZone { damage_per_second });
+        }
+        if extras.checkpoint == Some(true) {
+            entity_commands.insert(Checkpoint);
+        }
+    }
+}
+
+fn update_triggers(
+    mut commands: Commands,
+    mut entered_events: EventWriter<TriggerEntered>,
+    mut exited_events: EventWriter<TriggerExited>,
+    mut query_triggers: Query<(
+        Entity,
+        &TriggerVolume,
+        &GlobalTransform,
+        Option<&mut TriggerOccupants>,
+    )>,
+    query_players: Query<(Entity, &GlobalTransform), With<Player>>,
+) {
+    for (trigger, volume, transform, occupants) in query_triggers.iter_mut() {
+        let inside: Vec<Entity> = query_players
+            .iter()
+            .filter(|(_, player_transform)| {
+                volume.contains(transform, player_transform.translation())
+            })
+            .map(|(entity, _)| entity)
+            .collect();
+
+        let previous = occupants.as_ref().map(|o| o.0.as_slice()).unwrap_or(&[]);
+        for entity in inside.iter().filter(|e| !previous.contains(e)) {
+            entered_events.send(TriggerEntered {
+                trigger,
+                entity: *entity,
+            });
+        }
+        for entity in previous.iter().filter(|e| !inside.contains(e)) {
+            exited_events.send(TriggerExited {
+                trigger,
+                entity: *entity,
+            });
+        }
+
+        match occupants {
+            Some(mut occupants) => {
+                if occupants.0 != inside {
+                    occupants.0 = inside;
+                }
+            }
+            None => {
+                commands.entity(trigger).insert(TriggerOccupants(inside));
+            }
+        }
+    }
+}
+
+// Debug output, only while TriggerSettings::log_events is set
+fn log_triggers(
+    settings: Res<TriggerSettings>,
+    mut entered_events: EventReader<TriggerEntered>,
+    mut exited_events: EventReader<TriggerExited>,
+    query_names: Query<&Name>,
+) {
+    if !settings.log_events {
+        entered_events.clear();
+        exited_events.clear();
+        return;
+    }
+
+    let name = |entity| {
+        query_names
+            .get(entity)
+            .map_or_else(|_| format!("{entity:?}"), |name| name.to_string())
+    };
+
+    for event in entered_events.iter() {
+        debug!("{:?} entered {}", event.entity, name(event.trigger));
+    }
+    for event in exited_events.iter() {
+        debug!("{:?} exited {}", event.entity, name(event.trigger));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{ecs::event::ManualEventReader, transform::TransformPlugin};
+    use std::f32::consts::FRAC_PI_4;
+
+    fn volume(shape: TriggerShape) -> TriggerVolume {
+        TriggerVolume {
+            shape,
+            half_extents: Vec3::ONE,
+        }
+    }
+
+    #[test]
+    fn aabb_contains_points_inside_and_on_the_boundary() {
+        let transform = GlobalTransform::from(
+            Transform::from_xyz(5.0, 0.0, 0.0).with_scale(Vec3::new(2.0, 1.0, 1.0)),
+        );
+        let volume = volume(TriggerShape::Aabb);
+        assert!(volume.contains(&transform, Vec3::new(5.0, 0.0, 0.0)));
+        assert!(volume.contains(&transform, Vec3::new(7.0, 1.0, -1.0)));
+        assert!(volume.contains(&transform, Vec3::new(3.0, 0.0, 0.0)));
+        assert!(!volume.contains(&transform, Vec3::new(7.01, 0.0, 0.0)));
+        assert!(!volume.contains(&transform, Vec3::new(5.0, 1.01, 0.0)));
+    }
+
+    #[test]
+    fn aabb_ignores_rotation() {
+        let transform =
+            GlobalTransform::from(Transform::from_rotation(Quat::from_rotation_y(FRAC_PI_4)));
+        let volume = volume(TriggerShape::Aabb);
+        assert!(volume.contains(&transform, Vec3::new(1.0, 0.0, 1.0)));
+        assert!(!volume.contains(&transform, Vec3::new(1.1, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn oriented_follows_rotation() {
+        let transform = GlobalTransform::from(
+            Transform::from_xyz(0.0, 0.0, 10.0).with_rotation(Quat::from_rotation_y(FRAC_PI_4)),
+        );
+        let volume = volume(TriggerShape::Oriented);
+        // Turned 45 degrees, so the corners of the box lie on the world axes
+        let corner = 2.0_f32.sqrt();
+        assert!(volume.contains(&transform, Vec3::new(corner - 0.01, 0.0, 10.0)));
+        assert!(volume.contains(&transform, Vec3::new(0.0, 1.0, 10.0 - corner + 0.01)));
+        assert!(!volume.contains(&transform, Vec3::new(corner + 0.01, 0.0, 10.0)));
+        assert!(!volume.contains(&transform, Vec3::new(0.9, 0.0, 10.9)));
+        // Without rotation the boundary is exact
+        let transform =
+            GlobalTransform::from(Transform::from_xyz(0.0, 0.0, 10.0).with_scale(Vec3::splat(2.0)));
+        assert!(volume.contains(&transform, Vec3::new(2.0, -2.0, 12.0)));
+        assert!(!volume.contains(&transform, Vec3::new(2.0, -2.0, 12.01)));
+    }
+
+    #[test]
+    fn player_entering_and_leaving_sends_one_event_each() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(TransformPlugin)
+            .add_plugin(TriggerPlugin);
+
+        let trigger = app
+            .world
+            .spawn((volume(TriggerShape::Aabb), TransformBundle::default()))
+            .id();
+        let player = app
+            .world
+            .spawn((
+                Player,
+                TransformBundle::from_transform(Transform::from_xyz(5.0, 0.0, 0.0)),
+            ))
+            .id();
+
+        let mut entered_reader = ManualEventReader::<TriggerEntered>::default();
+        let mut exited_reader = ManualEventReader::<TriggerExited>::default();
+        let mut entered = Vec::new();
+        let mut exited = Vec::new();
+        for x in [5.0, 0.5, 0.0, -0.5, 5.0, 6.0] {
+            app.world
+                .get_mut::<Transform>(player)
+                .unwrap()
+                .translation
+                .x = x;
+            app.update();
+
+            let events = app.world.resource::<Events<TriggerEntered>>();
+            entered.extend(
+                entered_reader
+                    .iter(events)
+                    .map(|event| (event.trigger, event.entity)),
+            );
+            let events = app.world.resource::<Events<TriggerExited>>();
+            exited.extend(
+                exited_reader
+                    .iter(events)
+                    .map(|event| (event.trigger, event.entity)),
+            );
+        }
+
+        assert_eq!(entered, vec![(trigger, player)]);
+        assert_eq!(exited, vec![(trigger, player)]);
+    }
+}
@@ -1,5 +1,7 @@
 use bevy::{
-    core_pipeline::bloom::BloomSettings, prelude::*, window::close_when_requested,
+    core_pipeline::{bloom::BloomSettings, tonemapping::Tonemapping},
+    prelude::*,
+    window::close_when_requested,
     window::CursorGrabMode,
 };
 
@@ -7,11 +9,33 @@ use bevy_egui::{
     egui::{self, Ui},
     EguiContext, EguiPlugin,
 };
+use std::path::Path;
 // use bevy_inspector_egui::{widgets::InspectorQuery, InspectorPlugin, WorldInspectorPlugin};
 
 use crate::{
-    player::{CAMERA_TPS_POS_RELATIVE, HEAD_SIZE},
-    AppState, PointLightSettings,
+    appearance::PlayerAppearance,
+    audio::AudioSettings,
+    camera::{CameraRig, CameraRigs, CameraTransition},
+    camera_effects::CameraEffectSettings,
+    dungeon_generator::{procedural_level, procedural_seed},
+    editor::{
+        DeleteSelected, Editor, EditorObject, EditorObjectKind, GizmoMode, PropKind, SaveOverlay,
+    },
+    flythrough::{CameraPath, Flythrough, FlythroughSettings, Waypoint},
+    health::{Health, ScreenFade},
+    interaction::{Door, Interactable, InteractionFocus},
+    items::{Inventory, ItemKind},
+    light_animation::LightAnimationSettings,
+    lighting_presets::{LightingPresetSettings, LightingPresets},
+    map::{MapFog, MapSettings, MapView, MapViews, FOG_CELL_SIZE},
+    materials::{MaterialOverride, MaterialOverrideSettings, SaveMaterialOverrides},
+    navigation::{NavGrid, NavigationSettings, NpcPath},
+    player::{HeadState, LocalPlayer, PrimaryPlayer, CAMERA_TPS_POS_RELATIVE, HEAD_SIZE},
+    save::{LoadRequest, SaveRequest, SaveSlots, SaveStatus},
+    shading::{FogMode, FogSettings},
+    shadows::{ShadowSettings, MAX_SHADOW_CASTERS, SHADOW_MAP_SIZES},
+    triggers::TriggerSettings,
+    AppState, ChangeLevel, CurrentLevel, PointLightSettings, DUNGEON_LEVEL,
 };
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -19,18 +43,39 @@ const IS_DESKTOP_BUILD: bool = true;
 #[cfg(target_arch = "wasm32")]
 static IS_DESKTOP_BUILD: bool = false;
 
+const MINIMAP_SIZE: f32 = 180.0;
+// Levels made in Blender, the procedural ones are listed by seed
+const HAND_MADE_LEVELS: [&str; 1] = [DUNGEON_LEVEL];
+// How long the outcome of saving or loading stays on screen
+const SAVE_STATUS_DURATION: f64 = 3.0;
+const KEY_EDITOR: KeyCode = KeyCode::F2;
+
 #[derive(PartialEq, Clone, Copy)]
-enum CameraType {
+pub enum CameraType {
     FirstPerson,
     ThirdPerson,
+    // Any other registered camera rig, identified by its label
+    Custom(&'static str),
+}
+
+impl CameraType {
+    pub fn label(&self) -> &'static str {
+        match self {
+            CameraType::FirstPerson => "First Person",
+            CameraType::ThirdPerson => "Third Person",
+            CameraType::Custom(label) => label,
+        }
+    }
 }
 
 #[derive(Resource)]
 pub struct CameraSettings {
-    c_type: CameraType,
+    pub c_type: CameraType,
     distance: f32,
     pub bloom: BloomSettings,
     pub bloom_enabled: bool,
+    pub exposure: f32,
+    pub tonemapping: Tonemapping,
 }
 
 impl Default for CameraSettings {
@@ -44,6 +89,10 @@ impl Default for CameraSettings {
                 ..default()
             },
             bloom_enabled: IS_DESKTOP_BUILD,
+            exposure: 0.0,
+            tonemapping: Tonemapping::Enabled {
+                deband_dither: true,
+            },
         }
     }
 }
@@ -59,11 +108,35 @@ impl Plugin for UIPlugin {
             .add_system(ui_info.before(ui_graphics))
             .add_system_set(
                 SystemSet::on_update(AppState::Menu)
+                    .with_system(ui_audio.before(ui_graphics))
+                    .with_system(ui_player.before(ui_graphics))
+                    .with_system(ui_inventory.before(ui_graphics))
+                    .with_system(ui_navigation.before(ui_graphics))
+                    .with_system(ui_map.before(ui_graphics))
+                    .with_system(ui_saves.before(ui_graphics))
+                    .with_system(ui_flythrough.before(ui_graphics))
+                    .with_system(ui_level.before(ui_graphics))
+                    .with_system(ui_materials.before(ui_graphics))
                     .with_system(ui_graphics.before(ui_camera))
                     .with_system(ui_camera.before(close_when_requested)),
             )
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(ui_interaction_prompt)
+                    .with_system(ui_health)
+                    .with_system(ui_minimap),
+            )
+            .add_system_set(SystemSet::on_update(AppState::Editor).with_system(ui_editor))
+            .add_system(ui_screen_fade.after(ui_info))
+            .add_system(ui_save_status.after(ui_screen_fade))
+            .add_system(ui_navigation_overlay.before(ui_info))
             .add_system(grab_mouse_system.label("grab_mouse").before(ui_info))
-            .add_system(switch_camera.before(ui_camera));
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame).with_system(switch_camera.before(ui_camera)),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Menu).with_system(switch_camera.before(ui_camera)),
+            );
     }
 }
 
@@ -91,6 +164,24 @@ fn grab_mouse_system(
         }
     }
 
+    if key.just_pressed(KEY_EDITOR) {
+        let window = windows.get_primary_mut().unwrap();
+
+        match app_state.current() {
+            AppState::InGame | AppState::Menu => {
+                window.set_cursor_visibility(true);
+                window.set_cursor_grab_mode(CursorGrabMode::None);
+                app_state.set(AppState::Editor).unwrap();
+            }
+            AppState::Editor => {
+                window.set_cursor_visibility(false);
+                app_state.set(AppState::InGame).unwrap();
+                grab_mouse(window);
+            }
+            _ => (),
+        }
+    }
+
     if mouse.just_pressed(MouseButton::Left) && (*app_state.current() == AppState::Start) {
         let window = windows.get_primary_mut().unwrap();
         window.set_cursor_visibility(false);
@@ -120,8 +211,20 @@ fn ui_info(mut egui_context: ResMut<EguiContext>, app_state: Res<State<AppState>
         AppState::InGame => |ui| {
             ui.label("- Use the mouse to look");
             ui.label("- Use WASD or arrow keys to move");
+            ui.label("- Press F to interact");
             ui.label("- Press C to switch camera");
+            ui.label("- Press Start on a gamepad to join");
+            ui.label("- Press F5 to quick save and F9 to quick load");
             ui.label("- Press M for the settings menu");
+            ui.label("- Press F2 for the level editor");
+        },
+        AppState::Editor => |ui| {
+            ui.label("- Hold the right mouse button to look");
+            ui.label("- Use WASD to move and Q/E to go down and up");
+            ui.label("- Click a light or a prop to select it, drag the arrows to edit");
+            ui.label("- Press G to move and R to rotate");
+            ui.label("- Press Delete to remove the selection");
+            ui.label("- Press F2 to go back to the game");
         },
     };
 
@@ -132,34 +235,817 @@ fn ui_info(mut egui_context: ResMut<EguiContext>, app_state: Res<State<AppState>
         .show(egui_context.ctx_mut(), contents);
 }
 
+fn ui_interaction_prompt(
+    mut egui_context: ResMut<EguiContext>,
+    focus: Res<InteractionFocus>,
+    query_interactables: Query<(&Interactable, Option<&Door>)>,
+) {
+    let Some((interactable, door)) = focus
+        .entity
+        .and_then(|entity| query_interactables.get(entity).ok())
+    else {
+        return;
+    };
+
+    let prompt = match door {
+        Some(Door { locked: true, .. }) => "Unlock (needs a key)",
+        _ => interactable.verb(),
+    };
+    egui::Area::new("Interaction Prompt")
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 40.0))
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.label(format!("[F] {prompt}"));
+        });
+}
+
+fn ui_health(
+    mut egui_context: ResMut<EguiContext>,
+    query_health: Query<&Health, With<PrimaryPlayer>>,
+) {
+    let Ok(health) = query_health.get_single() else {
+        return;
+    };
+
+    egui::Area::new("Health")
+        .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(10.0, -10.0))
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.add(
+                egui::ProgressBar::new((health.current / health.max).clamp(0.0, 1.0))
+                    .desired_width(200.0)
+                    .text(format!("Health {:.0}", health.current.max(0.0))),
+            );
+        });
+}
+
+fn ui_minimap(
+    mut egui_context: ResMut<EguiContext>,
+    map_settings: Res<MapSettings>,
+    views: Res<MapViews>,
+    fog: Res<MapFog>,
+    query_primary: Query<Entity, With<PrimaryPlayer>>,
+    query_heads: Query<(&GlobalTransform, &Parent), With<HeadState>>,
+) {
+    if !map_settings.show_minimap {
+        return;
+    }
+    let texture = egui_context.add_image(views.minimap.image.clone_weak());
+    let player = primary_head(&query_primary, &query_heads);
+
+    egui::Area::new("Minimap")
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-10.0, 10.0))
+        .show(egui_context.ctx_mut(), |ui| {
+            draw_map(ui, texture, MINIMAP_SIZE, &views.minimap, &fog, player);
+        });
+}
+
+fn ui_map(
+    mut egui_context: ResMut<EguiContext>,
+    mut map_settings: ResMut<MapSettings>,
+    views: Res<MapViews>,
+    fog: Res<MapFog>,
+    query_primary: Query<Entity, With<PrimaryPlayer>>,
+    query_heads: Query<(&GlobalTransform, &Parent), With<HeadState>>,
+) {
+    let texture = egui_context.add_image(views.map.image.clone_weak());
+    let player = primary_head(&query_primary, &query_heads);
+    let ctx = egui_context.ctx_mut();
+
+    egui::Window::new("Map")
+        .id(egui::Id::new("Map"))
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.checkbox(&mut map_settings.show_minimap, "Show Minimap");
+            ui.horizontal(|ui| {
+                ui.label("Minimap Extent");
+                ui.add(egui::Slider::new(
+                    &mut map_settings.minimap_extent,
+                    10.0..=50.0,
+                ));
+            });
+            if ui.button("Open Full Map").clicked() {
+                map_settings.show_full_map = true;
+            }
+        });
+
+    if !map_settings.show_full_map {
+        return;
+    }
+    // Covers the whole screen, on top of the other windows
+    let screen = ctx.input().screen_rect();
+    egui::Area::new("Full Map")
+        .order(egui::Order::Foreground)
+        .fixed_pos(screen.min)
+        .show(ctx, |ui| {
+            ui.painter()
+                .rect_filled(screen, 0.0, egui::Color32::from_black_alpha(230));
+            ui.allocate_ui_at_rect(screen.shrink(20.0), |ui| {
+                ui.vertical_centered(|ui| {
+                    if ui.button("Close Map").clicked() {
+                        map_settings.show_full_map = false;
+                    }
+                    let size = (screen.width().min(screen.height()) - 80.0).max(100.0);
+                    draw_map(ui, texture, size, &views.map, &fog, player);
+                });
+            });
+        });
+}
+
+// The head turns on its own while the player stands still, so it shows where they are looking
+fn primary_head<'a>(
+    query_primary: &Query<Entity, With<PrimaryPlayer>>,
+    query_heads: &'a Query<(&GlobalTransform, &Parent), With<HeadState>>,
+) -> Option<&'a GlobalTransform> {
+    let primary = query_primary.get_single().ok()?;
+    query_heads
+        .iter()
+        .find(|(_, parent)| parent.get() == primary)
+        .map(|(transform, _)| transform)
+}
+
+// Draws what a map camera sees with the unexplored cells covered up, and an arrow for the player
+fn draw_map(
+    ui: &mut Ui,
+    texture: egui::TextureId,
+    size: f32,
+    view: &MapView,
+    fog: &MapFog,
+    player: Option<&GlobalTransform>,
+) {
+    let rect = ui.image(texture, egui::vec2(size, size)).rect;
+    let painter = ui.painter_at(rect);
+    // North is up, so world X goes right and world Z goes down
+    let scale = size / view.extent;
+    let to_map = |point: Vec2| {
+        rect.center() + egui::vec2(point.x - view.center.x, point.y - view.center.y) * scale
+    };
+
+    let min = MapFog::cell(view.center - view.extent / 2.0);
+    let max = MapFog::cell(view.center + view.extent / 2.0);
+    for z in min.y..=max.y {
+        for x in min.x..=max.x {
+            if fog.is_explored(IVec2::new(x, z)) {
+                continue;
+            }
+            let corner = Vec2::new(x as f32, z as f32) * FOG_CELL_SIZE;
+            let cell = egui::Rect::from_min_max(to_map(corner), to_map(corner + FOG_CELL_SIZE));
+            // Slightly bigger so that no seams show between the cells
+            painter.rect_filled(cell.expand(0.5), 0.0, egui::Color32::BLACK);
+        }
+    }
+
+    if let Some(transform) = player {
+        let position = transform.translation();
+        let forward = transform.forward();
+        let direction = egui::vec2(forward.x, forward.z).normalized();
+        let side = egui::vec2(-direction.y, direction.x);
+        let center = to_map(Vec2::new(position.x, position.z));
+        painter.add(egui::Shape::convex_polygon(
+            vec![
+                center + direction * 8.0,
+                center - direction * 5.0 + side * 5.0,
+                center - direction * 5.0 - side * 5.0,
+            ],
+            egui::Color32::from_rgb(255, 200, 60),
+            egui::Stroke::new(1.0, egui::Color32::BLACK),
+        ));
+    }
+}
+
+fn ui_screen_fade(mut egui_context: ResMut<EguiContext>, screen_fade: Res<ScreenFade>) {
+    if screen_fade.alpha <= 0.0 {
+        return;
+    }
+
+    let ctx = egui_context.ctx_mut();
+    let painter = ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Foreground,
+        egui::Id::new("Screen Fade"),
+    ));
+    painter.rect_filled(
+        ctx.input().screen_rect(),
+        0.0,
+        egui::Color32::from_black_alpha((screen_fade.alpha.min(1.0) * 255.0) as u8),
+    );
+}
+
+fn ui_level(
+    mut egui_context: ResMut<EguiContext>,
+    time: Res<Time>,
+    level: Res<CurrentLevel>,
+    mut level_events: EventWriter<ChangeLevel>,
+    mut trigger_settings: ResMut<TriggerSettings>,
+    mut seed: Local<u64>,
+) {
+    egui::Window::new("Level")
+        .id(egui::Id::new("Level"))
+        .resizable(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            let current_seed = procedural_seed(&level.0);
+            ui.label(match current_seed {
+                Some(seed) => format!("Current: generated from seed {seed}"),
+                None => format!("Current: {}", level.0),
+            });
+            ui.separator();
+
+            for path in HAND_MADE_LEVELS {
+                if ui.button(format!("Load {path}")).clicked() {
+                    level_events.send(ChangeLevel(path.to_string()));
+                }
+            }
+            ui.horizontal(|ui| {
+                ui.label("Seed");
+                ui.add(egui::DragValue::new(&mut *seed));
+                if ui.button("Random").clicked() {
+                    *seed = time.elapsed_seconds_f64().to_bits() % 100_000;
+                }
+                if ui.button("Generate").clicked() {
+                    level_events.send(ChangeLevel(procedural_level(*seed)));
+                }
+            });
+            ui.separator();
+            ui.checkbox(&mut trigger_settings.log_events, "Log trigger events");
+        });
+}
+
+fn ui_editor(
+    mut egui_context: ResMut<EguiContext>,
+    mut editor: ResMut<Editor>,
+    mut save_events: EventWriter<SaveOverlay>,
+    mut delete_events: EventWriter<DeleteSelected>,
+    // Either something placed with the editor or a node of the level
+    mut query_selected: Query<(Option<&mut EditorObject>, Option<&Name>, &mut Transform)>,
+) {
+    egui::Window::new("Editor")
+        .id(egui::Id::new("Editor"))
+        .resizable(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Gizmo");
+                ui.radio_value(&mut editor.mode, GizmoMode::Move, "Move");
+                ui.radio_value(&mut editor.mode, GizmoMode::Rotate, "Rotate");
+            });
+            ui.horizontal(|ui| {
+                ui.label("Place");
+                let kinds = std::iter::once(EditorObjectKind::LIGHT)
+                    .chain(PropKind::ALL.into_iter().map(EditorObjectKind::Prop));
+                for kind in kinds {
+                    let placing = editor.placing == Some(kind);
+                    if ui.selectable_label(placing, kind.label()).clicked() {
+                        editor.placing = if placing { None } else { Some(kind) };
+                    }
+                }
+            });
+            if editor.placing.is_some() {
+                ui.weak("Click on the floor to place it");
+            }
+            ui.separator();
+
+            match editor
+                .selected
+                .and_then(|entity| query_selected.get_mut(entity).ok())
+            {
+                None => {
+                    ui.weak("Nothing selected");
+                }
+                Some((mut object, name, mut transform)) => {
+                    egui::Grid::new("Editor Grid").show(ui, |ui| {
+                        ui.label("Selected");
+                        match (&object, name) {
+                            (Some(object), _) => ui.label(object.kind.label()),
+                            (None, Some(name)) => ui.label(name.as_str()),
+                            (None, None) => ui.label("?"),
+                        };
+                        ui.end_row();
+
+                        ui.label("Position");
+                        ui.horizontal(|ui| {
+                            for value in transform.translation.as_mut() {
+                                ui.add(egui::DragValue::new(value).speed(0.05));
+                            }
+                        });
+                        ui.end_row();
+
+                        ui.label("Yaw");
+                        let (yaw, pitch, roll) = transform.rotation.to_euler(EulerRot::YXZ);
+                        let mut degrees = yaw.to_degrees();
+                        if ui
+                            .add(egui::DragValue::new(&mut degrees).suffix("°"))
+                            .changed()
+                        {
+                            transform.rotation =
+                                Quat::from_euler(EulerRot::YXZ, degrees.to_radians(), pitch, roll);
+                        }
+                        ui.end_row();
+
+                        // Edit a copy so that lights are only updated when something changes
+                        let Some(object) = object.as_mut() else {
+                            return;
+                        };
+                        if let EditorObjectKind::Light { range, flicker } = object.kind {
+                            let (mut range, mut flicker) = (range, flicker);
+                            ui.label("Range");
+                            let mut changed =
+                                ui.add(egui::Slider::new(&mut range, 1.0..=30.0)).changed();
+                            ui.end_row();
+                            ui.label("Flicker");
+                            changed |= ui.checkbox(&mut flicker, "").changed();
+                            ui.end_row();
+                            if changed {
+                                object.kind = EditorObjectKind::Light { range, flicker };
+                            }
+                        }
+                    });
+                    if ui.button("Delete").clicked() {
+                        delete_events.send(DeleteSelected);
+                    }
+                }
+            }
+            ui.separator();
+
+            if ui.button("Save Overlay").clicked() {
+                save_events.send(SaveOverlay);
+            }
+            if let Some(status) = &editor.status {
+                ui.label(status);
+            }
+        });
+}
+
+fn ui_materials(
+    mut egui_context: ResMut<EguiContext>,
+    asset_server: Res<AssetServer>,
+    mut settings: ResMut<MaterialOverrideSettings>,
+    mut save_events: EventWriter<SaveMaterialOverrides>,
+) {
+    // Edit a copy so that the materials are only updated when something actually changes
+    let mut edited = settings.overrides.clone();
+    egui::Window::new("Materials")
+        .id(egui::Id::new("Materials"))
+        .resizable(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            if settings.originals().next().is_none() {
+                ui.weak("This level has no named materials");
+                return;
+            }
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    for (name, original) in settings.originals() {
+                        let material_override = edited.materials.entry(name.clone()).or_default();
+                        let header = if *material_override == MaterialOverride::default() {
+                            egui::RichText::new(name)
+                        } else {
+                            egui::RichText::new(format!("{name} *")).strong()
+                        };
+                        egui::CollapsingHeader::new(header)
+                            .id_source(name)
+                            .show(ui, |ui| {
+                                egui::Grid::new(("Material Grid", name)).show(ui, |ui| {
+                                    ui_material_override(
+                                        ui,
+                                        "Base Color",
+                                        &mut material_override.base_color,
+                                        original.base_color.as_linear_rgba_f32(),
+                                        |ui, color| {
+                                            ui.color_edit_button_rgba_unmultiplied(color);
+                                        },
+                                    );
+                                    ui_material_override(
+                                        ui,
+                                        "Roughness",
+                                        &mut material_override.perceptual_roughness,
+                                        original.perceptual_roughness,
+                                        |ui, roughness| {
+                                            ui.add(egui::Slider::new(roughness, 0.089..=1.0));
+                                        },
+                                    );
+                                    ui_material_override(
+                                        ui,
+                                        "Emissive Strength",
+                                        &mut material_override.emissive_strength,
+                                        1.0,
+                                        |ui, strength| {
+                                            ui.add(
+                                                egui::Slider::new(strength, 0.0..=100.0)
+                                                    .logarithmic(true),
+                                            );
+                                        },
+                                    );
+                                    ui_material_override(
+                                        ui,
+                                        "Texture",
+                                        &mut material_override.base_color_texture,
+                                        String::new(),
+                                        |ui, path| {
+                                            ui.text_edit_singleline(path);
+                                            if !asset_server.asset_io().is_file(Path::new(path)) {
+                                                ui.colored_label(
+                                                    egui::Color32::LIGHT_RED,
+                                                    "Not found",
+                                                );
+                                            }
+                                        },
+                                    );
+                                });
+                                if ui.button("Reset").clicked() {
+                                    *material_override = MaterialOverride::default();
+                                }
+                            });
+                    }
+                });
+            ui.separator();
+
+            if ui.button("Save Overrides").clicked() {
+                save_events.send(SaveMaterialOverrides);
+            }
+            if let Some(status) = &settings.status {
+                ui.label(status);
+            }
+        });
+
+    // Materials without any override are left out of the file
+    edited
+        .materials
+        .retain(|_, material_override| *material_override != MaterialOverride::default());
+    if edited != settings.overrides {
+        settings.overrides = edited;
+    }
+}
+
+// A row with a checkbox to turn the override on, starting from the exported value
+fn ui_material_override<T>(
+    ui: &mut Ui,
+    label: &str,
+    value: &mut Option<T>,
+    original: T,
+    add_contents: impl FnOnce(&mut Ui, &mut T),
+) {
+    let mut enabled = value.is_some();
+    if ui.checkbox(&mut enabled, label).changed() {
+        *value = enabled.then_some(original);
+    }
+    ui.horizontal(|ui| {
+        if let Some(value) = value {
+            add_contents(ui, value);
+        }
+    });
+    ui.end_row();
+}
+
+fn ui_flythrough(
+    mut egui_context: ResMut<EguiContext>,
+    mut path: ResMut<CameraPath>,
+    mut settings: ResMut<FlythroughSettings>,
+    mut flythrough: ResMut<Flythrough>,
+    query_cams: Query<(&Camera, &GlobalTransform), With<CameraRig>>,
+) {
+    egui::Window::new("Flythrough")
+        .id(egui::Id::new("Flythrough"))
+        .resizable(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.label(format!(
+                "{} waypoints, {:.1} m",
+                path.waypoints.len(),
+                path.length()
+            ));
+            ui.horizontal(|ui| {
+                // Waypoints are placed where the camera currently is
+                let camera = query_cams.iter().find(|(cam, _)| cam.is_active);
+                if ui
+                    .add_enabled(camera.is_some(), egui::Button::new("Add Waypoint"))
+                    .clicked()
+                {
+                    if let Some((_, transform)) = camera {
+                        path.waypoints.push(Waypoint::from(transform));
+                    }
+                }
+                if ui.button("Remove Last").clicked() {
+                    path.waypoints.pop();
+                }
+                if ui.button("Clear").clicked() {
+                    path.waypoints.clear();
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Speed");
+                ui.add(egui::Slider::new(&mut settings.speed, 0.5..=10.0).suffix(" m/s"));
+            });
+            ui.checkbox(&mut settings.looping, "Loop");
+            if flythrough.is_playing() {
+                if ui.button("Stop").clicked() {
+                    flythrough.stop();
+                }
+            } else if ui
+                .add_enabled(path.waypoints.len() > 1, egui::Button::new("Play"))
+                .clicked()
+            {
+                flythrough.play();
+            }
+        });
+}
+
+fn ui_saves(
+    mut egui_context: ResMut<EguiContext>,
+    slots: Res<SaveSlots>,
+    mut save_requests: EventWriter<SaveRequest>,
+    mut load_requests: EventWriter<LoadRequest>,
+) {
+    egui::Window::new("Save Game")
+        .id(egui::Id::new("Save Game"))
+        .resizable(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            egui::Grid::new("Save Grid").show(ui, |ui| {
+                for (slot, summary) in slots.slots.iter() {
+                    ui.label(slot.label());
+                    match summary {
+                        None => ui.weak("Empty"),
+                        Some(Ok(summary)) => ui.label(summary),
+                        Some(Err(error)) => ui.colored_label(egui::Color32::LIGHT_RED, error),
+                    };
+                    if ui.button("Save").clicked() {
+                        save_requests.send(SaveRequest(*slot));
+                    }
+                    let can_load = matches!(summary, Some(Ok(_)));
+                    if ui
+                        .add_enabled(can_load, egui::Button::new("Load"))
+                        .clicked()
+                    {
+                        load_requests.send(LoadRequest(*slot));
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+}
+
+fn ui_save_status(mut egui_context: ResMut<EguiContext>, time: Res<Time>, status: Res<SaveStatus>) {
+    let Some((message, shown_at)) = &status.message else {
+        return;
+    };
+    if time.elapsed_seconds_f64() - shown_at > SAVE_STATUS_DURATION {
+        return;
+    }
+
+    egui::Area::new("Save Status")
+        .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -40.0))
+        .order(egui::Order::Foreground)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.label(egui::RichText::new(message).strong());
+        });
+}
+
+fn ui_player(
+    mut egui_context: ResMut<EguiContext>,
+    mut appearance: ResMut<PlayerAppearance>,
+    mut model_path: Local<Option<String>>,
+) {
+    // Edit a copy so that the avatar is only rebuilt when something actually changes
+    let mut edited = appearance.clone();
+    // The model is only loaded once its path has been typed in
+    let model_path = model_path.get_or_insert_with(|| appearance.model.clone());
+    let contents = |ui: &mut Ui| {
+        egui::Grid::new("Player Grid").show(ui, |ui| {
+            ui.label("Body Color");
+            ui.color_edit_button_rgba_unmultiplied(&mut edited.body_color);
+            ui.end_row();
+            ui.label("Head Color");
+            ui.color_edit_button_rgba_unmultiplied(&mut edited.head_color);
+            ui.end_row();
+            ui.label("Emissive");
+            ui.color_edit_button_rgba_unmultiplied(&mut edited.emissive_color);
+            ui.end_row();
+            ui.label("Roughness");
+            ui.add(egui::Slider::new(
+                &mut edited.perceptual_roughness,
+                0.089..=1.0,
+            ));
+            ui.end_row();
+            ui.label("Metallic");
+            ui.add(egui::Slider::new(&mut edited.metallic, 0.0..=1.0));
+            ui.end_row();
+            ui.label("Torso Width");
+            ui.add(egui::Slider::new(&mut edited.torso_width, 0.2..=1.5));
+            ui.end_row();
+            ui.label("Torso Height");
+            ui.add(egui::Slider::new(&mut edited.torso_height, 0.3..=1.6));
+            ui.end_row();
+            ui.label("Head Size");
+            ui.add(egui::Slider::new(&mut edited.head_size, 0.2..=0.6));
+            ui.end_row();
+            ui.label("Model");
+            let response = ui.add(
+                egui::TextEdit::singleline(model_path)
+                    .hint_text("glTF file in assets, empty for boxes"),
+            );
+            if response.lost_focus() {
+                edited.model = model_path.trim().to_string();
+            }
+            ui.end_row();
+        });
+        if ui.button("Reset").clicked() {
+            edited = PlayerAppearance::default();
+            *model_path = edited.model.clone();
+        }
+    };
+
+    egui::Window::new("Player")
+        .id(egui::Id::new("Player"))
+        .resizable(false)
+        .show(egui_context.ctx_mut(), contents);
+
+    if edited != *appearance {
+        *appearance = edited;
+    }
+}
+
+fn ui_audio(mut egui_context: ResMut<EguiContext>, mut audio_settings: ResMut<AudioSettings>) {
+    let contents = |ui: &mut Ui| {
+        ui.add(egui::Slider::new(&mut audio_settings.master_volume, 0.0..=1.0).text("Master"));
+        ui.add(egui::Slider::new(&mut audio_settings.ambient_volume, 0.0..=1.0).text("Ambient"));
+        ui.add(egui::Slider::new(&mut audio_settings.footstep_volume, 0.0..=1.0).text("Footsteps"));
+        ui.add(
+            egui::Slider::new(&mut audio_settings.hearing_distance, 2.0..=30.0)
+                .text("Hearing distance"),
+        );
+    };
+
+    egui::Window::new("Audio")
+        .id(egui::Id::new("Audio"))
+        .resizable(false)
+        .show(egui_context.ctx_mut(), contents);
+}
+
+fn ui_navigation(
+    mut egui_context: ResMut<EguiContext>,
+    mut nav_settings: ResMut<NavigationSettings>,
+    nav_grid: Res<NavGrid>,
+) {
+    let contents = |ui: &mut Ui| {
+        ui.checkbox(&mut nav_settings.show_overlay, "Show navmesh and paths");
+        ui.label(format!(
+            "{} walkable cells",
+            nav_grid.walkable_cells().count()
+        ));
+    };
+
+    egui::Window::new("Navigation")
+        .id(egui::Id::new("Navigation"))
+        .resizable(false)
+        .show(egui_context.ctx_mut(), contents);
+}
+
+// Draws the walkable cells and the NPC paths on top of the game screen
+fn ui_navigation_overlay(
+    mut egui_context: ResMut<EguiContext>,
+    nav_settings: Res<NavigationSettings>,
+    nav_grid: Res<NavGrid>,
+    query_cams: Query<(&Camera, &GlobalTransform), With<CameraRig>>,
+    query_paths: Query<(&NpcPath, &GlobalTransform)>,
+) {
+    if !nav_settings.show_overlay {
+        return;
+    }
+    let Some((camera, cam_transform)) = query_cams.iter().find(|(cam, _)| cam.is_active) else {
+        return;
+    };
+    let Some(viewport_size) = camera.logical_viewport_size() else {
+        return;
+    };
+    // The viewport's origin is at the bottom left, egui's at the top left
+    let to_screen = |position: Vec3| {
+        camera
+            .world_to_viewport(cam_transform, position)
+            .map(|point| egui::pos2(point.x, viewport_size.y - point.y))
+    };
+
+    let painter = egui_context.ctx_mut().layer_painter(egui::LayerId::new(
+        egui::Order::Background,
+        egui::Id::new("Navigation Overlay"),
+    ));
+
+    let half = nav_grid.cell_size() * 0.4;
+    let cell_color = egui::Color32::from_rgba_unmultiplied(80, 200, 120, 40);
+    for center in nav_grid.walkable_cells() {
+        let corners: Option<Vec<egui::Pos2>> = [
+            Vec3::new(-half, 0.02, -half),
+            Vec3::new(half, 0.02, -half),
+            Vec3::new(half, 0.02, half),
+            Vec3::new(-half, 0.02, half),
+        ]
+        .into_iter()
+        .map(|corner| to_screen(center + corner))
+        .collect();
+        if let Some(corners) = corners {
+            painter.add(egui::Shape::convex_polygon(
+                corners,
+                cell_color,
+                egui::Stroke::none(),
+            ));
+        }
+    }
+
+    let path_stroke = egui::Stroke::new(2.0, egui::Color32::from_rgb(255, 140, 0));
+    for (path, transform) in query_paths.iter() {
+        let start = transform.translation() * Vec3::new(1.0, 0.0, 1.0);
+        let points = std::iter::once(start).chain(path.points.iter().copied());
+        let points: Vec<Vec3> = points.map(|point| point + Vec3::Y * 0.05).collect();
+        for segment in points.windows(2) {
+            if let (Some(a), Some(b)) = (to_screen(segment[0]), to_screen(segment[1])) {
+                painter.line_segment([a, b], path_stroke);
+            }
+        }
+    }
+}
+
+fn ui_inventory(
+    mut egui_context: ResMut<EguiContext>,
+    query_inventory: Query<(&LocalPlayer, &Inventory)>,
+) {
+    let contents = |ui: &mut Ui| {
+        for (local_player, inventory) in query_inventory.iter() {
+            // Each split-screen player gets their own grid, so the ids mustn't clash
+            egui::Grid::new(("Inventory Grid", local_player.index)).show(ui, |ui| {
+                for kind in ItemKind::ALL {
+                    ui.label(kind.label());
+                    ui.label(inventory.count(kind).to_string());
+                    ui.end_row();
+                }
+            });
+        }
+    };
+
+    egui::Window::new("Inventory")
+        .id(egui::Id::new("Inventory"))
+        .resizable(false)
+        .show(egui_context.ctx_mut(), contents);
+}
+
+// The camera itself is switched over by the camera transition. A flythrough keeps its camera
+// until it's stopped
 fn switch_camera(
     key: Res<Input<KeyCode>>,
+    rigs: Res<CameraRigs>,
     mut cam_settings: ResMut<CameraSettings>,
-    mut query_cams: Query<&mut Camera>,
+    query_rigs: Query<&CameraRig>,
 ) {
-    if key.just_pressed(KeyCode::C) {
-        cam_settings.c_type = match cam_settings.c_type {
-            CameraType::FirstPerson => CameraType::ThirdPerson,
-            CameraType::ThirdPerson => CameraType::FirstPerson,
-        };
-
-        for mut cam in query_cams.iter_mut() {
-            cam.is_active = !cam.is_active;
-        }
+    let flythrough = query_rigs
+        .iter()
+        .any(|rig| rig.c_type == cam_settings.c_type && rig.player.is_none());
+    if key.just_pressed(KeyCode::C) && !flythrough {
+        cam_settings.c_type = rigs.next(cam_settings.c_type);
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn ui_graphics(
     mut egui_context: ResMut<EguiContext>,
-    mut query_light_point: Query<&mut PointLight>,
     mut clear_color: ResMut<ClearColor>,
     mut ambient_light: ResMut<AmbientLight>,
     mut plight_settings: ResMut<PointLightSettings>,
+    mut anim_settings: ResMut<LightAnimationSettings>,
+    mut preset_settings: ResMut<LightingPresetSettings>,
+    presets: Res<Assets<LightingPresets>>,
+    mut fog: ResMut<FogSettings>,
+    mut shadow_settings: ResMut<ShadowSettings>,
 ) {
     let contents = |ui: &mut Ui| {
+        if let Some(presets) = presets.get(&preset_settings.handle) {
+            ui.horizontal(|ui| {
+                ui.label("Preset");
+                let mut chosen = None;
+                egui::ComboBox::from_id_source("Lighting Preset")
+                    .selected_text(preset_settings.selected.as_deref().unwrap_or("Custom"))
+                    .show_ui(ui, |ui| {
+                        for preset in presets.presets.iter() {
+                            let selected = preset_settings.selected.as_ref() == Some(&preset.name);
+                            if ui.selectable_label(selected, &preset.name).clicked() {
+                                chosen = Some(preset);
+                            }
+                        }
+                    });
+                ui.label("Duration");
+                ui.add(
+                    egui::DragValue::new(&mut preset_settings.duration)
+                        .clamp_range(0.0..=10.0)
+                        .speed(0.05),
+                );
+
+                if let Some(preset) = chosen {
+                    preset_settings.start_transition(
+                        preset,
+                        &clear_color,
+                        &ambient_light,
+                        &plight_settings,
+                    );
+                }
+            });
+            ui.separator();
+        }
+
         let mut color_lrgba_clear = clear_color.as_linear_rgba_f32();
         let mut color_lrgba_ambient = ambient_light.color.as_linear_rgba_f32();
         let mut color_lrgba_point = plight_settings.light.color.as_linear_rgba_f32();
+        let mut color_lrgba_fog = fog.color.as_linear_rgba_f32();
 
         ui.horizontal(|ui| {
             ui.label("Clear Color");
@@ -168,6 +1054,9 @@ fn ui_graphics(
             if ui.button("Ambient").clicked() {
                 color_lrgba_ambient = color_lrgba_clear;
             }
+            if ui.button("Fog").clicked() {
+                color_lrgba_fog = color_lrgba_clear;
+            }
         });
         clear_color.0 = Color::rgba_linear(
             color_lrgba_clear[0],
@@ -192,6 +1081,55 @@ fn ui_graphics(
             color_lrgba_ambient[3],
         );
 
+        ui.separator();
+
+        ui.checkbox(&mut fog.enabled, "Fog");
+        ui.add_enabled_ui(fog.enabled, |ui| {
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut fog.mode, FogMode::Linear, "Linear");
+                ui.radio_value(&mut fog.mode, FogMode::Exponential, "Exponential");
+                ui.radio_value(
+                    &mut fog.mode,
+                    FogMode::ExponentialSquared,
+                    "Exponential Squared",
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.label("Color");
+                ui.color_edit_button_rgba_unmultiplied(&mut color_lrgba_fog);
+                match fog.mode {
+                    FogMode::Linear => {
+                        ui.label("Start");
+                        ui.add(
+                            egui::DragValue::new(&mut fog.start)
+                                .clamp_range(0.0..=200.0)
+                                .speed(0.1),
+                        );
+                        ui.label("End");
+                        ui.add(
+                            egui::DragValue::new(&mut fog.end)
+                                .clamp_range(0.0..=200.0)
+                                .speed(0.1),
+                        );
+                    }
+                    FogMode::Exponential | FogMode::ExponentialSquared => {
+                        ui.label("Density");
+                        ui.add(
+                            egui::DragValue::new(&mut fog.density)
+                                .clamp_range(0.0..=1.0)
+                                .speed(0.001),
+                        );
+                    }
+                }
+            });
+        });
+        fog.color = Color::rgba_linear(
+            color_lrgba_fog[0],
+            color_lrgba_fog[1],
+            color_lrgba_fog[2],
+            color_lrgba_fog[3],
+        );
+
         ui.separator();
         ui.label("Point Lights");
         let mut changed = false;
@@ -211,9 +1149,40 @@ fn ui_graphics(
         changed |= ui
             .checkbox(&mut plight_settings.light.shadows_enabled, "Shadows")
             .changed();
+        ui.add_enabled_ui(plight_settings.light.shadows_enabled, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Shadow Map");
+                for size in SHADOW_MAP_SIZES {
+                    ui.radio_value(&mut shadow_settings.map_size, size, size.to_string());
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Depth Bias");
+                ui.add(
+                    egui::DragValue::new(&mut plight_settings.light.shadow_depth_bias)
+                        .clamp_range(0.0..=1.0)
+                        .speed(0.001),
+                );
+                ui.label("Normal Bias");
+                ui.add(
+                    egui::DragValue::new(&mut plight_settings.light.shadow_normal_bias)
+                        .clamp_range(0.0..=5.0)
+                        .speed(0.01),
+                );
+            });
+            ui.add_enabled_ui(IS_DESKTOP_BUILD, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Shadow Casters");
+                    ui.add(egui::Slider::new(
+                        &mut shadow_settings.budget,
+                        0..=MAX_SHADOW_CASTERS,
+                    ));
+                });
+            });
+        });
         if !IS_DESKTOP_BUILD {
             ui.label(
-                "! Currently, shadows can only be enabled for one point light in WebAssembly !",
+                "! Currently, shadows can only be enabled for one point light in WebAssembly, the nearest one is chosen !",
             );
         }
         if changed {
@@ -223,48 +1192,62 @@ fn ui_graphics(
                 color_lrgba_point[2],
                 color_lrgba_point[3],
             );
-            for mut point_light in query_light_point.iter_mut() {
-                point_light.color = plight_settings.light.color;
-                point_light.intensity = plight_settings.light.intensity;
-                point_light.shadows_enabled = plight_settings.light.shadows_enabled;
-            }
         }
+
+        ui.separator();
+        ui.checkbox(&mut anim_settings.enabled, "Light Animation");
+        ui.add_enabled_ui(anim_settings.enabled, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Flicker Speed");
+                ui.add(egui::DragValue::new(&mut anim_settings.flicker_speed).speed(0.1));
+                ui.label("Amount");
+                ui.add(egui::Slider::new(
+                    &mut anim_settings.flicker_amount,
+                    0.0..=1.0,
+                ));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Pulse Speed");
+                ui.add(egui::DragValue::new(&mut anim_settings.pulse_speed).speed(0.01));
+                ui.label("Amount");
+                ui.add(egui::Slider::new(
+                    &mut anim_settings.pulse_amount,
+                    0.0..=1.0,
+                ));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Color Cycle Speed");
+                ui.add(egui::DragValue::new(&mut anim_settings.cycle_speed).speed(0.01));
+            });
+        });
     };
 
     egui::Window::new("Graphics").show(egui_context.ctx_mut(), contents);
 }
 
+#[allow(clippy::too_many_arguments)]
 fn ui_camera(
     mut egui_context: ResMut<EguiContext>,
     mut cam_settings: ResMut<CameraSettings>,
-    mut query_cams: Query<(&mut Camera, &mut Transform), With<Camera>>,
+    mut effect_settings: ResMut<CameraEffectSettings>,
+    rigs: Res<CameraRigs>,
+    transition: Res<CameraTransition>,
+    mut query_cams: Query<(&mut Camera, &mut Transform, &CameraRig)>,
     mut query_bloom: Query<&mut BloomSettings>,
+    mut query_tonemapping: Query<&mut Tonemapping>,
 ) {
     let contents = |ui: &mut Ui| {
         ui.horizontal(|ui| {
-            let cam_settings_prev = cam_settings.c_type;
-            ui.radio_value(
-                &mut cam_settings.c_type,
-                CameraType::FirstPerson,
-                "First Person",
-            );
-            ui.radio_value(
-                &mut cam_settings.c_type,
-                CameraType::ThirdPerson,
-                "Third Person",
-            );
-
-            if cam_settings_prev != cam_settings.c_type {
-                for (mut cam, _) in query_cams.iter_mut() {
-                    cam.is_active = !cam.is_active;
-                }
+            for c_type in rigs.iter() {
+                ui.radio_value(&mut cam_settings.c_type, c_type, c_type.label());
             }
         });
 
         ui.separator();
 
-        for (cam, mut transform) in query_cams.iter_mut() {
-            if !cam.is_active {
+        for (_, mut transform, rig) in query_cams.iter_mut() {
+            // The camera being moved is not where the settings say it is
+            if rig.c_type != cam_settings.c_type || transition.in_progress() {
                 continue;
             }
 
@@ -296,6 +1279,9 @@ fn ui_camera(
                             .step_by(0.05),
                     );
                 }
+                CameraType::Custom(_) => {
+                    ui.label("No settings for this camera");
+                }
             });
 
             if cam_settings.c_type == CameraType::ThirdPerson {
@@ -335,6 +1321,14 @@ fn ui_camera(
         }
         ui.separator();
 
+        // Both can be turned off for motion-sensitive players
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut effect_settings.head_bob, "Head Bob");
+            ui.checkbox(&mut effect_settings.camera_shake, "Camera Shake");
+        });
+
+        ui.separator();
+
         ui.add_enabled_ui(IS_DESKTOP_BUILD, |ui| {
             let mut changed = false;
             changed |= ui
@@ -369,14 +1363,51 @@ fn ui_camera(
                 for mut bloom in query_bloom.iter_mut() {
                     *bloom = cam_settings.bloom.clone();
                 }
-                for (mut cam, _) in query_cams.iter_mut() {
+                for (mut cam, ..) in query_cams.iter_mut() {
                     cam.hdr = cam_settings.bloom_enabled;
                 }
+                // Tonemapping runs in a separate pass with HDR, so keep it in sync when toggling
+                for mut tonemapping in query_tonemapping.iter_mut() {
+                    *tonemapping = cam_settings.tonemapping.clone();
+                }
             }
         });
         if !IS_DESKTOP_BUILD {
             ui.label("! Currently, bloom effect does not work in WebAssembly !");
         }
+
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.label("Exposure (EV)");
+            ui.add(egui::Slider::new(&mut cam_settings.exposure, -4.0..=4.0).step_by(0.1));
+        });
+        let mut tonemapping_enabled = cam_settings.tonemapping.is_enabled();
+        let mut deband_dither = matches!(
+            cam_settings.tonemapping,
+            Tonemapping::Enabled {
+                deband_dither: true
+            }
+        );
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            changed |= ui
+                .checkbox(&mut tonemapping_enabled, "Tonemapping")
+                .changed();
+            ui.add_enabled_ui(tonemapping_enabled, |ui| {
+                changed |= ui.checkbox(&mut deband_dither, "Deband Dither").changed();
+            });
+        });
+        if changed {
+            cam_settings.tonemapping = if tonemapping_enabled {
+                Tonemapping::Enabled { deband_dither }
+            } else {
+                Tonemapping::Disabled
+            };
+            for mut tonemapping in query_tonemapping.iter_mut() {
+                *tonemapping = cam_settings.tonemapping.clone();
+            }
+        }
     };
 
     egui::Window::new("Camera")